@@ -0,0 +1,540 @@
+use core::fmt;
+use std::convert::TryFrom;
+use std::marker::PhantomData;
+use std::mem::{replace, swap};
+
+use crate::balance::{Storage, StorageMut};
+use crate::storage::{transfer, Backend, Node};
+use crate::summary::{Eager, Propagate};
+use crate::{AvlTree, Boxes, DuplicatePolicy, Natural, Side, Summary};
+
+/// The index of no node, an empty subtree
+const NIL: u32 = u32::MAX;
+
+/// A slot of the arena holds a node or the index of the next free slot
+enum Slot<T, S, P> {
+    Occupied(Node<T, S, Arena<T, S, P>>),
+    Free(u32),
+}
+
+/// The nodes of a tree in one contiguous Vec addressed by u32 indices, the removed slots are chained in a free list and reused first
+/// It holds at most u32::MAX - 1 nodes (one per key, the duplicates of a key share their node), the pending updates are handed down as P says (see Boxes)
+pub struct Arena<T, S = (), P = Eager> {
+    slots: Vec<Slot<T, S, P>>,
+    /// Head of the free list (NIL when every slot is occupied)
+    free: u32,
+    /// Number of occupied slots
+    nodes: usize,
+    marker: PhantomData<P>,
+}
+
+/// An AvlTree keeping its nodes in an Arena instead of one Box per node
+/// The nodes are close in memory and the slots of the removed ones are reused, so the insertions rarely allocate
+/// Every method and iterator of AvlTree is available, only the capacity is handled here
+pub type ArenaAvlTree<T, C = Natural, S = ()> = AvlTree<T, C, S, Arena<T, S>>;
+
+impl<T: Clone, S: Clone, P> Clone for Node<T, S, Arena<T, S, P>> {
+    fn clone(&self) -> Self {
+        Node {
+            children: self.children,
+            value: self.value.clone(),
+            duplicates: self.duplicates.clone(),
+            copies: self.copies,
+            height: self.height,
+            size: self.size,
+            summary: self.summary.clone(),
+        }
+    }
+}
+
+impl<T: Clone, S: Clone, P> Clone for Slot<T, S, P> {
+    fn clone(&self) -> Self {
+        match self {
+            Slot::Occupied(node) => Slot::Occupied(node.clone()),
+            Slot::Free(next) => Slot::Free(*next),
+        }
+    }
+}
+
+impl<T: Clone, S: Clone, P> Clone for Arena<T, S, P> {
+    fn clone(&self) -> Self {
+        Arena {
+            slots: self.slots.clone(),
+            free: self.free,
+            nodes: self.nodes,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T, S, P> Default for Arena<T, S, P> {
+    fn default() -> Self {
+        Arena::with_capacity(0)
+    }
+}
+
+impl<T, S, P> fmt::Debug for Arena<T, S, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Arena").field("slots", &self.slots.len()).field("nodes", &self.nodes).finish()
+    }
+}
+
+impl<T, S, P> Arena<T, S, P> {
+    /// Create an empty arena with room for the given number of nodes before it grows
+    pub fn with_capacity(capacity: usize) -> Self {
+        Arena {
+            slots: Vec::with_capacity(capacity),
+            free: NIL,
+            nodes: 0,
+            marker: PhantomData,
+        }
+    }
+
+    fn node(&self, index: u32) -> &Node<T, S, Self> {
+        match &self.slots[index as usize] {
+            Slot::Occupied(node) => node,
+            Slot::Free(_) => unreachable!("a link points to a free slot"),
+        }
+    }
+
+    fn node_mut(&mut self, index: u32) -> &mut Node<T, S, Self> {
+        match &mut self.slots[index as usize] {
+            Slot::Occupied(node) => node,
+            Slot::Free(_) => unreachable!("a link points to a free slot"),
+        }
+    }
+
+    /// Take the node out of its slot for the time of the change, so the arena can be used to reach its children meanwhile
+    fn detached<R, F: FnOnce(&mut Self, &mut Node<T, S, Self>) -> R>(&mut self, index: u32, change: F) -> R {
+        let mut slot: Slot<T, S, P> = replace(&mut self.slots[index as usize], Slot::Free(NIL));
+        let result: R = match &mut slot {
+            Slot::Occupied(node) => change(self, node),
+            Slot::Free(_) => unreachable!("a link points to a free slot"),
+        };
+        self.slots[index as usize] = slot;
+        result
+    }
+
+    /// Take the node out of its slot and put the slot at the head of the free list
+    fn release(&mut self, index: u32) -> Node<T, S, Self> {
+        self.nodes -= 1;
+        let next: u32 = replace(&mut self.free, index);
+        match replace(&mut self.slots[index as usize], Slot::Free(next)) {
+            Slot::Occupied(node) => node,
+            Slot::Free(_) => unreachable!("a free slot is released again"),
+        }
+    }
+
+    /// Lay the nodes of the tree out again in pre order at the start of a Vec of the exact size, the free slots are dropped
+    /// A lookup then walks toward the end of the Vec, and the new index of the root (0 or NIL) is returned
+    fn compact(&mut self, root: u32) -> u32 {
+        let mut order: Vec<u32> = Vec::with_capacity(self.nodes);
+        let mut stack: Vec<u32> = vec![root];
+        while let Some(index) = stack.pop() {
+            if index != NIL {
+                order.push(index);
+                let [left, right] = self.node(index).children;
+                stack.push(right);
+                stack.push(left);
+            }
+        }
+        let mut moved: Vec<u32> = vec![NIL; self.slots.len()];
+        for (position, index) in order.iter().enumerate() {
+            moved[*index as usize] = position as u32;
+        }
+        let mut slots: Vec<Slot<T, S, P>> = Vec::with_capacity(order.len());
+        for index in order {
+            let mut node: Node<T, S, Self> = match replace(&mut self.slots[index as usize], Slot::Free(NIL)) {
+                Slot::Occupied(node) => node,
+                Slot::Free(_) => unreachable!("a link points to a free slot"),
+            };
+            for child in node.children.iter_mut().filter(|child: &&mut u32| **child != NIL) {
+                *child = moved[*child as usize];
+            }
+            slots.push(Slot::Occupied(node));
+        }
+        self.slots = slots;
+        self.free = NIL;
+        if root == NIL { NIL } else { 0 }
+    }
+}
+
+/// The nodes of an arena are reached by their index, a rotation moves the values between the slots
+impl<T, S, P> Storage for Arena<T, S, P> {
+    type Node = u32;
+    type Link = u32;
+
+    fn height(&self, link: &u32) -> usize {
+        if *link == NIL { 0 } else { self.node(*link).height }
+    }
+
+    fn child<'a>(&'a self, node: &'a u32, side: Side) -> &'a u32 {
+        &self.node(*node).children[side as usize]
+    }
+}
+
+impl<T, S: Summary<T>, P: Propagate<T, S>> StorageMut for Arena<T, S, P> {
+    fn node(link: &mut u32) -> &mut u32 {
+        link
+    }
+
+    fn take_child(&mut self, node: &mut u32, side: Side) -> u32 {
+        self.push_down(node);
+        replace(&mut self.node_mut(*node).children[side as usize], NIL)
+    }
+
+    fn set_child(&mut self, node: &mut u32, side: Side, child: u32) {
+        self.node_mut(*node).children[side as usize] = child;
+    }
+
+    fn swap_values(&mut self, node: &mut u32, other: &mut u32) {
+        let node: u32 = *node;
+        self.detached(*other, |arena: &mut Self, other: &mut Node<T, S, Self>| {
+            let node: &mut Node<T, S, Self> = arena.node_mut(node);
+            swap(&mut node.value, &mut other.value);
+            swap(&mut node.duplicates, &mut other.duplicates);
+            swap(&mut node.copies, &mut other.copies);
+        })
+    }
+
+    fn update(&mut self, node: &mut u32) {
+        self.detached(*node, |arena: &mut Self, node: &mut Node<T, S, Self>| node.update(arena))
+    }
+}
+
+impl<T, S: Summary<T>, P: Propagate<T, S>> Backend<T, S> for Arena<T, S, P> {
+    fn root<'a>(&'a self, link: &'a u32) -> Option<&'a Node<T, S, Self>> {
+        if *link == NIL { None } else { Some(self.node(*link)) }
+    }
+
+    fn get<'a>(&'a self, node: &'a u32) -> &'a Node<T, S, Self> {
+        self.node(*node)
+    }
+
+    fn get_mut<'a>(&'a mut self, node: &'a mut u32) -> &'a mut Node<T, S, Self> {
+        self.node_mut(*node)
+    }
+
+    fn empty() -> u32 {
+        NIL
+    }
+
+    /// Store the node in the first free slot (or a new one at the end) and give its index
+    fn alloc(&mut self, node: Node<T, S, Self>) -> u32 {
+        self.nodes += 1;
+        if self.free != NIL {
+            let index: u32 = self.free;
+            self.free = match replace(&mut self.slots[index as usize], Slot::Occupied(node)) {
+                Slot::Free(next) => next,
+                Slot::Occupied(_) => unreachable!("the free list points to an occupied slot"),
+            };
+            return index;
+        }
+        let index: u32 = u32::try_from(self.slots.len()).ok().filter(|index: &u32| *index != NIL).expect("The arena can not address more nodes");
+        self.slots.push(Slot::Occupied(node));
+        index
+    }
+
+    fn free(&mut self, link: &mut u32) -> Option<Node<T, S, Self>> {
+        if *link == NIL {
+            return None;
+        }
+        self.push_down(link);
+        Some(self.release(replace(link, NIL)))
+    }
+
+    fn push_down(&mut self, node: &mut u32) {
+        // the node and its left child leave their slots for the time of the push, the right child is reached in its own
+        self.detached(*node, |arena: &mut Self, node: &mut Node<T, S, Self>| {
+            let [left, right] = node.children;
+            let mut push = |arena: &mut Self, left: Option<&mut S>| {
+                let right: Option<&mut S> = if right == NIL { None } else { Some(&mut arena.node_mut(right).summary) };
+                P::push_down(&mut node.summary, &mut node.value, &mut node.duplicates, [left, right]);
+            };
+            if left == NIL {
+                push(arena, None)
+            } else {
+                arena.detached(left, |arena: &mut Self, left: &mut Node<T, S, Self>| push(arena, Some(&mut left.summary)))
+            }
+        })
+    }
+
+    fn rebuild(summary: &S, part: S) -> S {
+        P::rebuild(summary, part)
+    }
+
+    fn descend<'a, F: FnMut(&mut Self, &mut u32) -> Option<Side>>(&'a mut self, link: &'a mut u32, mut step: F) -> Option<&'a mut Node<T, S, Self>> {
+        let mut index: u32 = *link;
+        while index != NIL {
+            let mut node: u32 = index;
+            match step(self, &mut node) {
+                None => return Some(self.node_mut(index)),
+                Some(side) => index = self.node(index).children[side as usize],
+            }
+        }
+        None
+    }
+
+    fn adopt(&mut self, other: &mut Self, link: u32) -> u32 {
+        transfer(other, self, link)
+    }
+
+    /// The arena holds only this tree, the slots are dropped at once and the capacity is kept
+    fn clear(&mut self, root: &mut u32) {
+        self.slots.clear();
+        self.free = NIL;
+        self.nodes = 0;
+        *root = NIL;
+    }
+}
+
+impl<T: fmt::Debug, C, S: Summary<T>> fmt::Debug for ArenaAvlTree<T, C, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<T> ArenaAvlTree<T> {
+    /// Create a new empty tree ordered by Ord with room for the given number of nodes before the arena grows
+    pub fn with_capacity(capacity: usize) -> Self {
+        AvlTree::with_storage(Arena::with_capacity(capacity), Natural, DuplicatePolicy::default())
+    }
+}
+
+impl<T, C, S: Summary<T>> ArenaAvlTree<T, C, S> {
+    /// Get the number of nodes the arena can hold before it grows, the free slots are reused first
+    pub fn capacity(&self) -> usize {
+        self.storage.slots.capacity()
+    }
+
+    /// Make room for at least the given number of new nodes, counting the free slots
+    pub fn reserve(&mut self, additional: usize) {
+        let free: usize = self.storage.slots.len() - self.storage.nodes;
+        self.storage.slots.reserve(additional.saturating_sub(free));
+    }
+
+    /// Give back the free slots and the unused capacity, the nodes are moved in pre order to the start of the arena
+    /// This is O(n), the indices of the nodes change but the values are not moved out of their nodes
+    pub fn shrink_to_fit(&mut self) {
+        self.root = self.storage.compact(self.root);
+        self.storage.slots.shrink_to_fit();
+    }
+}
+
+/// The nodes of the boxed tree are moved in an arena of the exact size in linear time, without copying the values
+impl<T, C, S: Summary<T>> From<AvlTree<T, C, S>> for ArenaAvlTree<T, C, S> {
+    fn from(mut tree: AvlTree<T, C, S>) -> Self {
+        let mut arena: ArenaAvlTree<T, C, S> = AvlTree::with_storage(Arena::default(), tree.cmp, tree.policy);
+        arena.root = transfer(&mut tree.storage, &mut arena.storage, tree.root.take());
+        arena.storage.slots.shrink_to_fit();
+        arena
+    }
+}
+
+/// The nodes of the arena are moved in boxed nodes in linear time, without copying the values
+impl<T, C, S: Summary<T>> From<ArenaAvlTree<T, C, S>> for AvlTree<T, C, S> {
+    fn from(mut tree: ArenaAvlTree<T, C, S>) -> Self {
+        let mut boxed: AvlTree<T, C, S> = AvlTree::with_storage(Boxes::default(), tree.cmp, tree.policy);
+        boxed.root = transfer(&mut tree.storage, &mut boxed.storage, replace(&mut tree.root, NIL));
+        boxed
+    }
+}
+
+#[cfg(test)]
+mod test_arena {
+    use super::*;
+    use crate::fixture::{shuffled, Position};
+    use crate::AvlError;
+
+    #[test]
+    fn test_insert_remove() {
+        let mut tree: ArenaAvlTree<u64> = ArenaAvlTree::default();
+        for value in shuffled(200) {
+            tree.insert(value).expect("Failed insert");
+            assert!(tree.is_correct());
+            assert!(tree.is_balanced());
+        }
+        assert_eq!(tree.len(), 200);
+        assert!(tree.iter().cloned().eq(0..200));
+        assert_eq!(tree.insert(5).unwrap_err(), AvlError::DuplicateValue);
+        for value in (0..200).filter(|value| value % 4 != 1) {
+            tree.remove(&value).expect("Failed remove");
+            assert!(tree.is_correct());
+            assert!(tree.is_balanced());
+        }
+        assert!(tree.iter().cloned().eq((0..200).filter(|value| value % 4 == 1)));
+        assert_eq!(tree.remove(&0).unwrap_err(), AvlError::NotFound);
+        assert_eq!((tree.first(), tree.last()), (Some(&1), Some(&197)));
+        assert_eq!(tree.select(2), Some(&9));
+        assert_eq!(tree.take(&9), Some(9));
+        assert_eq!(tree.take(&9), None);
+        assert_eq!(ArenaAvlTree::<u64>::default().remove(&0).unwrap_err(), AvlError::Empty);
+    }
+
+    #[test]
+    fn test_free_list() {
+        let mut tree: ArenaAvlTree<u64> = (0..100).collect();
+        assert_eq!(tree.storage.slots.len(), 100);
+        for value in 0..50 {
+            tree.remove(&value).expect("Failed remove");
+        }
+        assert_eq!(tree.storage.nodes, 50);
+        // the removed slots are taken again before the arena grows
+        for value in 100..150 {
+            tree.insert(value).expect("Failed insert");
+        }
+        assert_eq!(tree.storage.slots.len(), 100);
+        assert_eq!(tree.storage.free, NIL);
+        tree.insert(150).expect("Failed insert");
+        assert_eq!(tree.storage.slots.len(), 101);
+        assert!(tree.iter().cloned().eq(50..151));
+        assert!(tree.is_correct());
+    }
+
+    #[test]
+    fn test_capacity() {
+        let mut tree: ArenaAvlTree<u64> = ArenaAvlTree::with_capacity(64);
+        assert!(tree.capacity() >= 64);
+        for value in 0..64 {
+            tree.insert(value).expect("Failed insert");
+        }
+        assert!(tree.capacity() >= 64 && tree.capacity() < 128);
+        for value in 0..32 {
+            tree.remove(&(value * 2)).expect("Failed remove");
+        }
+        // the 32 free slots count in the reservation
+        let capacity: usize = tree.capacity();
+        tree.reserve(32);
+        assert_eq!(tree.capacity(), capacity);
+        tree.reserve(100);
+        assert!(tree.capacity() >= 132);
+        tree.shrink_to_fit();
+        assert_eq!(tree.capacity(), 32);
+        assert_eq!((tree.storage.slots.len(), tree.storage.free, tree.root), (32, NIL, 0));
+        assert!(tree.is_correct());
+        assert!(tree.is_balanced());
+        assert!(tree.iter().cloned().eq((0..32).map(|value| value * 2 + 1)));
+        tree.insert(0).expect("Failed insert");
+        assert_eq!(tree.first(), Some(&0));
+        tree.clear();
+        assert!(tree.is_empty());
+        tree.shrink_to_fit();
+        assert_eq!((tree.capacity(), tree.root), (0, NIL));
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let by_x = |left: &Position, right: &Position| left.x.cmp(&right.x);
+        let mut tree = AvlTree::with_storage(Arena::<Position>::default(), by_x, DuplicatePolicy::default());
+        tree.insert(Position { x: 1, y: 0 }).expect("Failed insert")
+            .insert(Position { x: 1, y: 1 }).expect("Failed insert")
+            .insert(Position { x: 0, y: 0 }).expect("Failed insert");
+        assert_eq!(tree.len(), 3);
+        assert_eq!(tree.storage.nodes, 2);
+        assert_eq!(tree.occurrences(&Position { x: 1, y: -1 }), 2);
+        assert_eq!(tree.last(), Some(&Position { x: 1, y: 0 }));
+        assert_eq!(tree.iter().next_back(), Some(&Position { x: 1, y: 1 }));
+        assert!(tree.contains_exact(&Position { x: 1, y: 1 }));
+        assert!(!tree.contains_exact(&Position { x: 1, y: 2 }));
+        assert_eq!(tree.take(&Position { x: 1, y: -1 }), Some(Position { x: 1, y: 0 }));
+        assert_eq!(tree.get_ref(&Position { x: 1, y: -1 }), Some(&Position { x: 1, y: 1 }));
+        let mut counted: ArenaAvlTree<u64> = AvlTree::with_storage(Arena::default(), Natural, DuplicatePolicy::Count);
+        counted.insert(7).expect("Failed insert").insert(7).expect("Failed insert");
+        assert_eq!(counted.occurrences(&7), 2);
+        counted.remove(&7).expect("Failed remove");
+        assert_eq!(counted.occurrences(&7), 1);
+        assert_eq!(counted.len(), 1);
+        let mut rejecting: ArenaAvlTree<u64> = AvlTree::with_storage(Arena::default(), Natural, DuplicatePolicy::Reject);
+        rejecting.insert(7).expect("Failed insert");
+        assert_eq!(rejecting.insert(7).unwrap_err(), AvlError::DuplicateKey);
+    }
+
+    #[test]
+    fn test_model() {
+        // the same operations on a boxed tree and an arena tree give the same values
+        let mut boxed: AvlTree<u64> = AvlTree::new();
+        let mut arena: ArenaAvlTree<u64> = ArenaAvlTree::default();
+        let mut seed: u64 = 7;
+        for step in 0..3000 {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            let value: u64 = (seed >> 33) % 300;
+            match (seed >> 20) % 3 {
+                0 => assert_eq!(boxed.remove(&value).is_ok(), arena.remove(&value).is_ok()),
+                1 => assert_eq!(boxed.take(&value), arena.take(&value)),
+                _ => assert_eq!(boxed.insert(value).is_ok(), arena.insert(value).is_ok()),
+            }
+            if step % 500 == 0 {
+                arena.shrink_to_fit();
+            }
+            assert_eq!(boxed.len(), arena.len());
+        }
+        assert!(arena.is_correct());
+        assert!(arena.is_balanced());
+        assert!(boxed.iter().eq(arena.iter()));
+        assert_eq!(arena.storage.nodes + arena.storage.slots.iter().filter(|slot| matches!(slot, Slot::Free(_))).count(), arena.storage.slots.len());
+    }
+
+    #[test]
+    fn test_against_tree() {
+        // every query gives on the arena what it gives on a boxed tree, the duplicates included
+        let mut boxed: AvlTree<Position> = AvlTree::new();
+        let mut arena: ArenaAvlTree<Position> = ArenaAvlTree::default();
+        let mut seed: u64 = 11;
+        for _ in 0..2000 {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            let position: Position = Position { x: ((seed >> 33) % 60) as i32, y: ((seed >> 45) % 3) as i32 };
+            let key: i32 = ((seed >> 24) % 64) as i32 - 2;
+            match (seed >> 20) % 4 {
+                0 => assert_eq!(boxed.remove(&position).is_ok(), arena.remove(&position).is_ok()),
+                1 => assert_eq!(boxed.take(&key), arena.take(&key)),
+                _ => assert_eq!(boxed.insert(position.clone()).is_ok(), arena.insert(position).is_ok()),
+            }
+            assert_eq!((boxed.first(), boxed.last()), (arena.first(), arena.last()));
+            assert_eq!((boxed.get_ref(&key), boxed.occurrences(&key)), (arena.get_ref(&key), arena.occurrences(&key)));
+            assert_eq!((boxed.rank(&key), boxed.select(key as usize)), (arena.rank(&key), arena.select(key as usize)));
+            assert_eq!((boxed.floor(&key), boxed.ceiling(&key)), (arena.floor(&key), arena.ceiling(&key)));
+            assert_eq!((boxed.predecessor(&key), boxed.successor(&key)), (arena.predecessor(&key), arena.successor(&key)));
+            assert!(boxed.range::<i32, _>(key..key + 9).eq(arena.range::<i32, _>(key..key + 9)));
+            assert!(boxed.range::<i32, _>(..=key).rev().eq(arena.range::<i32, _>(..=key).rev()));
+        }
+        assert!(arena.is_correct());
+        assert!(arena.is_balanced());
+        assert!(boxed.iter().rev().eq(arena.iter().rev()));
+        // the two ends meet inside the bucket of a key without yielding a value twice
+        let (mut ours, mut theirs) = (arena.range::<i32, _>(10..50), boxed.range::<i32, _>(10..50));
+        let (mut iter, mut reference) = (arena.iter(), boxed.iter());
+        for step in 0..boxed.len() {
+            if step % 3 == 0 {
+                assert_eq!(ours.next_back(), theirs.next_back());
+                assert_eq!(iter.next_back(), reference.next_back());
+            } else {
+                assert_eq!(ours.next(), theirs.next());
+                assert_eq!(iter.next(), reference.next());
+            }
+            assert_eq!(iter.len(), reference.len());
+        }
+        let present: Position = boxed.iter().next_back().cloned().unwrap();
+        assert_eq!(boxed.get_exact(&present), arena.get_exact(&present));
+        assert_eq!((boxed.min(), boxed.max()), (arena.min(), arena.max()));
+        assert!(AvlTree::<Position>::from(arena.clone()).iter().eq(boxed.iter()));
+        assert!(arena.into_iter().eq(boxed.into_iter()));
+    }
+
+    #[test]
+    fn test_from_tree() {
+        let tree: AvlTree<u64> = (0..50).collect();
+        let height: usize = tree.height();
+        let arena: ArenaAvlTree<u64> = ArenaAvlTree::from(tree);
+        assert!(arena.is_correct());
+        assert_eq!(arena.height(), height);
+        assert_eq!(arena.capacity(), 50);
+        assert!(arena.iter().cloned().eq(0..50));
+        let collected: ArenaAvlTree<u64> = (0..50).rev().collect();
+        assert_eq!(format!("{:?}", collected), format!("{:?}", arena));
+        assert!(collected.clone().iter().eq(arena.iter()));
+        let back: AvlTree<u64> = AvlTree::from(arena);
+        assert!(back.is_correct());
+        assert_eq!(back.height(), height);
+        assert!(back.into_iter().eq(0..50));
+    }
+}
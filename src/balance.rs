@@ -0,0 +1,85 @@
+use std::cmp::max;
+
+use crate::Side;
+
+/// How the nodes of an AVL tree are held (a Box per node, Arc shared nodes, the slots of an arena),
+/// the heights and the rotations of the three trees are written once over it
+pub trait Storage {
+    /// What the rotations hold to reach a node: the node itself, or its index in an arena
+    type Node: ?Sized;
+    /// A link to a subtree, empty or not
+    type Link;
+
+    /// Height of the subtree, 0 when empty
+    fn height(&self, link: &Self::Link) -> usize;
+
+    fn child<'a>(&'a self, node: &'a Self::Node, side: Side) -> &'a Self::Link;
+}
+
+/// What the rotations change in the storage of the nodes
+/// A rotation keeps the node in its place and moves the values between the nodes, so the link to the root of the subtree never changes
+pub trait StorageMut: Storage {
+    /// The root of a subtree which is not empty, copied first if it is shared with another tree
+    fn node(link: &mut Self::Link) -> &mut Self::Node;
+
+    /// Detach the child of the node on the side and leave that side empty
+    fn take_child(&mut self, node: &mut Self::Node, side: Side) -> Self::Link;
+
+    fn set_child(&mut self, node: &mut Self::Node, side: Side, child: Self::Link);
+
+    /// Exchange the values (value, duplicates and copies) of the two nodes, the children stay
+    fn swap_values(&mut self, node: &mut Self::Node, other: &mut Self::Node);
+
+    /// Recompute what the node keeps about its subtree (height, size...), its children are already up to date
+    fn update(&mut self, node: &mut Self::Node);
+}
+
+/// Height of the subtree of the node out of the heights of its children
+pub(crate) fn height<L: Storage>(storage: &L, node: &L::Node) -> usize {
+    1 + max(storage.height(storage.child(node, Side::Left)), storage.height(storage.child(node, Side::Right)))
+}
+
+/// Height of the left subtree of the node minus the one of the right subtree
+pub(crate) fn balance_factor<L: Storage>(storage: &L, node: &L::Node) -> isize {
+    storage.height(storage.child(node, Side::Left)) as isize - storage.height(storage.child(node, Side::Right)) as isize
+}
+
+/// The node is lowered toward the side and its child of the other side goes up in its place, false if there is no such child
+pub(crate) fn rotate<L: StorageMut>(storage: &mut L, node: &mut L::Node, side: Side) -> bool {
+    let mut child: L::Link = storage.take_child(node, !side);
+    if storage.height(&child) == 0 {
+        return false;
+    }
+    let rising: &mut L::Node = L::node(&mut child);
+    let inner: L::Link = storage.take_child(rising, side);
+    let outer: L::Link = storage.take_child(rising, !side);
+    let lowered: L::Link = storage.take_child(node, side);
+    // the rising values go in the node, the node of the child now holds the lowered ones
+    storage.swap_values(node, rising);
+    storage.set_child(rising, side, lowered);
+    storage.set_child(rising, !side, inner);
+    storage.update(rising);
+    storage.set_child(node, side, child);
+    storage.set_child(node, !side, outer);
+    storage.update(node);
+    true
+}
+
+/// Update the node and rotate it if the heights of its children differ by 2 (they can not differ more after one change)
+/// Return true if the node was rotated
+pub(crate) fn rebalance<L: StorageMut>(storage: &mut L, node: &mut L::Node) -> bool {
+    storage.update(node);
+    let side: Side = match balance_factor(storage, node) {
+        2 => Side::Left,
+        -2 => Side::Right,
+        _ => return false,
+    };
+    let mut child: L::Link = storage.take_child(node, side);
+    // a child leaning to the other side first goes the other way (double rotation)
+    let leaning: isize = balance_factor(storage, L::node(&mut child));
+    if (side == Side::Left && leaning < 0) || (side == Side::Right && leaning > 0) {
+        rotate(storage, L::node(&mut child), side);
+    }
+    storage.set_child(node, side, child);
+    rotate(storage, node, !side)
+}
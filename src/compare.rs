@@ -0,0 +1,50 @@
+use std::cmp::Ordering;
+
+/// The ordering used by a tree to place, find and remove its values
+/// It must be a total order and must not change while values are in the tree
+/// Values comparing Equal share the same node (they are the duplicates of each other)
+pub trait Comparator<T: ?Sized> {
+    fn compare(&self, left: &T, right: &T) -> Ordering;
+}
+
+/// The natural order of the values given by their Ord trait, this is the default of every tree
+/// It also compares any key the values can be borrowed as, see AvlTree::get
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Natural;
+
+impl<T: Ord + ?Sized> Comparator<T> for Natural {
+    fn compare(&self, left: &T, right: &T) -> Ordering {
+        left.cmp(right)
+    }
+}
+
+/// Any closure (or function) taking two values can be used as the order of a tree
+impl<T: ?Sized, F: Fn(&T, &T) -> Ordering> Comparator<T> for F {
+    fn compare(&self, left: &T, right: &T) -> Ordering {
+        self(left, right)
+    }
+}
+
+#[cfg(test)]
+mod test_compare {
+    use super::*;
+
+    #[test]
+    fn test_natural() {
+        assert_eq!(Natural.compare(&1, &2), Ordering::Less);
+        assert_eq!(Natural.compare("b", "a"), Ordering::Greater);
+        assert_eq!(Natural.compare(&[1, 2][..], &[1, 2][..]), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_closure() {
+        let reverse = |left: &u64, right: &u64| right.cmp(left);
+        assert_eq!(reverse.compare(&1, &2), Ordering::Greater);
+        let insensitive = |left: &str, right: &str| left.to_lowercase().cmp(&right.to_lowercase());
+        assert_eq!(insensitive.compare("Alpha", "aLPHA"), Ordering::Equal);
+        fn by_length(left: &str, right: &str) -> Ordering {
+            left.len().cmp(&right.len())
+        }
+        assert_eq!(by_length.compare("bb", "a"), Ordering::Greater);
+    }
+}
@@ -0,0 +1,794 @@
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::ptr;
+
+use crate::balance::Storage;
+use crate::storage::{Backend, Path};
+use crate::{AvlError, AvlTree, Boxes, Comparator, Node, Side, Summary};
+
+/// Where a cursor stands: the nodes from the root down to the current one and the index of the current value
+/// in the bucket of that node (0 for its value, then its duplicates), no node at all is the ghost position
+/// between the last value and the first one
+struct Position<'a, T, S, B: Storage> {
+    storage: &'a B,
+    root: Option<&'a Node<T, S, B>>,
+    stack: Vec<&'a Node<T, S, B>>,
+    index: usize,
+}
+
+fn child<'a, T, S: Summary<T>, B: Backend<T, S>>(storage: &'a B, node: &'a Node<T, S, B>, side: Side) -> Option<&'a Node<T, S, B>> {
+    node.child(storage, side)
+}
+
+/// Check if the node is the child of the parent on this side (the nodes are told apart by address)
+fn is_child<T, S: Summary<T>, B: Backend<T, S>>(storage: &B, parent: &Node<T, S, B>, side: Side, node: &Node<T, S, B>) -> bool {
+    child(storage, parent, side).map_or(false, |child: &Node<T, S, B>| ptr::eq(child, node))
+}
+
+fn value_at<T, S, B: Storage>(node: &Node<T, S, B>, index: usize) -> &T {
+    if index == 0 {
+        &node.value
+    } else {
+        &node.duplicates[index - 1]
+    }
+}
+
+/// Index of the value a walk toward the side meets first in the bucket of the node (the value going right, the last duplicate going left)
+fn entry_index<T, S, B: Storage>(node: &Node<T, S, B>, side: Side) -> usize {
+    match side {
+        Side::Right => 0,
+        Side::Left => node.duplicates.len(),
+    }
+}
+
+impl<'a, T, S: Summary<T>, B: Backend<T, S>> Clone for Position<'a, T, S, B> {
+    fn clone(&self) -> Self {
+        Position {
+            storage: self.storage,
+            root: self.root,
+            stack: self.stack.clone(),
+            index: self.index,
+        }
+    }
+}
+
+impl<'a, T, S: Summary<T>, B: Backend<T, S>> Position<'a, T, S, B> {
+    fn new(storage: &'a B, root: &'a B::Link) -> Self {
+        Position {
+            storage,
+            root: storage.root(root),
+            stack: Vec::new(),
+            index: 0,
+        }
+    }
+
+    fn current(&self) -> Option<&'a T> {
+        let top: &'a Node<T, S, B> = *self.stack.last()?;
+        Some(value_at(top, self.index))
+    }
+
+    /// Push the node and its children toward the side down to the last one
+    fn push_spine(&mut self, mut tree: Option<&'a Node<T, S, B>>, side: Side) {
+        while let Some(node) = tree {
+            self.stack.push(node);
+            tree = child(self.storage, node, side);
+        }
+    }
+
+    /// Move to the next value toward the side (right for the next one), the ghost comes after the last value and before the first one
+    /// A step goes up or down the tree as far as needed, that is amortized O(1) over a walk
+    fn step(&mut self, side: Side) {
+        let top: &'a Node<T, S, B> = match self.stack.last() {
+            None => {
+                self.push_spine(self.root, !side);
+                self.index = self.stack.last().map_or(0, |top: &&Node<T, S, B>| entry_index(top, side));
+                return;
+            }
+            Some(top) => top,
+        };
+        match side {
+            Side::Right if self.index < top.duplicates.len() => {
+                self.index += 1;
+                return;
+            }
+            Side::Left if self.index > 0 => {
+                self.index -= 1;
+                return;
+            }
+            _ => {}
+        }
+        if let Some(next) = child(self.storage, top, side) {
+            self.push_spine(Some(next), !side);
+            self.index = entry_index(self.stack.last().unwrap(), side);
+            return;
+        }
+        // go up until coming from the other side of a node, that node is the next one
+        while let Some(from) = self.stack.pop() {
+            if let Some(parent) = self.stack.last() {
+                if is_child(self.storage, parent, !side, from) {
+                    self.index = entry_index(parent, side);
+                    return;
+                }
+            }
+        }
+        self.index = 0;
+    }
+
+    /// The value a step toward the side would reach, without moving
+    fn peek(&self, side: Side) -> Option<&'a T> {
+        let top: &'a Node<T, S, B> = match self.stack.last() {
+            None => {
+                let mut edge: &'a Node<T, S, B> = self.root?;
+                while let Some(next) = child(self.storage, edge, !side) {
+                    edge = next;
+                }
+                return Some(value_at(edge, entry_index(edge, side)));
+            }
+            Some(top) => top,
+        };
+        match side {
+            Side::Right if self.index < top.duplicates.len() => return Some(value_at(top, self.index + 1)),
+            Side::Left if self.index > 0 => return Some(value_at(top, self.index - 1)),
+            _ => {}
+        }
+        if let Some(mut edge) = child(self.storage, top, side) {
+            while let Some(next) = child(self.storage, edge, !side) {
+                edge = next;
+            }
+            return Some(value_at(edge, entry_index(edge, side)));
+        }
+        let parent: &'a Node<T, S, B> = self.stack.windows(2).rev()
+            .find(|pair: &&[&Node<T, S, B>]| is_child(self.storage, pair[0], !side, pair[1]))
+            .map(|pair: &[&'a Node<T, S, B>]| pair[0])?;
+        Some(value_at(parent, entry_index(parent, side)))
+    }
+
+    /// Stand on the value at this index of the in order walk (as AvlTree::select), on the ghost if there is none
+    fn seek_rank(&mut self, index: usize) {
+        let mut index: usize = index;
+        let mut tree: Option<&'a Node<T, S, B>> = self.root;
+        self.stack.clear();
+        self.index = 0;
+        while let Some(current) = tree {
+            self.stack.push(current);
+            let left: usize = current.left_size(self.storage);
+            if index < left {
+                tree = child(self.storage, current, Side::Left);
+            } else if index <= left + current.duplicates.len() {
+                self.index = index - left;
+                return;
+            } else {
+                index -= left + 1 + current.duplicates.len();
+                tree = child(self.storage, current, Side::Right);
+            }
+        }
+        self.stack.clear();
+    }
+
+    /// Stand on the first value whose key is not lower than the key, on the ghost if there is none
+    fn seek_key<Q: ?Sized, C: Comparator<Q>>(&mut self, key: &Q, cmp: &C) where T: Borrow<Q> {
+        let mut tree: Option<&'a Node<T, S, B>> = self.root;
+        let mut found: usize = 0;
+        self.stack.clear();
+        self.index = 0;
+        while let Some(current) = tree {
+            self.stack.push(current);
+            tree = match cmp.compare(key, current.value.borrow()) {
+                Ordering::Greater => child(self.storage, current, Side::Right),
+                Ordering::Less => {
+                    found = self.stack.len();
+                    child(self.storage, current, Side::Left)
+                }
+                Ordering::Equal => return,
+            };
+        }
+        self.stack.truncate(found);
+    }
+}
+
+/// A position in an AvlTree which can move to the previous or the next value, see AvlTree::cursor_front
+/// The values sharing a key are stepped through one by one, starting by the one stored in the node
+/// Past the last value (or before the first one) the cursor stands on a ghost position where current is None
+pub struct Cursor<'a, T, S = (), B: Storage = Boxes<T, S>> {
+    position: Position<'a, T, S, B>,
+}
+
+impl<'a, T, S: Summary<T>, B: Backend<T, S>> Clone for Cursor<'a, T, S, B> {
+    fn clone(&self) -> Self {
+        Cursor {
+            position: self.position.clone(),
+        }
+    }
+}
+
+impl<'a, T, S: Summary<T>, B: Backend<T, S>> Cursor<'a, T, S, B> {
+    pub(crate) fn front(storage: &'a B, root: &'a B::Link) -> Self {
+        let mut cursor: Cursor<'a, T, S, B> = Cursor {
+            position: Position::new(storage, root),
+        };
+        cursor.move_next();
+        cursor
+    }
+
+    pub(crate) fn back(storage: &'a B, root: &'a B::Link) -> Self {
+        let mut cursor: Cursor<'a, T, S, B> = Cursor {
+            position: Position::new(storage, root),
+        };
+        cursor.move_prev();
+        cursor
+    }
+
+    pub(crate) fn at<Q: ?Sized, C: Comparator<Q>>(storage: &'a B, root: &'a B::Link, key: &Q, cmp: &C) -> Self where T: Borrow<Q> {
+        let mut position: Position<'a, T, S, B> = Position::new(storage, root);
+        position.seek_key(key, cmp);
+        Cursor {
+            position,
+        }
+    }
+
+    /// Get the value the cursor stands on, None on the ghost position
+    pub fn current(&self) -> Option<&'a T> {
+        self.position.current()
+    }
+
+    /// Move to the next value in ascending order, amortized O(1)
+    pub fn move_next(&mut self) {
+        self.position.step(Side::Right);
+    }
+
+    /// Move to the previous value in ascending order, amortized O(1)
+    pub fn move_prev(&mut self) {
+        self.position.step(Side::Left);
+    }
+
+    /// Get the value move_next would reach without moving
+    pub fn peek_next(&self) -> Option<&'a T> {
+        self.position.peek(Side::Right)
+    }
+
+    /// Get the value move_prev would reach without moving
+    pub fn peek_prev(&self) -> Option<&'a T> {
+        self.position.peek(Side::Left)
+    }
+}
+
+/// A cursor which can also insert values next to its position and remove the value it stands on, see AvlTree::cursor_front_mut
+/// It can not hold references in the tree it changes, so it takes the nodes from the root down to its value out of the tree
+/// (each one without the child leading to the next one): a move takes a child out or puts it back, amortized O(1) as for Cursor
+/// The tree is put back together before each change (O(log n), it rebalances the tree) and when the cursor is dropped,
+/// the cursor keeps standing on the same value after a change (leaking the cursor leaks the nodes it holds)
+pub struct CursorMut<'a, T, C, S = (), B: Backend<T, S> = Boxes<T, S>> {
+    /// Down to the current node, it holds no node on the ghost position and the tree is whole then
+    path: Path<'a, T, C, S, B>,
+    index: usize,
+    /// The rank of the value to stand on once the tree given whole to as_cursor is taken apart again
+    parked: Option<usize>,
+}
+
+impl<'a, T, C, S: Summary<T>, B: Backend<T, S>> CursorMut<'a, T, C, S, B> {
+    fn new(tree: &'a mut AvlTree<T, C, S, B>) -> Self {
+        CursorMut {
+            path: Path::new(tree),
+            index: 0,
+            parked: None,
+        }
+    }
+
+    pub(crate) fn front(tree: &'a mut AvlTree<T, C, S, B>) -> Self {
+        let mut cursor: CursorMut<'a, T, C, S, B> = CursorMut::new(tree);
+        cursor.step(Side::Right);
+        cursor
+    }
+
+    pub(crate) fn back(tree: &'a mut AvlTree<T, C, S, B>) -> Self {
+        let mut cursor: CursorMut<'a, T, C, S, B> = CursorMut::new(tree);
+        cursor.step(Side::Left);
+        cursor
+    }
+
+    /// Stand on the first value whose key is not lower than the key, on the ghost if there is none
+    pub(crate) fn at<Q: ?Sized>(tree: &'a mut AvlTree<T, C, S, B>, key: &Q) -> Self where T: Borrow<Q>, C: Comparator<Q> {
+        let mut cursor: CursorMut<'a, T, C, S, B> = CursorMut::new(tree);
+        if !cursor.enter() {
+            return cursor;
+        }
+        let mut found: usize = 0;
+        loop {
+            let side: Side = match cursor.path.tree().cmp.compare(key, cursor.path.node().unwrap().value.borrow()) {
+                Ordering::Greater => Side::Right,
+                Ordering::Less => {
+                    found = cursor.path.depth();
+                    Side::Left
+                }
+                Ordering::Equal => return cursor,
+            };
+            if !cursor.down(side) {
+                break;
+            }
+        }
+        if found == 0 {
+            cursor.close();
+        }
+        while cursor.path.depth() > found {
+            cursor.path.up();
+        }
+        cursor
+    }
+
+    /// The node the cursor stands on, None on the ghost position
+    fn top(&self) -> Option<&Node<T, S, B>> {
+        self.path.node()
+    }
+
+    /// Take the root out of the tree and stand on it, false if the tree is empty
+    fn enter(&mut self) -> bool {
+        if self.path.tree().is_empty() {
+            return false;
+        }
+        self.path.enter();
+        self.index = 0;
+        true
+    }
+
+    /// Take the child of the current node on the side out of it and stand on it, false if there is none
+    fn down(&mut self, side: Side) -> bool {
+        if self.top().map_or(true, |top: &Node<T, S, B>| top.child(&self.path.tree().storage, side).is_none()) {
+            return false;
+        }
+        self.path.down(side);
+        true
+    }
+
+    /// Put the tree back together, the cursor is left on the ghost position
+    fn close(&mut self) {
+        self.path.close();
+        self.index = 0;
+    }
+
+    /// Put the tree back together and give the rank of the value the cursor stood on, None on the ghost position
+    fn zip(&mut self) -> Option<usize> {
+        if let Some(rank) = self.parked.take() {
+            return Some(rank);
+        }
+        let top: &Node<T, S, B> = self.top()?;
+        let mut rank: usize = top.left_size(&self.path.tree().storage) + self.index;
+        while let Some(side) = self.path.sides().last().cloned() {
+            self.path.up();
+            if side == Side::Right {
+                let parent: &Node<T, S, B> = self.top().unwrap();
+                rank += parent.size - parent.right_size(&self.path.tree().storage);
+            }
+        }
+        self.close();
+        Some(rank)
+    }
+
+    /// Stand on the value at this index of the in order walk (as AvlTree::select), on the ghost if there is none
+    fn seek_rank(&mut self, index: usize) {
+        if index >= self.path.tree().len() || !self.enter() {
+            return;
+        }
+        let mut index: usize = index;
+        loop {
+            let top: &Node<T, S, B> = self.top().unwrap();
+            let left: usize = top.left_size(&self.path.tree().storage);
+            if index < left {
+                self.down(Side::Left);
+            } else if index <= left + top.duplicates.len() {
+                self.index = index - left;
+                return;
+            } else {
+                index -= left + 1 + top.duplicates.len();
+                self.down(Side::Right);
+            }
+        }
+    }
+
+    /// Take the tree apart again down to the value it stood on before as_cursor
+    fn unpark(&mut self) {
+        if let Some(rank) = self.parked.take() {
+            self.seek_rank(rank);
+        }
+    }
+
+    /// A read only position at the value of the cursor while the tree is whole for as_cursor
+    fn parked(&self) -> Option<Position<'_, T, S, B>> {
+        let rank: usize = self.parked?;
+        let tree: &AvlTree<T, C, S, B> = self.path.tree();
+        let mut position: Position<'_, T, S, B> = Position::new(&tree.storage, &tree.root);
+        position.seek_rank(rank);
+        Some(position)
+    }
+
+    /// Move to the next value toward the side (right for the next one) as Position::step does, a child is taken out
+    /// or put back at each step down or up
+    fn step(&mut self, side: Side) {
+        self.unpark();
+        let top: &Node<T, S, B> = match self.top() {
+            None => {
+                if self.enter() {
+                    while self.down(!side) {}
+                    self.index = entry_index(self.top().unwrap(), side);
+                }
+                return;
+            }
+            Some(top) => top,
+        };
+        match side {
+            Side::Right if self.index < top.duplicates.len() => {
+                self.index += 1;
+                return;
+            }
+            Side::Left if self.index > 0 => {
+                self.index -= 1;
+                return;
+            }
+            _ => {}
+        }
+        if self.down(side) {
+            while self.down(!side) {}
+            self.index = entry_index(self.top().unwrap(), side);
+            return;
+        }
+        // go up until coming from the other side of a node, that node is the next one
+        while let Some(from) = self.path.sides().last().cloned() {
+            self.path.up();
+            if from == !side {
+                self.index = entry_index(self.top().unwrap(), side);
+                return;
+            }
+        }
+        self.close();
+    }
+
+    /// The value a step toward the side would reach, without moving
+    fn peek(&self, side: Side) -> Option<&T> {
+        if let Some(position) = self.parked() {
+            return position.peek(side);
+        }
+        let storage: &B = &self.path.tree().storage;
+        let top: &Node<T, S, B> = match self.top() {
+            None => return Position::new(storage, &self.path.tree().root).peek(side),
+            Some(top) => top,
+        };
+        match side {
+            Side::Right if self.index < top.duplicates.len() => return Some(value_at(top, self.index + 1)),
+            Side::Left if self.index > 0 => return Some(value_at(top, self.index - 1)),
+            _ => {}
+        }
+        if let Some(mut edge) = child(storage, top, side) {
+            while let Some(next) = child(storage, edge, !side) {
+                edge = next;
+            }
+            return Some(value_at(edge, entry_index(edge, side)));
+        }
+        // the deepest node left toward the other side on the way down
+        let depth: usize = self.path.sides().iter().rposition(|from: &Side| *from == !side)?;
+        let parent: &Node<T, S, B> = self.path.node_at(depth).unwrap();
+        Some(value_at(parent, entry_index(parent, side)))
+    }
+
+    /// Get the value the cursor stands on, None on the ghost position
+    pub fn current(&self) -> Option<&T> {
+        if let Some(position) = self.parked() {
+            return position.current();
+        }
+        let top: &Node<T, S, B> = self.top()?;
+        Some(value_at(top, self.index))
+    }
+
+    /// Move to the next value in ascending order, amortized O(1)
+    pub fn move_next(&mut self) {
+        self.step(Side::Right);
+    }
+
+    /// Move to the previous value in ascending order, amortized O(1)
+    pub fn move_prev(&mut self) {
+        self.step(Side::Left);
+    }
+
+    /// Get the value move_next would reach without moving
+    pub fn peek_next(&self) -> Option<&T> {
+        self.peek(Side::Right)
+    }
+
+    /// Get the value move_prev would reach without moving
+    pub fn peek_prev(&self) -> Option<&T> {
+        self.peek(Side::Left)
+    }
+
+    /// Get a read only cursor at the same position, for as long as this one is not used
+    /// The tree is put back together for it in O(log n), the next move takes it apart again
+    pub fn as_cursor(&mut self) -> Cursor<'_, T, S, B> {
+        self.parked = self.zip();
+        let tree: &AvlTree<T, C, S, B> = self.path.tree();
+        let mut position: Position<'_, T, S, B> = Position::new(&tree.storage, &tree.root);
+        if let Some(rank) = self.parked {
+            position.seek_rank(rank);
+        }
+        Cursor {
+            position,
+        }
+    }
+
+    /// Remove the value the cursor stands on and move to the next one, None on the ghost position
+    /// As AvlTree::take the oldest duplicate takes the place of a removed node value
+    pub fn remove_current(&mut self) -> Option<T> {
+        self.unpark();
+        let (path, index): (Vec<Side>, usize) = (self.path.sides().to_vec(), self.index);
+        let rank: usize = self.zip()?;
+        let tree: &mut AvlTree<T, C, S, B> = self.path.tree_mut();
+        let value: Option<T> = Node::edit_at(&mut tree.storage, &mut tree.root, &path, |storage: &mut B, tree: &mut B::Link| {
+            if index == 0 {
+                return Node::take_value(storage, tree);
+            }
+            let node: &mut B::Node = B::node(tree);
+            storage.push_down(node);
+            let value: T = storage.get_mut(node).duplicates.remove(index - 1);
+            storage.update(node);
+            Some(value)
+        });
+        // the next value now has the rank of the removed one
+        self.seek_rank(rank);
+        value
+    }
+}
+
+impl<'a, T: Eq, C: Comparator<T>, S: Summary<T>, B: Backend<T, S>> CursorMut<'a, T, C, S, B> {
+    /// Insert the value right before the current one (at the back on the ghost position), the cursor does not move
+    /// The value must be lower than the current one and not lower than the previous one, else AvlError::Misplaced is returned
+    /// A value sharing the key of the previous one is stored as the policy of the tree says
+    pub fn insert_before(&mut self, value: T) -> Result<(), AvlError> {
+        let fits: bool = self.peek_prev().map_or(true, |prev: &T| self.path.tree().cmp.compare(prev, &value) != Ordering::Greater)
+            && self.current().map_or(true, |current: &T| self.path.tree().cmp.compare(&value, current) == Ordering::Less);
+        if !fits {
+            return Err(AvlError::Misplaced);
+        }
+        self.insert(value, true)
+    }
+
+    /// Insert the value right after the current one (at the front on the ghost position), the cursor does not move
+    /// The value must not be lower than the current one and lower than the next one, else AvlError::Misplaced is returned
+    /// A value sharing the key of the current one is stored as the policy of the tree says
+    pub fn insert_after(&mut self, value: T) -> Result<(), AvlError> {
+        let fits: bool = self.current().map_or(true, |current: &T| self.path.tree().cmp.compare(current, &value) != Ordering::Greater)
+            && self.peek_next().map_or(true, |next: &T| self.path.tree().cmp.compare(&value, next) == Ordering::Less);
+        if !fits {
+            return Err(AvlError::Misplaced);
+        }
+        self.insert(value, false)
+    }
+
+    fn insert(&mut self, value: T, before: bool) -> Result<(), AvlError> {
+        let mut rank: Option<usize> = self.zip();
+        let tree: &mut AvlTree<T, C, S, B> = self.path.tree_mut();
+        let len: usize = tree.len();
+        let res: Result<(), AvlError> = tree.insert(value).map(|_| ());
+        // a value inserted before the current one shifts its rank
+        if before && tree.len() > len {
+            rank = rank.map(|rank: usize| rank + 1);
+        }
+        if let Some(rank) = rank {
+            self.seek_rank(rank);
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod test_cursor {
+    use crate::fixture::{points, positions, Position};
+    use crate::{AvlError, AvlTree, DuplicatePolicy};
+
+    #[test]
+    fn test_walk() {
+        let tree: AvlTree<u64> = (0..100).collect();
+        let mut cursor = tree.cursor_front();
+        for value in 0..100 {
+            assert_eq!(cursor.current(), Some(&value));
+            assert_eq!(cursor.peek_next().cloned(), if value == 99 { None } else { Some(value + 1) });
+            assert_eq!(cursor.peek_prev().cloned(), value.checked_sub(1));
+            cursor.move_next();
+        }
+        assert_eq!(cursor.current(), None);
+        assert_eq!(cursor.peek_next(), Some(&0));
+        assert_eq!(cursor.peek_prev(), Some(&99));
+        cursor.move_prev();
+        for value in (0..100).rev() {
+            assert_eq!(cursor.current(), Some(&value));
+            cursor.move_prev();
+        }
+        assert_eq!(cursor.current(), None);
+        cursor.move_next();
+        assert_eq!(cursor.current(), Some(&0));
+        assert_eq!(tree.cursor_back().current(), Some(&99));
+        let empty: AvlTree<u64> = AvlTree::new();
+        let mut cursor = empty.cursor_front();
+        assert_eq!(cursor.current(), None);
+        cursor.move_next();
+        assert_eq!(cursor.peek_prev(), None);
+    }
+
+    #[test]
+    fn test_cursor_at() {
+        let tree: AvlTree<u64> = (0..50).map(|value| value * 2).collect();
+        assert_eq!(tree.cursor_at(&10).current(), Some(&10));
+        let cursor = tree.cursor_at(&11);
+        assert_eq!(cursor.current(), Some(&12));
+        assert_eq!(cursor.peek_prev(), Some(&10));
+        assert_eq!(tree.cursor_at(&0).current(), Some(&0));
+        assert_eq!(tree.cursor_at(&99).current(), None);
+        let mut cursor = tree.cursor_at(&99);
+        cursor.move_prev();
+        assert_eq!(cursor.current(), Some(&98));
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let tree: AvlTree<Position> = positions(&[(1, 0), (2, 0), (2, 1), (2, 2), (3, 0)]);
+        let mut cursor = tree.cursor_at(&Position { x: 2, y: -1 });
+        let mut seen: Vec<(i32, i32)> = Vec::new();
+        while let Some(position) = cursor.current() {
+            seen.push((position.x, position.y));
+            cursor.move_next();
+        }
+        assert_eq!(seen, vec![(2, 0), (2, 1), (2, 2), (3, 0)]);
+        let mut cursor = tree.cursor_back();
+        cursor.move_prev();
+        assert_eq!(cursor.current(), Some(&Position { x: 2, y: 2 }));
+        assert_eq!(cursor.peek_prev(), Some(&Position { x: 2, y: 1 }));
+        cursor.move_prev();
+        cursor.move_prev();
+        assert_eq!(cursor.peek_prev(), Some(&Position { x: 1, y: 0 }));
+    }
+
+    #[test]
+    fn test_remove_current() {
+        let mut tree: AvlTree<u64> = (0..200).collect();
+        let mut cursor = tree.cursor_front_mut();
+        while let Some(value) = cursor.current().cloned() {
+            if value % 3 == 0 {
+                assert_eq!(cursor.remove_current(), Some(value));
+            } else {
+                cursor.move_next();
+            }
+        }
+        assert_eq!(cursor.remove_current(), None);
+        drop(cursor);
+        assert!(tree.iter().cloned().eq((0..200).filter(|value| value % 3 != 0)));
+        assert!(tree.is_correct());
+        assert!(tree.is_balanced());
+    }
+
+    #[test]
+    fn test_insert() {
+        let mut tree: AvlTree<u64> = (0..20).map(|value| value * 10).collect();
+        let mut cursor = tree.cursor_at_mut(&50);
+        cursor.insert_before(45).expect("Failed insert");
+        cursor.insert_after(55).expect("Failed insert");
+        assert_eq!(cursor.current(), Some(&50));
+        assert_eq!(cursor.peek_prev(), Some(&45));
+        assert_eq!(cursor.peek_next(), Some(&55));
+        assert_eq!(cursor.insert_before(60), Err(AvlError::Misplaced));
+        assert_eq!(cursor.insert_after(40), Err(AvlError::Misplaced));
+        assert_eq!(cursor.insert_after(50), Err(AvlError::DuplicateValue));
+        drop(cursor);
+        // fill all the gaps while walking forward
+        let mut cursor = tree.cursor_front_mut();
+        while let Some(value) = cursor.current().cloned() {
+            if cursor.peek_next().map_or(false, |next: &u64| *next > value + 1) {
+                cursor.insert_after(value + 1).expect("Failed insert");
+            }
+            cursor.move_next();
+        }
+        drop(cursor);
+        let mut cursor = tree.cursor_back_mut();
+        cursor.move_next();
+        cursor.insert_before(1000).expect("Failed insert");
+        cursor.insert_after(0).expect_err("Value already there");
+        drop(cursor);
+        assert!(tree.iter().cloned().eq((0..=190).chain(Some(1000))));
+        assert!(tree.is_correct());
+        assert!(tree.is_balanced());
+    }
+
+    #[test]
+    fn test_moves_around_changes() {
+        let mut tree: AvlTree<Position> = positions(&[(1, 0), (2, 0), (2, 1), (3, 0)]);
+        let mut cursor = tree.cursor_back_mut();
+        cursor.move_next();
+        assert_eq!(cursor.current(), None);
+        assert_eq!(cursor.peek_next(), Some(&Position { x: 1, y: 0 }));
+        cursor.move_prev();
+        cursor.move_prev();
+        assert_eq!(cursor.current(), Some(&Position { x: 2, y: 1 }));
+        assert_eq!(cursor.remove_current(), Some(Position { x: 2, y: 1 }));
+        assert_eq!(cursor.current(), Some(&Position { x: 3, y: 0 }));
+        cursor.insert_before(Position { x: 2, y: 2 }).expect("Failed insert");
+        cursor.move_prev();
+        assert_eq!(cursor.current(), Some(&Position { x: 2, y: 2 }));
+        let mut view = cursor.as_cursor();
+        view.move_prev();
+        assert_eq!(view.current(), Some(&Position { x: 2, y: 0 }));
+        assert_eq!(cursor.remove_current(), Some(Position { x: 2, y: 2 }));
+        assert_eq!(cursor.remove_current(), Some(Position { x: 3, y: 0 }));
+        assert_eq!(cursor.current(), None);
+        cursor.move_prev();
+        assert_eq!(cursor.current(), Some(&Position { x: 2, y: 0 }));
+        drop(cursor);
+        assert_eq!(points(tree.iter()), vec![(1, 0), (2, 0)]);
+        let mut empty: AvlTree<u64> = AvlTree::new();
+        let mut cursor = empty.cursor_front_mut();
+        cursor.move_prev();
+        assert_eq!(cursor.current(), None);
+        cursor.insert_after(1).expect("Failed insert");
+        assert_eq!(cursor.current(), None);
+        assert_eq!(cursor.peek_prev(), Some(&1));
+    }
+
+    #[test]
+    fn test_insert_duplicates() {
+        let mut tree: AvlTree<Position> = positions(&[(1, 0), (2, 0), (3, 0)]);
+        let mut cursor = tree.cursor_at_mut(&Position { x: 2, y: 0 });
+        cursor.insert_after(Position { x: 2, y: 1 }).expect("Failed insert");
+        cursor.insert_before(Position { x: 1, y: 1 }).expect("Failed insert");
+        assert_eq!(cursor.insert_before(Position { x: 2, y: 2 }), Err(AvlError::Misplaced));
+        cursor.move_next();
+        assert_eq!(cursor.current(), Some(&Position { x: 2, y: 1 }));
+        assert_eq!(cursor.insert_before(Position { x: 2, y: 3 }), Err(AvlError::Misplaced));
+        drop(cursor);
+        assert_eq!(points(tree.iter()), vec![(1, 0), (1, 1), (2, 0), (2, 1), (3, 0)]);
+        let mut tree: AvlTree<u64> = AvlTree::with_policy(DuplicatePolicy::Reject);
+        tree.insert(1).expect("Failed insert");
+        assert_eq!(tree.cursor_front_mut().insert_after(1), Err(AvlError::DuplicateKey));
+    }
+
+    #[test]
+    fn test_mut_walks() {
+        let mut tree: AvlTree<u64> = (0..500).collect();
+        let mut cursor = tree.cursor_at_mut(&250);
+        for value in (0..250).rev() {
+            cursor.move_prev();
+            assert_eq!(cursor.current(), Some(&value));
+        }
+        cursor.move_prev();
+        assert_eq!(cursor.current(), None);
+        for value in 0..500 {
+            assert_eq!(cursor.peek_next(), Some(&value));
+            cursor.move_next();
+            assert_eq!(cursor.current(), Some(&value));
+            assert_eq!(cursor.peek_prev(), value.checked_sub(1).as_ref());
+        }
+        cursor.move_next();
+        assert_eq!(cursor.current(), None);
+        drop(cursor);
+        assert!(tree.iter().cloned().eq(0..500));
+        assert!(tree.is_correct());
+        // the tree is whole again after a cursor is dropped anywhere
+        for key in [0, 137, 499, 500].iter() {
+            let mut cursor = tree.cursor_at_mut(key);
+            cursor.move_next();
+            drop(cursor);
+            assert!(tree.iter().cloned().eq(0..500));
+            assert!(tree.is_correct());
+            assert!(tree.is_balanced());
+        }
+        let buckets: Vec<(i32, i32)> = (0..100).map(|value: i32| (value / 4, value % 4)).collect();
+        let mut tree: AvlTree<Position> = positions(&buckets);
+        let mut cursor = tree.cursor_back_mut();
+        let mut count: usize = 0;
+        while let Some(value) = cursor.current().cloned() {
+            if count % 2 == 0 {
+                assert_eq!(cursor.remove_current(), Some(value));
+                cursor.move_prev();
+            } else {
+                cursor.move_prev();
+            }
+            count += 1;
+        }
+        drop(cursor);
+        assert_eq!(tree.len(), 50);
+        assert!(tree.is_correct());
+        assert!(tree.is_balanced());
+    }
+}
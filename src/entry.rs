@@ -0,0 +1,378 @@
+use std::cmp::Ordering;
+use std::iter::once;
+use std::mem::replace;
+
+use crate::storage::{Backend, Path};
+use crate::{AvlError, AvlTree, Boxes, Comparator, Node, Side, Summary};
+
+/// What the tree holds for the key of a value, got with AvlTree::entry
+/// The key is compared once on each node of a single descent which takes the nodes of its path out of the tree (see Path):
+/// the entry reads and changes the node of the key (or fills its empty place) where the descent stopped, then the path is
+/// put back and rebalanced on the way up when the entry is dropped or consumed
+pub enum Entry<'a, T, C, S = (), B: Backend<T, S> = Boxes<T, S>> {
+    Occupied(OccupiedEntry<'a, T, C, S, B>),
+    Vacant(VacantEntry<'a, T, C, S, B>),
+}
+
+/// A key already in the tree, the node holds a value and maybe duplicates (or counted insertions)
+pub struct OccupiedEntry<'a, T, C, S = (), B: Backend<T, S> = Boxes<T, S>> {
+    /// Down to the node holding the key
+    path: Path<'a, T, C, S, B>,
+    /// The value the entry was asked for, every value of the node must keep comparing equal to it
+    key: T,
+}
+
+/// A key not in the tree yet with the value to insert for it
+pub struct VacantEntry<'a, T, C, S = (), B: Backend<T, S> = Boxes<T, S>> {
+    /// Down to the empty place of the key
+    path: Path<'a, T, C, S, B>,
+    /// Number of values lower than the key, to find the new value back after the rotations
+    rank: usize,
+    value: T,
+}
+
+impl<'a, T: Eq, C: Comparator<T>, S: Summary<T>, B: Backend<T, S>> Entry<'a, T, C, S, B> {
+    /// Descend the tree once with the key of the value, taking the nodes of the path out of it
+    pub(crate) fn new(tree: &'a mut AvlTree<T, C, S, B>, value: T) -> Self {
+        let mut path: Path<'a, T, C, S, B> = Path::new(tree);
+        let mut rank: usize = 0;
+        path.enter();
+        loop {
+            // the pending updates are handed down on the way (see Lazy) so the entry sees the values as they are
+            path.push_down();
+            let node: &Node<T, S, B> = match path.node() {
+                None => break,
+                Some(node) => node,
+            };
+            let side: Side = match path.tree().cmp.compare(&value, &node.value) {
+                Ordering::Equal => return Entry::Occupied(OccupiedEntry { path, key: value }),
+                Ordering::Less => Side::Left,
+                Ordering::Greater => {
+                    rank += node.size - node.right_size(&path.tree().storage);
+                    Side::Right
+                }
+            };
+            path.down(side);
+        }
+        Entry::Vacant(VacantEntry { path, rank, value })
+    }
+
+    /// Insert the value of the entry if its key is not in the tree, then return the value stored for the key
+    /// As with get_mut a change through the reference must not change the summary of the value (see Summary)
+    pub fn or_insert(self) -> &'a mut T {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(),
+        }
+    }
+
+    /// Change the value stored for the key if it is in the tree, the change must not move the value to another key (debug builds check it)
+    /// The summaries of the path are computed again after the change
+    pub fn and_modify<F: FnOnce(&mut T)>(mut self, f: F) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            entry.modify(|node: &mut Node<T, S, B>| f(&mut node.value));
+        }
+        self
+    }
+}
+
+impl<'a, T: Eq, C: Comparator<T>, S: Summary<T>, B: Backend<T, S>> OccupiedEntry<'a, T, C, S, B> {
+    fn node(&self) -> &Node<T, S, B> {
+        let node: &Node<T, S, B> = self.path.node().unwrap();
+        debug_assert!(self.holds_key(node), "A value of the entry was moved to another key");
+        node
+    }
+
+    fn node_mut(&mut self) -> &mut Node<T, S, B> {
+        debug_assert!(self.holds_key(self.path.node().unwrap()), "A value of the entry was moved to another key");
+        self.path.node_mut().unwrap()
+    }
+
+    /// Check that the values of the node still have the key of the entry (they can be changed through get_mut)
+    fn holds_key(&self, node: &Node<T, S, B>) -> bool {
+        once(&node.value).chain(node.duplicates.iter()).all(|value: &T| self.path.tree().cmp.compare(value, &self.key) == Ordering::Equal)
+    }
+
+    /// Change the node holding the key, the nodes of the path (its summary included) are updated when they are put back
+    fn modify<R, F: FnOnce(&mut Node<T, S, B>) -> R>(&mut self, change: F) -> R {
+        let res: R = change(self.path.node_mut().unwrap());
+        debug_assert!(self.holds_key(self.path.node().unwrap()), "A value of the entry was moved to another key");
+        res
+    }
+
+    /// Get the value of the node holding the key (the oldest one with the key)
+    pub fn get(&self) -> &T {
+        &self.node().value
+    }
+
+    /// Get the value of the node holding the key, it must keep its key (debug builds check it the next time the entry reads the node)
+    /// The summaries of the path are computed again when it is put back, unlike with into_mut
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.node_mut().value
+    }
+
+    /// Same as get_mut but borrowing the tree for as long as the entry did
+    /// The path is put back first, the node is then reached again along it without comparing
+    pub fn into_mut(self) -> &'a mut T {
+        let OccupiedEntry { path, .. } = self;
+        let sides: Vec<Side> = path.sides().to_vec();
+        let tree: &'a mut AvlTree<T, C, S, B> = path.into_tree();
+        &mut Node::at_path_mut(&mut tree.storage, &mut tree.root, &sides).value
+    }
+
+    /// Get the other values sharing the key in insertion order (see DuplicatePolicy::Bucket)
+    pub fn duplicates(&self) -> &[T] {
+        &self.node().duplicates
+    }
+
+    /// Get the other values sharing the key to change them in place, they must keep their key and their summary
+    pub fn duplicates_mut(&mut self) -> &mut [T] {
+        &mut self.node_mut().duplicates
+    }
+
+    /// Get how many times the key was inserted again with DuplicatePolicy::Count
+    pub fn copies(&self) -> usize {
+        self.node().copies
+    }
+
+    /// Replace the value of the node holding the key and return the old one
+    /// The new value must have the same key, AvlError::Misplaced is returned otherwise
+    pub fn insert(&mut self, value: T) -> Result<T, AvlError> {
+        if self.path.tree().cmp.compare(&value, &self.key) != Ordering::Equal {
+            return Err(AvlError::Misplaced);
+        }
+        Ok(self.modify(|node: &mut Node<T, S, B>| replace(&mut node.value, value)))
+    }
+
+    /// Put the value the entry was asked for in place of the value of the node holding the key and return the old one
+    pub fn replace(self) -> T {
+        let OccupiedEntry { mut path, key } = self;
+        replace(&mut path.node_mut().unwrap().value, key)
+    }
+
+    /// Store another value with the same key as the policy of the tree says, or get the error insert would give
+    /// AvlError::Misplaced is returned for a value with another key
+    pub fn push(&mut self, value: T) -> Result<(), AvlError> {
+        if self.path.tree().cmp.compare(&value, &self.key) != Ordering::Equal {
+            return Err(AvlError::Misplaced);
+        }
+        let policy = self.path.tree().policy;
+        let stored: bool = self.modify(|node: &mut Node<T, S, B>| node.insert_duplicate(value, policy));
+        if !stored {
+            return Err(policy.refusal());
+        }
+        Ok(())
+    }
+
+    /// Take the value of the node out of the tree as AvlTree::take does, its oldest duplicate takes its place if any
+    pub fn remove(mut self) -> T {
+        self.path.edit(Node::take_value).unwrap()
+    }
+}
+
+impl<'a, T: Eq, C: Comparator<T>, S: Summary<T>, B: Backend<T, S>> VacantEntry<'a, T, C, S, B> {
+    /// Get the value which would be inserted
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    /// Give back the value without inserting it
+    pub fn into_value(self) -> T {
+        self.value
+    }
+
+    /// Insert the value in the empty place of its key, then rebalance the path as it is put back up to the root
+    /// The rotations may move the value to another node so it is reached again by its rank, without comparing
+    /// As with OccupiedEntry::get_mut a change through the reference must not change the summary of the value
+    pub fn insert(self) -> &'a mut T {
+        let VacantEntry { mut path, rank, value } = self;
+        path.edit(|storage: &mut B, place: &mut B::Link| *place = Node::create_tree(storage, value));
+        let tree: &'a mut AvlTree<T, C, S, B> = path.into_tree();
+        &mut Node::select_node_mut(&mut tree.storage, &mut tree.root, rank).unwrap().value
+    }
+}
+
+#[cfg(test)]
+mod test_entry {
+    use crate::arena::ArenaAvlTree;
+    use crate::{AvlError, AvlTree, DuplicatePolicy, Entry};
+    use std::borrow::Borrow;
+    use std::cmp::Ordering;
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct Counter {
+        word: String,
+        count: usize,
+    }
+
+    impl Counter {
+        fn new(word: &str) -> Self {
+            Counter {
+                word: word.to_string(),
+                count: 1,
+            }
+        }
+    }
+
+    impl Ord for Counter {
+        fn cmp(&self, other: &Self) -> Ordering {
+            self.word.cmp(&other.word)
+        }
+    }
+
+    impl PartialOrd for Counter {
+        fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl Borrow<str> for Counter {
+        fn borrow(&self) -> &str {
+            &self.word
+        }
+    }
+
+    #[test]
+    fn test_counter() {
+        let mut tree: AvlTree<Counter> = AvlTree::new();
+        let text: &str = "the quick brown fox jumps over the lazy dog the end fox";
+        for word in text.split(' ') {
+            tree.entry(Counter::new(word)).and_modify(|counter: &mut Counter| counter.count += 1).or_insert();
+        }
+        assert_eq!(tree.len(), 9);
+        assert_eq!(tree.get_ref("the").map(|counter: &Counter| counter.count), Some(3));
+        assert_eq!(tree.get_ref("fox").map(|counter: &Counter| counter.count), Some(2));
+        assert_eq!(tree.get_ref("dog").map(|counter: &Counter| counter.count), Some(1));
+        assert!(tree.is_correct());
+        assert!(tree.is_balanced());
+    }
+
+    #[test]
+    fn test_vacant_insert() {
+        let mut tree: AvlTree<u64> = AvlTree::new();
+        for value in (0..200).rev().chain(200..400) {
+            match tree.entry(value) {
+                Entry::Occupied(_) => panic!("The key should not be there"),
+                Entry::Vacant(entry) => {
+                    assert_eq!(*entry.value(), value);
+                    assert_eq!(*entry.insert(), value);
+                }
+            }
+            assert!(tree.is_correct());
+            assert!(tree.is_balanced());
+        }
+        assert!(tree.iter().cloned().eq(0..400));
+        assert_eq!(*tree.entry(7).or_insert(), 7);
+        assert_eq!(tree.len(), 400);
+        match tree.entry(1000) {
+            Entry::Vacant(entry) => assert_eq!(entry.into_value(), 1000),
+            Entry::Occupied(_) => panic!("The key should not be there"),
+        }
+        assert_eq!(tree.len(), 400);
+    }
+
+    #[test]
+    fn test_occupied() {
+        let mut tree: AvlTree<Counter> = ["a", "b", "c", "d", "e"].iter().map(|word: &&str| Counter::new(word)).collect();
+        let mut entry = match tree.entry(Counter::new("d")) {
+            Entry::Occupied(entry) => entry,
+            Entry::Vacant(_) => panic!("The key should be there"),
+        };
+        assert_eq!(entry.get().word, "d");
+        let old: Counter = entry.insert(Counter { word: "d".to_string(), count: 5 }).expect("Failed insert");
+        assert_eq!(old.count, 1);
+        assert_eq!(entry.insert(Counter::new("e")).map(|counter: Counter| counter.count), Err(AvlError::Misplaced));
+        assert_eq!(entry.push(Counter::new("c")), Err(AvlError::Misplaced));
+        entry.push(Counter { word: "d".to_string(), count: 6 }).expect("Failed push");
+        assert_eq!(entry.push(Counter { word: "d".to_string(), count: 6 }), Err(AvlError::DuplicateValue));
+        entry.duplicates_mut()[0].count += 1;
+        assert_eq!(entry.duplicates().iter().map(|counter: &Counter| counter.count).collect::<Vec<usize>>(), vec![7]);
+        drop(entry);
+        assert_eq!(tree.len(), 6);
+        assert_eq!(tree.occurrences("d"), 2);
+        assert!(tree.is_correct());
+        if let Entry::Occupied(entry) = tree.entry(Counter { word: "e".to_string(), count: 3 }) {
+            assert_eq!(entry.replace().count, 1);
+        }
+        assert_eq!(tree.get_ref("e").map(|counter: &Counter| counter.count), Some(3));
+        let entry = match tree.entry(Counter::new("d")) {
+            Entry::Occupied(entry) => entry,
+            Entry::Vacant(_) => panic!("The key should be there"),
+        };
+        assert_eq!(entry.remove().count, 5);
+        assert_eq!(tree.get_ref("d").map(|counter: &Counter| counter.count), Some(7));
+        if let Entry::Occupied(entry) = tree.entry(Counter::new("d")) {
+            entry.remove();
+        }
+        assert!(!tree.contains("d"));
+        assert_eq!(tree.len(), 4);
+        assert!(tree.is_correct());
+        assert!(tree.is_balanced());
+    }
+
+    #[test]
+    fn test_occupied_remove() {
+        let mut tree: AvlTree<u64> = (0..100).collect();
+        for value in (0..100).filter(|value| value % 3 != 0) {
+            if let Entry::Occupied(entry) = tree.entry(value) {
+                assert_eq!(entry.remove(), value);
+            }
+            assert!(tree.is_correct());
+            assert!(tree.is_balanced());
+        }
+        assert!(tree.iter().cloned().eq((0..100).filter(|value| value % 3 == 0)));
+    }
+
+    #[test]
+    #[should_panic(expected = "A value of the entry was moved to another key")]
+    fn test_modify_key() {
+        let mut tree: AvlTree<Counter> = ["a", "b", "c"].iter().map(|word: &&str| Counter::new(word)).collect();
+        tree.entry(Counter::new("b")).and_modify(|counter: &mut Counter| counter.word = "z".to_string());
+    }
+
+    #[test]
+    fn test_dropped_entries() {
+        let mut tree: AvlTree<u64> = (0..100).map(|value| value * 2).collect();
+        let mut arena: ArenaAvlTree<u64> = (0..100).map(|value| value * 2).collect();
+        for value in 0..200 {
+            match tree.entry(value) {
+                Entry::Occupied(entry) => assert_eq!(*entry.get(), value),
+                Entry::Vacant(entry) => assert_eq!(*entry.value(), value),
+            }
+            if let Entry::Vacant(entry) = arena.entry(value) {
+                assert_eq!(entry.into_value(), value);
+            }
+            assert!(tree.iter().cloned().eq((0..100).map(|value| value * 2)));
+            assert!(arena.iter().cloned().eq((0..100).map(|value| value * 2)));
+        }
+        for value in 0..200 {
+            assert_eq!(*arena.entry(value).or_insert(), value);
+            if value % 4 == 0 {
+                if let Entry::Occupied(entry) = arena.entry(value) {
+                    entry.remove();
+                }
+            }
+            assert!(arena.is_correct());
+            assert!(arena.is_balanced());
+        }
+        assert!(arena.iter().cloned().eq((0..200).filter(|value| value % 4 != 0)));
+    }
+
+    #[test]
+    fn test_policy() {
+        let mut tree: AvlTree<u64> = AvlTree::with_policy(DuplicatePolicy::Count);
+        tree.insert(1).expect("Failed insert");
+        if let Entry::Occupied(mut entry) = tree.entry(1) {
+            entry.push(1).expect("Failed push");
+            entry.push(1).expect("Failed push");
+            assert_eq!(entry.copies(), 2);
+        }
+        assert_eq!(tree.occurrences(&1), 3);
+        let mut tree: AvlTree<u64> = AvlTree::with_policy(DuplicatePolicy::Reject);
+        tree.insert(1).expect("Failed insert");
+        if let Entry::Occupied(mut entry) = tree.entry(1) {
+            assert_eq!(entry.push(1), Err(AvlError::DuplicateKey));
+        }
+        assert_eq!(tree.len(), 1);
+    }
+}
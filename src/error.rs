@@ -0,0 +1,80 @@
+use core::fmt;
+use std::error::Error;
+
+/// The reasons an operation on an AvlTree can fail
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AvlError {
+    /// The tree has no node
+    Empty,
+    /// No value in the tree matches the one asked for
+    NotFound,
+    /// The exact same value (by Eq) is already in the tree
+    DuplicateValue,
+    /// A value with the same key is already in the tree and the tree rejects duplicate keys
+    DuplicateKey,
+    /// The value found for removal was not the one asked for, the tree was left untouched
+    InconsistentRemoval,
+    /// The dump can not be read (or one of its values can not be parsed) at this byte position
+    InvalidDump(usize),
+    /// The value at this byte position of the dump is not strictly after the values before it
+    UnorderedDump(usize),
+    /// The node at this byte position of the dump breaks the AVL balance
+    UnbalancedDump(usize),
+    /// The value at this index of the input is lower than the one before it
+    Unsorted(usize),
+    /// The keys of the trees (and pivot) to join are not in order
+    Overlap,
+    /// The value inserted with a cursor or an entry does not belong at their position
+    Misplaced,
+}
+
+impl fmt::Display for AvlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AvlError::Empty => write!(f, "You don't have any node in the tree"),
+            AvlError::NotFound => write!(f, "The value was not found"),
+            AvlError::DuplicateValue => write!(f, "Can not insert same value twice"),
+            AvlError::DuplicateKey => write!(f, "Can not insert a value with a key already in the tree"),
+            AvlError::InconsistentRemoval => write!(f, "ERROR ! The value removed was not the correct one."),
+            AvlError::InvalidDump(position) => write!(f, "The dump is invalid at position {}", position),
+            AvlError::UnorderedDump(position) => write!(f, "The dump is not ordered at position {}", position),
+            AvlError::UnbalancedDump(position) => write!(f, "The dump is not balanced at position {}", position),
+            AvlError::Unsorted(index) => write!(f, "The value at index {} is not sorted", index),
+            AvlError::Overlap => write!(f, "The keys of the trees to join overlap"),
+            AvlError::Misplaced => write!(f, "The value does not belong at the position of the cursor or the entry"),
+        }
+    }
+}
+
+impl Error for AvlError {}
+
+#[cfg(test)]
+mod test_error {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        assert_eq!(AvlError::Empty.to_string(), "You don't have any node in the tree");
+        assert_eq!(AvlError::NotFound.to_string(), "The value was not found");
+        assert_eq!(AvlError::DuplicateValue.to_string(), "Can not insert same value twice");
+        assert_eq!(AvlError::DuplicateKey.to_string(), "Can not insert a value with a key already in the tree");
+        assert_eq!(AvlError::InconsistentRemoval.to_string(), "ERROR ! The value removed was not the correct one.");
+        assert_eq!(AvlError::InvalidDump(3).to_string(), "The dump is invalid at position 3");
+        assert_eq!(AvlError::UnorderedDump(12).to_string(), "The dump is not ordered at position 12");
+        assert_eq!(AvlError::UnbalancedDump(0).to_string(), "The dump is not balanced at position 0");
+        assert_eq!(AvlError::Unsorted(7).to_string(), "The value at index 7 is not sorted");
+        assert_eq!(AvlError::Overlap.to_string(), "The keys of the trees to join overlap");
+        assert_eq!(AvlError::Misplaced.to_string(), "The value does not belong at the position of the cursor or the entry");
+    }
+
+    #[test]
+    fn test_boxed() {
+        fn fails() -> Result<(), Box<dyn Error>> {
+            Err(AvlError::NotFound)?;
+            Ok(())
+        }
+        let err: Box<dyn Error> = fails().unwrap_err();
+        assert_eq!(err.to_string(), "The value was not found");
+        assert_eq!(err.downcast_ref::<AvlError>(), Some(&AvlError::NotFound));
+    }
+}
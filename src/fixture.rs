@@ -0,0 +1,53 @@
+use core::fmt;
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+
+use crate::AvlTree;
+
+/// A payload ordered by x only, so the positions sharing x are duplicates told apart by y
+#[derive(Clone, Eq, PartialEq, Debug, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct Position {
+    pub(crate) x: i32,
+    pub(crate) y: i32,
+}
+
+impl Ord for Position {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.x.cmp(&other.x)
+    }
+}
+
+impl PartialOrd for Position {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "\"{},{}\"", self.x, self.y)
+    }
+}
+
+/// Position is ordered by x only so it can be looked up with x alone
+impl Borrow<i32> for Position {
+    fn borrow(&self) -> &i32 {
+        &self.x
+    }
+}
+
+/// A tree of the positions (x, y) inserted in the given order
+pub(crate) fn positions(points: &[(i32, i32)]) -> AvlTree<Position> {
+    points.iter().map(|(x, y)| Position { x: *x, y: *y }).collect()
+}
+
+/// The (x, y) of the positions, to compare them with a literal
+pub(crate) fn points<'a, I: Iterator<Item = &'a Position>>(values: I) -> Vec<(i32, i32)> {
+    values.map(|position: &Position| (position.x, position.y)).collect()
+}
+
+/// Every value below count once in a scattered order, count must not be a multiple of 37
+pub(crate) fn shuffled(count: u64) -> impl Iterator<Item = u64> {
+    (0..count).map(move |value: u64| (value * 37) % count)
+}
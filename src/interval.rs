@@ -0,0 +1,387 @@
+use core::fmt;
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::iter::{FromIterator, FusedIterator};
+use std::ops::{Bound, Range};
+
+use crate::{iter, AvlTree, Entry, Natural, Node, Side, Summary, Tree};
+
+/// A half-open interval [start, end) with its value, ordered by start then by end
+#[derive(Clone)]
+struct Interval<K, V> {
+    bounds: (K, K),
+    value: V,
+}
+
+impl<K: PartialEq, V> PartialEq for Interval<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.bounds == other.bounds
+    }
+}
+
+impl<K: Eq, V> Eq for Interval<K, V> {}
+
+impl<K: Ord, V> PartialOrd for Interval<K, V> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K: Ord, V> Ord for Interval<K, V> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.bounds.cmp(&other.bounds)
+    }
+}
+
+impl<K, V> Borrow<(K, K)> for Interval<K, V> {
+    fn borrow(&self) -> &(K, K) {
+        &self.bounds
+    }
+}
+
+/// The greatest end of the intervals of a subtree, the subtrees ending before a query are not walked
+#[derive(Clone)]
+struct MaxEnd<K>(Option<K>);
+
+impl<K: Ord + Clone, V> Summary<Interval<K, V>> for MaxEnd<K> {
+    fn identity() -> Self {
+        MaxEnd(None)
+    }
+
+    fn from_value(value: &Interval<K, V>) -> Self {
+        MaxEnd(Some(value.bounds.1.clone()))
+    }
+
+    fn combine(&self, other: &Self) -> Self {
+        match (&self.0, &other.0) {
+            (Some(left), Some(right)) if left >= right => self.clone(),
+            (Some(_), None) => self.clone(),
+            _ => other.clone(),
+        }
+    }
+}
+
+/// A map from half-open intervals [start, end) to values, built on an AvlTree ordered by the starts
+/// Every node keeps the greatest end of its subtree so the queries only walk the subtrees holding a match
+/// Each interval is stored once, inserting an existing interval replaces its value
+/// An empty interval (start not lower than end) can be stored but contains and overlaps nothing
+pub struct IntervalTree<K, V> {
+    tree: AvlTree<Interval<K, V>, Natural, MaxEnd<K>>,
+}
+
+impl<K: Ord + Clone, V> IntervalTree<K, V> {
+    /// Create a new empty interval tree
+    pub fn new() -> Self {
+        IntervalTree {
+            tree: AvlTree::with_summary(),
+        }
+    }
+
+    /// Get the number of intervals in constant time
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    /// Check if the interval tree is empty or not
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Remove all the intervals, it can be reused afterwards
+    pub fn clear(&mut self) {
+        self.tree.clear();
+    }
+
+    /// Return the height of the underlying tree
+    pub fn height(&self) -> usize {
+        self.tree.height()
+    }
+
+    /// Check if the underlying tree is balanced (this is quite intensive but gave correct result)
+    pub fn is_balanced(&self) -> bool {
+        self.tree.is_balanced()
+    }
+
+    /// Check if the heights, sizes and greatest ends of the underlying tree are correct
+    pub fn is_correct(&self) -> bool {
+        self.tree.is_correct() && self.tree.root.as_deref().map_or(true, max_end_check)
+    }
+
+    /// Insert a value for the interval, if the interval was already there its old value is returned
+    pub fn insert(&mut self, interval: Range<K>, value: V) -> Option<V> {
+        let bounds: (K, K) = (interval.start, interval.end);
+        match self.tree.entry(Interval { bounds, value }) {
+            Entry::Occupied(entry) => Some(entry.replace().value),
+            Entry::Vacant(entry) => {
+                entry.insert();
+                None
+            }
+        }
+    }
+
+    /// Get the value of exactly this interval
+    pub fn get(&self, interval: &Range<K>) -> Option<&V> {
+        let bounds: (K, K) = (interval.start.clone(), interval.end.clone());
+        self.tree.get_ref(&bounds).map(|interval: &Interval<K, V>| &interval.value)
+    }
+
+    /// Take the value of exactly this interval out of the tree, the other intervals sharing its start or its end stay
+    pub fn remove(&mut self, interval: &Range<K>) -> Option<V> {
+        let bounds: (K, K) = (interval.start.clone(), interval.end.clone());
+        self.tree.take(&bounds).map(|interval: Interval<K, V>| interval.value)
+    }
+
+    /// Iterate over the intervals in ascending order of their starts (then of their ends)
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            inner: self.tree.iter(),
+        }
+    }
+
+    /// Iterate over the intervals containing the point (start <= point < end) in ascending order of their starts
+    /// This is one in-order walk skipping the subtrees whose greatest end is not after the point and stopping at the first start after it,
+    /// so only the subtrees holding a match are walked: O(log n + k) for k matches next to each other in the tree, O(log n + k log(n / k)) when they are spread out
+    pub fn containing(&self, point: &K) -> Overlaps<'_, K, V> {
+        Overlaps::new(&self.tree.root, point.clone(), Bound::Included(point.clone()))
+    }
+
+    /// Iterate over the intervals sharing at least a point with [start, end) in ascending order of their starts
+    /// This is one in-order walk skipping the subtrees whose greatest end is not after start and stopping at the first start not before end,
+    /// so only the subtrees holding a match are walked: O(log n + k) for k matches next to each other in the tree, O(log n + k log(n / k)) when they are spread out
+    pub fn overlapping(&self, range: Range<K>) -> Overlaps<'_, K, V> {
+        if range.start >= range.end {
+            return Overlaps::new(&None, range.start, Bound::Excluded(range.end));
+        }
+        Overlaps::new(&self.tree.root, range.start, Bound::Excluded(range.end))
+    }
+}
+
+/// Check the greatest end kept in every node against its children
+fn max_end_check<K: Ord + Clone, V>(node: &Node<Interval<K, V>, MaxEnd<K>>) -> bool {
+    let children = node.children.iter().flatten();
+    let expected: Option<&K> = children.clone().filter_map(|child| child.summary.0.as_ref()).chain(Some(&node.value.bounds.1)).max();
+    node.summary.0.as_ref() == expected && children.into_iter().all(|child| max_end_check(child))
+}
+
+impl<K: Ord + Clone, V> Default for IntervalTree<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Clone, V: Clone> Clone for IntervalTree<K, V> {
+    fn clone(&self) -> Self {
+        IntervalTree {
+            tree: self.tree.clone(),
+        }
+    }
+}
+
+impl<K: Ord + Clone + fmt::Debug, V: fmt::Debug> fmt::Debug for IntervalTree<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K: Ord + Clone, V> FromIterator<(Range<K>, V)> for IntervalTree<K, V> {
+    fn from_iter<I: IntoIterator<Item = (Range<K>, V)>>(iter: I) -> Self {
+        let mut intervals: IntervalTree<K, V> = IntervalTree::new();
+        intervals.extend(iter);
+        intervals
+    }
+}
+
+impl<K: Ord + Clone, V> Extend<(Range<K>, V)> for IntervalTree<K, V> {
+    fn extend<I: IntoIterator<Item = (Range<K>, V)>>(&mut self, iter: I) {
+        for (interval, value) in iter {
+            self.insert(interval, value);
+        }
+    }
+}
+
+/// An iterator over the intervals of an IntervalTree in ascending order of their starts (then of their ends)
+pub struct Iter<'a, K, V> {
+    inner: iter::Iter<'a, Interval<K, V>, MaxEnd<K>>,
+}
+
+impl<'a, K: Ord + Clone, V> Iterator for Iter<'a, K, V> {
+    type Item = (Range<&'a K>, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|interval: &'a Interval<K, V>| (&interval.bounds.0..&interval.bounds.1, &interval.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, K: Ord + Clone, V> DoubleEndedIterator for Iter<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|interval: &'a Interval<K, V>| (&interval.bounds.0..&interval.bounds.1, &interval.value))
+    }
+}
+
+impl<'a, K: Ord + Clone, V> ExactSizeIterator for Iter<'a, K, V> {}
+
+impl<'a, K: Ord + Clone, V> FusedIterator for Iter<'a, K, V> {}
+
+/// An iterator over the intervals of an IntervalTree ending after a point and starting before a bound,
+/// in ascending order of their starts
+pub struct Overlaps<'a, K, V> {
+    stack: Vec<&'a Node<Interval<K, V>, MaxEnd<K>>>,
+    /// The intervals must end after it
+    after: K,
+    /// The intervals must start before it
+    before: Bound<K>,
+}
+
+impl<'a, K: Ord + Clone, V> Overlaps<'a, K, V> {
+    fn new(root: &'a Tree<Interval<K, V>, MaxEnd<K>>, after: K, before: Bound<K>) -> Self {
+        let mut overlaps = Overlaps {
+            stack: Vec::new(),
+            after,
+            before,
+        };
+        overlaps.push_spine(root);
+        overlaps
+    }
+
+    /// Stack the node and its left children, stopping at the first subtree ending too early
+    fn push_spine(&mut self, mut tree: &'a Tree<Interval<K, V>, MaxEnd<K>>) {
+        while let Some(node) = tree.as_deref() {
+            if node.summary.0.as_ref().map_or(true, |end: &K| *end <= self.after) {
+                return;
+            }
+            self.stack.push(node);
+            tree = &node.children[Side::Left as usize];
+        }
+    }
+
+    fn starts_before(&self, start: &K) -> bool {
+        match &self.before {
+            Bound::Included(bound) => start <= bound,
+            Bound::Excluded(bound) => start < bound,
+            Bound::Unbounded => true,
+        }
+    }
+}
+
+impl<'a, K: Ord + Clone, V> Iterator for Overlaps<'a, K, V> {
+    type Item = (Range<&'a K>, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(node) = self.stack.pop() {
+            let interval: &'a Interval<K, V> = &node.value;
+            if !self.starts_before(&interval.bounds.0) {
+                // the intervals left are walked in order of their starts, none of them can start before the bound
+                self.stack.clear();
+                return None;
+            }
+            self.push_spine(&node.children[Side::Right as usize]);
+            // an empty interval overlaps nothing even when its start is inside the query
+            if interval.bounds.1 > self.after && interval.bounds.0 < interval.bounds.1 {
+                return Some((&interval.bounds.0..&interval.bounds.1, &interval.value));
+            }
+        }
+        None
+    }
+}
+
+impl<'a, K: Ord + Clone, V> FusedIterator for Overlaps<'a, K, V> {}
+
+#[cfg(test)]
+mod test_interval {
+    use super::*;
+
+    /// The intervals of the list overlapping [start, end), found by looking at all of them
+    fn naive(intervals: &[(Range<u32>, usize)], start: u32, end: u32) -> Vec<(Range<u32>, usize)> {
+        let mut found: Vec<(Range<u32>, usize)> = intervals.iter().filter(|(interval, _)| start < end && interval.start < end && interval.end > start && interval.start < interval.end).cloned().collect();
+        found.sort_by_key(|(interval, _)| (interval.start, interval.end));
+        found
+    }
+
+    fn owned<'a>(found: impl Iterator<Item = (Range<&'a u32>, &'a usize)>) -> Vec<(Range<u32>, usize)> {
+        found.map(|(interval, value)| (*interval.start..*interval.end, *value)).collect()
+    }
+
+    fn intervals(count: usize) -> Vec<(Range<u32>, usize)> {
+        let mut seed: u64 = 11;
+        (0..count).map(|index: usize| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            let start: u32 = ((seed >> 33) % 1000) as u32;
+            let length: u32 = ((seed >> 20) % 50) as u32;
+            (start..start + length, index)
+        }).collect()
+    }
+
+    #[test]
+    fn test_containing() {
+        let tree: IntervalTree<u32, &str> = vec![(9..17, "work"), (12..13, "lunch"), (17..19, "sport"), (8..9, "commute"), (17..18, "commute")].into_iter().collect();
+        assert_eq!(tree.containing(&12).map(|(_, value)| *value).collect::<Vec<&str>>(), vec!["work", "lunch"]);
+        assert_eq!(tree.containing(&17).map(|(_, value)| *value).collect::<Vec<&str>>(), vec!["commute", "sport"]);
+        assert_eq!(tree.containing(&9).map(|(interval, _)| *interval.start..*interval.end).collect::<Vec<Range<u32>>>(), vec![9..17]);
+        assert_eq!(tree.containing(&19).count(), 0);
+        assert_eq!(tree.containing(&0).count(), 0);
+        assert!(tree.is_correct());
+    }
+
+    #[test]
+    fn test_overlapping() {
+        let intervals: Vec<(Range<u32>, usize)> = intervals(500);
+        let tree: IntervalTree<u32, usize> = intervals.iter().cloned().collect();
+        // the same interval inserted twice keeps the last value
+        let unique: Vec<(Range<u32>, usize)> = intervals.iter().filter(|(interval, index)| tree.get(interval) == Some(index)).cloned().collect();
+        assert_eq!(tree.len(), unique.len());
+        assert!(tree.is_balanced());
+        assert!(tree.is_correct());
+        for start in (0..1100).step_by(37) {
+            for length in [0, 1, 5, 60, 400].iter() {
+                assert_eq!(owned(tree.overlapping(start..start + length)), naive(&unique, start, start + length));
+            }
+            assert_eq!(owned(tree.containing(&start)), naive(&unique, start, start + 1));
+        }
+    }
+
+    #[test]
+    fn test_insert_remove() {
+        let mut tree: IntervalTree<u32, usize> = IntervalTree::new();
+        let mut intervals: Vec<(Range<u32>, usize)> = intervals(300);
+        intervals.sort_by_key(|(interval, _)| (interval.start, interval.end));
+        intervals.dedup_by_key(|(interval, _)| (interval.start, interval.end));
+        for (interval, index) in intervals.iter() {
+            assert_eq!(tree.insert(interval.clone(), *index), None);
+        }
+        assert_eq!(tree.insert(intervals[3].0.clone(), 1000), Some(intervals[3].1));
+        assert_eq!(tree.insert(intervals[3].0.clone(), intervals[3].1), Some(1000));
+        let (removed, kept): (Vec<_>, Vec<_>) = intervals.into_iter().partition(|(_, index)| index % 3 == 1);
+        for (interval, index) in removed.iter() {
+            assert_eq!(tree.remove(interval), Some(*index));
+            assert_eq!(tree.remove(interval), None);
+            assert!(tree.is_correct());
+            assert!(tree.is_balanced());
+        }
+        assert_eq!(tree.len(), kept.len());
+        assert_eq!(owned(tree.iter()), kept);
+        for point in (0..1100).step_by(13) {
+            assert_eq!(owned(tree.containing(&point)), naive(&kept, point, point + 1));
+            assert_eq!(owned(tree.overlapping(point..point + 100)), naive(&kept, point, point + 100));
+        }
+    }
+
+    #[test]
+    fn test_same_start() {
+        let mut tree: IntervalTree<u32, char> = IntervalTree::new();
+        tree.insert(5..10, 'a');
+        tree.insert(5..6, 'b');
+        tree.insert(5..20, 'c');
+        tree.insert(7..7, 'd');
+        assert_eq!(tree.containing(&8).map(|(_, value)| *value).collect::<String>(), "ac");
+        assert_eq!(tree.containing(&7).map(|(_, value)| *value).collect::<String>(), "ac");
+        assert_eq!(tree.overlapping(5..6).map(|(_, value)| *value).collect::<String>(), "bac");
+        assert_eq!(tree.overlapping(6..6).count(), 0);
+        assert_eq!(tree.remove(&(5..10)), Some('a'));
+        assert_eq!(tree.get(&(5..20)), Some(&'c'));
+        assert_eq!(format!("{:?}", tree), "{5..6: 'b', 5..20: 'c', 7..7: 'd'}");
+    }
+}
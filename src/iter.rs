@@ -0,0 +1,576 @@
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::iter::FusedIterator;
+use std::mem::{replace, take};
+use std::ops::{Bound, RangeBounds};
+use std::ptr;
+use std::vec;
+
+use crate::balance::Storage;
+use crate::storage::Backend;
+use crate::{AvlTree, Boxes, Comparator, Node, Side, Summary};
+
+/// One end of an in-order walk, it keeps the spine of nodes still to visit
+/// and the values of the node currently being yielded (value first, then its duplicates)
+struct Edge<'a, T, S, B: Storage> {
+    storage: &'a B,
+    side: Side,
+    stack: Vec<&'a Node<T, S, B>>,
+    bucket: Vec<&'a T>,
+    current: Option<&'a Node<T, S, B>>,
+}
+
+impl<'a, T, S: Summary<T>, B: Backend<T, S>> Clone for Edge<'a, T, S, B> {
+    fn clone(&self) -> Self {
+        Edge {
+            storage: self.storage,
+            side: self.side,
+            stack: self.stack.clone(),
+            bucket: self.bucket.clone(),
+            current: self.current,
+        }
+    }
+}
+
+impl<'a, T, S: Summary<T>, B: Backend<T, S>> Edge<'a, T, S, B> {
+    fn new(storage: &'a B, root: &'a B::Link, side: Side) -> Self {
+        let mut edge = Edge {
+            storage,
+            side,
+            stack: Vec::new(),
+            bucket: Vec::new(),
+            current: None,
+        };
+        edge.push_spine(storage.root(root));
+        edge
+    }
+
+    /// Same as new but the walk starts at the first node (from our side) for which within is true,
+    /// within must be monotonic along the tree order (true on our side of the bound, false on the other)
+    fn seek<F: Fn(&T) -> bool>(storage: &'a B, root: &'a B::Link, side: Side, within: F) -> Self {
+        let mut edge = Edge {
+            storage,
+            side,
+            stack: Vec::new(),
+            bucket: Vec::new(),
+            current: None,
+        };
+        let mut tree: Option<&'a Node<T, S, B>> = storage.root(root);
+        while let Some(node) = tree {
+            if within(&node.value) {
+                edge.stack.push(node);
+                tree = node.child(storage, side);
+            } else {
+                tree = node.child(storage, !side);
+            }
+        }
+        edge
+    }
+
+    /// Push the node and all its children toward our side (left for the front, right for the back)
+    fn push_spine(&mut self, mut tree: Option<&'a Node<T, S, B>>) {
+        while let Some(node) = tree {
+            self.stack.push(node);
+            tree = node.child(self.storage, self.side);
+        }
+    }
+
+    /// Move to the next node and load its values in the bucket, return false when the walk is over
+    fn advance(&mut self) -> bool {
+        let node: &'a Node<T, S, B> = match self.stack.pop() {
+            None => return false,
+            Some(node) => node,
+        };
+        self.bucket.clear();
+        self.bucket.push(&node.value);
+        self.bucket.extend(node.duplicates.iter());
+        if self.side == Side::Left {
+            // the bucket is consumed from its end so the front needs it reversed
+            self.bucket.reverse();
+        }
+        self.push_spine(node.child(self.storage, !self.side));
+        self.current = Some(node);
+        true
+    }
+
+    fn next(&mut self) -> Option<&'a T> {
+        loop {
+            if let Some(value) = self.bucket.pop() {
+                return Some(value);
+            }
+            if !self.advance() {
+                return None;
+            }
+        }
+    }
+
+    /// Step this edge toward the other one, nothing is yielded once they have met
+    fn next_until(&mut self, other: &Edge<'a, T, S, B>) -> Option<&'a T> {
+        loop {
+            let node: &'a Node<T, S, B> = self.current?;
+            if ptr::eq(node, other.current?) {
+                // both ends are in the same bucket, only yield what the other end did not take yet
+                let taken: usize = 1 + node.duplicates.len() - other.bucket.len();
+                return if self.bucket.len() > taken { self.bucket.pop() } else { None };
+            }
+            if let Some(value) = self.bucket.pop() {
+                return Some(value);
+            }
+            if !self.advance() {
+                return None;
+            }
+        }
+    }
+}
+
+/// An iterator over the values of an AvlTree in ascending order (by Ord),
+/// values sharing the same key are yielded together, starting by the one stored in the node
+pub struct Iter<'a, T, S = (), B: Storage = Boxes<T, S>> {
+    front: Edge<'a, T, S, B>,
+    back: Edge<'a, T, S, B>,
+    remaining: usize,
+}
+
+impl<'a, T, S: Summary<T>, B: Backend<T, S>> Iter<'a, T, S, B> {
+    pub(crate) fn new<C>(tree: &'a AvlTree<T, C, S, B>) -> Self {
+        Self::from_root(&tree.storage, &tree.root, tree.len())
+    }
+
+    /// Walk any tree of nodes, len must be the number of values it holds
+    pub(crate) fn from_root(storage: &'a B, root: &'a B::Link, len: usize) -> Self {
+        Iter {
+            front: Edge::new(storage, root, Side::Left),
+            back: Edge::new(storage, root, Side::Right),
+            remaining: len,
+        }
+    }
+}
+
+impl<'a, T, S: Summary<T>, B: Backend<T, S>> Clone for Iter<'a, T, S, B> {
+    fn clone(&self) -> Self {
+        Iter {
+            front: self.front.clone(),
+            back: self.back.clone(),
+            remaining: self.remaining,
+        }
+    }
+}
+
+impl<'a, T, S: Summary<T>, B: Backend<T, S>> Iterator for Iter<'a, T, S, B> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        self.front.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T, S: Summary<T>, B: Backend<T, S>> DoubleEndedIterator for Iter<'a, T, S, B> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        self.back.next()
+    }
+}
+
+impl<'a, T, S: Summary<T>, B: Backend<T, S>> ExactSizeIterator for Iter<'a, T, S, B> {}
+
+impl<'a, T, S: Summary<T>, B: Backend<T, S>> FusedIterator for Iter<'a, T, S, B> {}
+
+impl<'a, T, C, S: Summary<T>, B: Backend<T, S>> IntoIterator for &'a AvlTree<T, C, S, B> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, S, B>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator over a sub-range of the values of an AvlTree in ascending order (by Ord),
+/// values sharing the same key are yielded together like in Iter
+pub struct Range<'a, T, S = (), B: Storage = Boxes<T, S>> {
+    front: Edge<'a, T, S, B>,
+    back: Edge<'a, T, S, B>,
+}
+
+impl<'a, T, S: Summary<T>, B: Backend<T, S>> Range<'a, T, S, B> {
+    pub(crate) fn new<Q: ?Sized, C: Comparator<Q>, R: RangeBounds<Q>>(tree: &'a AvlTree<T, C, S, B>, range: R) -> Self where T: Borrow<Q> {
+        let cmp: &C = &tree.cmp;
+        let mut front: Edge<'a, T, S, B> = Edge::seek(&tree.storage, &tree.root, Side::Left, |value: &T| match range.start_bound() {
+            Bound::Included(start) => cmp.compare(value.borrow(), start) != Ordering::Less,
+            Bound::Excluded(start) => cmp.compare(value.borrow(), start) == Ordering::Greater,
+            Bound::Unbounded => true,
+        });
+        let mut back: Edge<'a, T, S, B> = Edge::seek(&tree.storage, &tree.root, Side::Right, |value: &T| match range.end_bound() {
+            Bound::Included(end) => cmp.compare(value.borrow(), end) != Ordering::Greater,
+            Bound::Excluded(end) => cmp.compare(value.borrow(), end) == Ordering::Less,
+            Bound::Unbounded => true,
+        });
+        let is_empty: bool = match (front.stack.last(), back.stack.last()) {
+            (Some(first), Some(last)) => cmp.compare(first.value.borrow(), last.value.borrow()) == Ordering::Greater,
+            _ => true,
+        };
+        if !is_empty {
+            front.advance();
+            back.advance();
+        }
+        Range { front, back }
+    }
+}
+
+impl<'a, T, S: Summary<T>, B: Backend<T, S>> Clone for Range<'a, T, S, B> {
+    fn clone(&self) -> Self {
+        Range {
+            front: self.front.clone(),
+            back: self.back.clone(),
+        }
+    }
+}
+
+impl<'a, T, S: Summary<T>, B: Backend<T, S>> Iterator for Range<'a, T, S, B> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.front.next_until(&self.back)
+    }
+}
+
+impl<'a, T, S: Summary<T>, B: Backend<T, S>> DoubleEndedIterator for Range<'a, T, S, B> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.back.next_until(&self.front)
+    }
+}
+
+impl<'a, T, S: Summary<T>, B: Backend<T, S>> FusedIterator for Range<'a, T, S, B> {}
+
+/// The nodes taken out of the storage in order, shared by IntoIter (owning the storage) and Drain (borrowing it)
+/// The nodes are freed as soon as all their values have been yielded
+struct Walk<T, S, B: Storage> {
+    stack: Vec<Node<T, S, B>>,
+    bucket: vec::IntoIter<T>,
+    remaining: usize,
+}
+
+impl<T, S, B: Backend<T, S>> Walk<T, S, B> {
+    /// Walk the nodes of the tree, taking them out of the storage
+    fn new(storage: &mut B, root: B::Link) -> Self {
+        let mut walk = Walk {
+            stack: Vec::new(),
+            bucket: Vec::new().into_iter(),
+            remaining: storage.root(&root).map_or(0, |node: &Node<T, S, B>| node.size),
+        };
+        walk.push_spine(storage, root);
+        walk
+    }
+
+    /// Detach the left children one by one and stack them, the left most node ends on top
+    fn push_spine(&mut self, storage: &mut B, mut tree: B::Link) {
+        while let Some(mut node) = storage.free(&mut tree) {
+            tree = replace(&mut node.children[Side::Left as usize], B::empty());
+            self.stack.push(node);
+        }
+    }
+
+    fn next(&mut self, storage: &mut B) -> Option<T> {
+        if let Some(value) = self.bucket.next() {
+            self.remaining -= 1;
+            return Some(value);
+        }
+        let mut node: Node<T, S, B> = self.stack.pop()?;
+        self.push_spine(storage, replace(&mut node.children[Side::Right as usize], B::empty()));
+        self.bucket = take(&mut node.duplicates).into_iter();
+        self.remaining -= 1;
+        Some(node.value)
+    }
+
+    /// Free the nodes not walked yet, the right subtrees of the stacked nodes are still in the storage
+    fn delete(&mut self, storage: &mut B) {
+        for mut node in self.stack.drain(..) {
+            Node::delete(storage, &mut node.children[Side::Right as usize]);
+        }
+    }
+}
+
+/// An owning iterator over the values of an AvlTree in ascending order (by Ord),
+/// the nodes are freed as soon as all their values have been yielded
+pub struct IntoIter<T, S = (), B: Storage = Boxes<T, S>> {
+    storage: B,
+    walk: Walk<T, S, B>,
+}
+
+impl<T, S: Summary<T>, B: Backend<T, S>> IntoIter<T, S, B> {
+    /// Walk the nodes of the tree, taking them over with their storage
+    pub(crate) fn new(mut storage: B, root: B::Link) -> Self {
+        let walk: Walk<T, S, B> = Walk::new(&mut storage, root);
+        IntoIter { storage, walk }
+    }
+}
+
+impl<T, S: Summary<T>, B: Backend<T, S>> Iterator for IntoIter<T, S, B> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.walk.next(&mut self.storage)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.walk.remaining, Some(self.walk.remaining))
+    }
+}
+
+impl<T, S: Summary<T>, B: Backend<T, S>> ExactSizeIterator for IntoIter<T, S, B> {}
+
+impl<T, S: Summary<T>, B: Backend<T, S>> FusedIterator for IntoIter<T, S, B> {}
+
+impl<T, C, S: Summary<T>, B: Backend<T, S>> IntoIterator for AvlTree<T, C, S, B> {
+    type Item = T;
+    type IntoIter = IntoIter<T, S, B>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter::new(self.storage, self.root)
+    }
+}
+
+/// A draining iterator over the values of an AvlTree in ascending order (by Ord),
+/// the tree is emptied right away and the values not consumed are dropped with the iterator
+pub struct Drain<'a, T, S = (), B: Backend<T, S> = Boxes<T, S>> {
+    storage: &'a mut B,
+    walk: Walk<T, S, B>,
+}
+
+impl<'a, T, S: Summary<T>, B: Backend<T, S>> Drain<'a, T, S, B> {
+    pub(crate) fn new<C>(tree: &'a mut AvlTree<T, C, S, B>) -> Self {
+        let root: B::Link = replace(&mut tree.root, B::empty());
+        Drain {
+            walk: Walk::new(&mut tree.storage, root),
+            storage: &mut tree.storage,
+        }
+    }
+}
+
+impl<'a, T, S: Summary<T>, B: Backend<T, S>> Iterator for Drain<'a, T, S, B> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.walk.next(self.storage)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.walk.remaining, Some(self.walk.remaining))
+    }
+}
+
+impl<'a, T, S: Summary<T>, B: Backend<T, S>> ExactSizeIterator for Drain<'a, T, S, B> {}
+
+impl<'a, T, S: Summary<T>, B: Backend<T, S>> FusedIterator for Drain<'a, T, S, B> {}
+
+impl<'a, T, S, B: Backend<T, S>> Drop for Drain<'a, T, S, B> {
+    fn drop(&mut self) {
+        self.walk.delete(self.storage);
+    }
+}
+
+#[cfg(test)]
+mod test_iter {
+    use crate::fixture::{positions, Position};
+    use crate::AvlTree;
+    use std::ops::Bound;
+
+    const TEST_VALUE: u64 = 42;
+
+    #[test]
+    fn test_iter_empty() {
+        let tree: AvlTree<u64> = AvlTree::new();
+        let mut iter = tree.iter();
+        assert_eq!(iter.size_hint(), (0, Some(0)));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
+    }
+
+    #[test]
+    fn test_iter_in_order() {
+        let mut tree: AvlTree<u64> = AvlTree::new();
+        for value in [42, 420, 66, 88, 99, 1, 7, 1000, 3].iter() {
+            tree.insert(*value).expect("Failed insert");
+        }
+        let values: Vec<u64> = tree.iter().cloned().collect();
+        assert_eq!(values, vec![1, 3, 7, 42, 66, 88, 99, 420, 1000]);
+        let values: Vec<u64> = tree.iter().rev().cloned().collect();
+        assert_eq!(values, vec![1000, 420, 99, 88, 66, 42, 7, 3, 1]);
+        let mut count = 0;
+        for value in &tree {
+            assert!(tree.contains(value));
+            count += 1;
+        }
+        assert_eq!(count, tree.count());
+    }
+
+    #[test]
+    fn test_iter_double_ended() {
+        let mut tree: AvlTree<u64> = AvlTree::new();
+        for value in 1..=15 {
+            tree.insert(value).expect("Failed insert");
+        }
+        let mut iter = tree.iter();
+        assert_eq!(iter.len(), 15);
+        assert_eq!(iter.next(), Some(&1));
+        assert_eq!(iter.next_back(), Some(&15));
+        assert_eq!(iter.next_back(), Some(&14));
+        assert_eq!(iter.len(), 12);
+        let middle: Vec<u64> = iter.by_ref().take(6).cloned().collect();
+        assert_eq!(middle, vec![2, 3, 4, 5, 6, 7]);
+        let rest: Vec<u64> = iter.by_ref().rev().cloned().collect();
+        assert_eq!(rest, vec![13, 12, 11, 10, 9, 8]);
+        assert_eq!(iter.size_hint(), (0, Some(0)));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
+    }
+
+    #[test]
+    fn test_iter_duplicates() {
+        let tree: AvlTree<Position> = positions(&[(1, 0), (5, 1), (5, 2), (5, 3), (9, 0)]);
+        let iter = tree.iter();
+        assert_eq!(iter.len(), 5);
+        let forward: Vec<&Position> = tree.iter().collect();
+        let keys: Vec<i32> = forward.iter().map(|position| position.x).collect();
+        assert_eq!(keys, vec![1, 5, 5, 5, 9]);
+        let mut backward: Vec<&Position> = tree.iter().rev().collect();
+        backward.reverse();
+        assert_eq!(forward, backward);
+        // both ends meeting inside the same bucket
+        let mut iter = tree.iter();
+        iter.next();
+        iter.next_back();
+        let mut middle: Vec<i32> = vec![iter.next().unwrap().y, iter.next_back().unwrap().y, iter.next().unwrap().y];
+        assert_eq!(iter.next(), None);
+        middle.sort();
+        assert_eq!(middle, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_into_iter() {
+        let mut tree: AvlTree<String> = AvlTree::new();
+        for value in ["delta", "alpha", "echo", "charlie", "bravo"].iter() {
+            tree.insert(value.to_string()).expect("Failed insert");
+        }
+        let mut iter = tree.into_iter();
+        assert_eq!(iter.len(), 5);
+        assert_eq!(iter.next(), Some("alpha".to_string()));
+        assert_eq!(iter.len(), 4);
+        let rest: Vec<String> = iter.collect();
+        assert_eq!(rest, vec!["bravo", "charlie", "delta", "echo"]);
+    }
+
+    #[test]
+    fn test_into_iter_duplicates() {
+        let tree: AvlTree<Position> = positions(&[(9, 0), (5, 1), (5, 2), (1, 0), (5, 3)]);
+        let borrowed: Vec<Position> = tree.iter().cloned().collect();
+        let owned: Vec<Position> = tree.into_iter().collect();
+        assert_eq!(owned, borrowed);
+        let keys: Vec<i32> = owned.iter().map(|position| position.x).collect();
+        assert_eq!(keys, vec![1, 5, 5, 5, 9]);
+    }
+
+    #[test]
+    fn test_drain() {
+        let mut tree: AvlTree<u64> = AvlTree::new();
+        for value in 1..=20 {
+            tree.insert(value).expect("Failed insert");
+        }
+        let drained: Vec<u64> = tree.drain().take(5).collect();
+        assert_eq!(drained, vec![1, 2, 3, 4, 5]);
+        assert!(tree.is_empty());
+        assert_eq!(tree.count(), 0);
+        tree.insert(TEST_VALUE).expect("Failed insert");
+        assert_eq!(tree.drain().len(), 1);
+        assert!(tree.is_empty());
+    }
+
+    #[test]
+    fn test_range() {
+        let mut tree: AvlTree<u64> = AvlTree::new();
+        for value in (0..50).map(|value| value * 2) {
+            tree.insert(value).expect("Failed insert");
+        }
+        let values: Vec<u64> = tree.range(10..20).cloned().collect();
+        assert_eq!(values, vec![10, 12, 14, 16, 18]);
+        let values: Vec<u64> = tree.range(10..=20).cloned().collect();
+        assert_eq!(values, vec![10, 12, 14, 16, 18, 20]);
+        let values: Vec<u64> = tree.range(11..19).cloned().collect();
+        assert_eq!(values, vec![12, 14, 16, 18]);
+        let values: Vec<u64> = tree.range((Bound::Excluded(10), Bound::Excluded(20))).cloned().collect();
+        assert_eq!(values, vec![12, 14, 16, 18]);
+        let values: Vec<u64> = tree.range(..5).cloned().collect();
+        assert_eq!(values, vec![0, 2, 4]);
+        let values: Vec<u64> = tree.range(93..).cloned().collect();
+        assert_eq!(values, vec![94, 96, 98]);
+        assert_eq!(tree.range(..).count(), 50);
+        assert_eq!(tree.range(..).rev().cloned().collect::<Vec<u64>>(), tree.iter().rev().cloned().collect::<Vec<u64>>());
+    }
+
+    #[test]
+    fn test_range_empty() {
+        let tree: AvlTree<u64> = AvlTree::new();
+        assert_eq!(tree.range(..).next(), None);
+        let mut tree: AvlTree<u64> = AvlTree::new();
+        for value in (0..50).map(|value| value * 2) {
+            tree.insert(value).expect("Failed insert");
+        }
+        assert_eq!(tree.range(11..12).next(), None);
+        assert_eq!(tree.range(10..10).next(), None);
+        assert_eq!(tree.range((Bound::Excluded(10), Bound::Excluded(12))).next(), None);
+        assert_eq!(tree.range((Bound::Included(30), Bound::Excluded(10))).next(), None);
+        assert_eq!(tree.range(100..).next_back(), None);
+        assert_eq!(tree.range(..0).next_back(), None);
+        assert_eq!(tree.range(10..=10).cloned().collect::<Vec<u64>>(), vec![10]);
+    }
+
+    #[test]
+    fn test_range_double_ended() {
+        let mut tree: AvlTree<u64> = AvlTree::new();
+        for value in 1..=30 {
+            tree.insert(value).expect("Failed insert");
+        }
+        let mut range = tree.range(5..15);
+        assert_eq!(range.next(), Some(&5));
+        assert_eq!(range.next_back(), Some(&14));
+        assert_eq!(range.next_back(), Some(&13));
+        assert_eq!(range.next(), Some(&6));
+        let rest: Vec<u64> = range.by_ref().cloned().collect();
+        assert_eq!(rest, vec![7, 8, 9, 10, 11, 12]);
+        assert_eq!(range.next(), None);
+        assert_eq!(range.next_back(), None);
+    }
+
+    #[test]
+    fn test_range_duplicates() {
+        let mut tree: AvlTree<Position> = AvlTree::new();
+        for x in 0..10 {
+            tree.insert(Position { x, y: 0 }).expect("Failed insert");
+        }
+        for y in 1..4 {
+            tree.insert(Position { x: 5, y }).expect("Failed insert");
+        }
+        let start: Position = Position { x: 5, y: -1 };
+        let keys: Vec<(i32, i32)> = tree.range::<Position, _>(&start..).map(|position| (position.x, position.y)).take(4).collect();
+        assert_eq!(keys.len(), 4);
+        assert!(keys.iter().all(|&(x, _)| x == 5));
+        let count: usize = tree.range(start.clone()..=start.clone()).count();
+        assert_eq!(count, 4);
+        let mut range = tree.range(start.clone()..=start);
+        let mut seen: Vec<i32> = vec![range.next().unwrap().y, range.next_back().unwrap().y, range.next_back().unwrap().y, range.next().unwrap().y];
+        assert_eq!(range.next(), None);
+        assert_eq!(range.next_back(), None);
+        seen.sort();
+        assert_eq!(seen, vec![0, 1, 2, 3]);
+    }
+}
@@ -0,0 +1,400 @@
+use std::borrow::Borrow;
+use std::iter::FromIterator;
+use std::ops::RangeBounds;
+
+use crate::storage::transfer;
+use crate::summary::Deferred;
+use crate::{AvlError, AvlTree, Boxes, Comparator, Drain, DuplicatePolicy, Entry, IntoIter, Lazy, Natural, Node, Summary, Update};
+
+/// The nodes of a LazyAvlTree, the updates pending in their summaries are handed down as they are reached
+type LazyBoxes<T, S, U> = Boxes<T, Lazy<S, U>, Deferred>;
+
+/// An AvlTree summarized by S whose values can be changed by range at once with an Update U (adding a delta to a price...)
+/// The update of a whole subtree is kept in its root and handed down to the values later, so the values are only
+/// read through &mut self (the updates of the path are handed down on the way) or through view (which hands all of them down)
+pub struct LazyAvlTree<T, S, U, C = Natural> {
+    tree: AvlTree<T, C, Lazy<S, U>, LazyBoxes<T, S, U>>,
+}
+
+impl<T, S: Summary<T>, U: Update<T, S>> LazyAvlTree<T, S, U> {
+    /// Create a new empty tree ordered by Ord
+    pub fn new() -> Self {
+        LazyAvlTree {
+            tree: AvlTree::with_storage(Boxes::default(), Natural, DuplicatePolicy::default()),
+        }
+    }
+
+    /// Create a new empty tree ordered by Ord which handles the duplicate keys as the policy says
+    pub fn with_policy(policy: DuplicatePolicy) -> Self {
+        LazyAvlTree {
+            tree: AvlTree::with_storage(Boxes::default(), Natural, policy),
+        }
+    }
+}
+
+impl<T, S: Summary<T>, U: Update<T, S>, C> LazyAvlTree<T, S, U, C> {
+    /// Create a new empty tree ordered by the comparator which handles the duplicate keys as the policy says
+    pub fn with_comparator_and_policy(cmp: C, policy: DuplicatePolicy) -> Self {
+        LazyAvlTree {
+            tree: AvlTree::with_storage(Boxes::default(), cmp, policy),
+        }
+    }
+
+    /// Get the number of values in constant time
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    /// Check if the tree is empty or not
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Return the height of the tree
+    pub fn height(&self) -> usize {
+        self.tree.height()
+    }
+
+    /// Remove all the values, the tree can be reused afterwards
+    pub fn clear(&mut self) {
+        self.tree.clear();
+    }
+
+    /// Check if the tree is balanced (this is quite intensive but gave correct result)
+    pub fn is_balanced(&self) -> bool {
+        self.tree.is_balanced()
+    }
+
+    /// Check if the heights and the sizes are correct
+    pub fn is_correct(&self) -> bool {
+        self.tree.is_correct()
+    }
+
+    /// Hand every pending update down to the values in O(n), then give the tree to read them (iter, range, get, dump...)
+    pub fn view(&mut self) -> &AvlTree<T, C, Lazy<S, U>, LazyBoxes<T, S, U>> {
+        Node::push_all(&mut self.tree.storage, &mut self.tree.root);
+        &self.tree
+    }
+
+    /// Hand every pending update down to the values in O(n) and give back the tree
+    pub fn into_tree(mut self) -> AvlTree<T, C, Lazy<S, U>> {
+        let mut tree: AvlTree<T, C, Lazy<S, U>> = AvlTree::with_storage(Boxes::default(), self.tree.cmp, self.tree.policy);
+        tree.root = transfer(&mut self.tree.storage, &mut tree.storage, self.tree.root.take());
+        tree
+    }
+
+    /// Take all the values out of the tree in ascending order, they get their updates as they are taken
+    pub fn drain(&mut self) -> Drain<'_, T, Lazy<S, U>, LazyBoxes<T, S, U>> {
+        self.tree.drain()
+    }
+}
+
+impl<T: Eq, S: Summary<T>, U: Update<T, S>, C: Comparator<T>> LazyAvlTree<T, S, U, C> {
+    /// Insert a value in the tree as AvlTree::insert does
+    pub fn insert(&mut self, value: T) -> Result<&mut Self, AvlError> {
+        self.tree.insert(value)?;
+        Ok(&mut *self)
+    }
+
+    /// Remove a value (matched with Eq once its updates are applied) as AvlTree::remove does
+    pub fn remove(&mut self, value: &T) -> Result<&mut Self, AvlError> {
+        self.tree.remove(value)?;
+        Ok(&mut *self)
+    }
+
+    /// Get the entry of the key of the value, its values are updated when the entry is made, see AvlTree::entry
+    pub fn entry(&mut self, value: T) -> Entry<'_, T, C, Lazy<S, U>, LazyBoxes<T, S, U>> {
+        self.tree.entry(value)
+    }
+
+    /// Remove the value matching the key (by Ord only) and return it updated, see AvlTree::take
+    pub fn take<Q: ?Sized>(&mut self, key: &Q) -> Option<T> where T: Borrow<Q>, C: Comparator<Q> {
+        self.tree.take(key)
+    }
+
+    /// Get a reference to the value matching the key (by Ord only), the updates of its path are handed down first
+    pub fn get_ref<Q: ?Sized>(&mut self, key: &Q) -> Option<&T> where T: Borrow<Q>, C: Comparator<Q> {
+        Node::get_mut(&mut self.tree.storage, &mut self.tree.root, key, &self.tree.cmp).map(|node: &mut Node<T, Lazy<S, U>, LazyBoxes<T, S, U>>| &node.value)
+    }
+
+    /// Check if the key is in the tree, the updates do not change the keys so nothing is handed down
+    pub fn contains<Q: ?Sized>(&self, key: &Q) -> bool where T: Borrow<Q>, C: Comparator<Q> {
+        self.tree.contains(key)
+    }
+
+    /// Apply the update to every value inside the range in O(log n), the subtrees fully inside only get it in their root
+    /// summary and aggregate see it right away, the values get it when they are read or when a change of the tree goes through their node
+    pub fn update_range<Q: ?Sized, R: RangeBounds<Q>>(&mut self, range: R, update: U) where T: Borrow<Q>, C: Comparator<Q> {
+        Node::update_range(&mut self.tree.storage, &mut self.tree.root, range.start_bound(), range.end_bound(), &update, &self.tree.cmp);
+    }
+
+    /// Get the summary of all the values of the tree, updates included, in O(1)
+    pub fn summary(&self) -> S {
+        self.tree.summary().into_summary()
+    }
+
+    /// Get the summary of the values inside the range, updates included, in O(log n), see AvlTree::aggregate
+    pub fn aggregate<Q: ?Sized, R: RangeBounds<Q>>(&self, range: R) -> S where T: Borrow<Q>, C: Comparator<Q> {
+        self.tree.aggregate(range).into_summary()
+    }
+
+    /// Move the values with a key greater or equal to the given one in a new tree in O(log n), see AvlTree::split_off
+    pub fn split_off<Q: ?Sized>(&mut self, key: &Q) -> Self where T: Borrow<Q>, C: Comparator<Q> + Clone {
+        LazyAvlTree {
+            tree: self.tree.split_off(key),
+        }
+    }
+}
+
+impl<T, S: Summary<T>, U: Update<T, S>> Default for LazyAvlTree<T, S, U> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone, S: Clone, U: Clone, C: Clone> Clone for LazyAvlTree<T, S, U, C> {
+    fn clone(&self) -> Self {
+        LazyAvlTree {
+            tree: self.tree.clone(),
+        }
+    }
+}
+
+/// The values are sorted then the tree is built in linear time, see AvlTree
+impl<T: Eq, S: Summary<T>, U: Update<T, S>, C: Comparator<T> + Default> FromIterator<T> for LazyAvlTree<T, S, U, C> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        LazyAvlTree {
+            tree: iter.into_iter().collect(),
+        }
+    }
+}
+
+/// The values are yielded in ascending order with their updates applied
+impl<T, S: Summary<T>, U: Update<T, S>, C> IntoIterator for LazyAvlTree<T, S, U, C> {
+    type Item = T;
+    type IntoIter = IntoIter<T, Lazy<S, U>, LazyBoxes<T, S, U>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.tree.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod test_lazy {
+    use super::*;
+    use std::cell::Cell;
+    use std::cmp::Ordering;
+    use std::collections::BTreeMap;
+
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    struct Price {
+        id: u64,
+        cents: i64,
+    }
+
+    impl Ord for Price {
+        fn cmp(&self, other: &Self) -> Ordering {
+            self.id.cmp(&other.id)
+        }
+    }
+
+    impl PartialOrd for Price {
+        fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl Borrow<u64> for Price {
+        fn borrow(&self) -> &u64 {
+            &self.id
+        }
+    }
+
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    struct Stats {
+        count: i64,
+        total: i64,
+        max: Option<i64>,
+    }
+
+    impl Summary<Price> for Stats {
+        fn identity() -> Self {
+            Stats { count: 0, total: 0, max: None }
+        }
+
+        fn from_value(value: &Price) -> Self {
+            Stats {
+                count: 1,
+                total: value.cents,
+                max: Some(value.cents),
+            }
+        }
+
+        fn combine(&self, other: &Self) -> Self {
+            Stats {
+                count: self.count + other.count,
+                total: self.total + other.total,
+                max: self.max.into_iter().chain(other.max).max(),
+            }
+        }
+    }
+
+    thread_local! {
+        static APPLIED: Cell<usize> = Cell::new(0);
+    }
+
+    /// cents * factor + delta, the factor is never negative so the max stays the max
+    #[derive(Clone, Debug, PartialEq)]
+    struct Affine {
+        factor: i64,
+        delta: i64,
+    }
+
+    impl Update<Price, Stats> for Affine {
+        fn apply(&self, value: &mut Price) {
+            APPLIED.with(|applied: &Cell<usize>| applied.set(applied.get() + 1));
+            value.cents = value.cents * self.factor + self.delta;
+        }
+
+        fn apply_summary(&self, summary: &Stats) -> Stats {
+            Stats {
+                count: summary.count,
+                total: summary.total * self.factor + summary.count * self.delta,
+                max: summary.max.map(|max: i64| max * self.factor + self.delta),
+            }
+        }
+
+        fn then(&self, next: &Self) -> Self {
+            Affine {
+                factor: self.factor * next.factor,
+                delta: self.delta * next.factor + next.delta,
+            }
+        }
+    }
+
+    type Prices = LazyAvlTree<Price, Stats, Affine>;
+
+    fn add(delta: i64) -> Affine {
+        Affine { factor: 1, delta }
+    }
+
+    fn check(tree: &Prices, model: &BTreeMap<u64, i64>) {
+        assert_eq!(tree.len(), model.len());
+        for (start, end) in [(0, 200), (10, 20), (37, 150), (90, 91), (150, 120)].iter() {
+            let expected: Vec<i64> = model.range(*start..*end.max(start)).map(|(_, cents)| *cents).collect();
+            let stats: Stats = tree.aggregate(*start..*end.max(start));
+            assert_eq!(stats.count, expected.len() as i64);
+            assert_eq!(stats.total, expected.iter().sum::<i64>());
+            assert_eq!(stats.max, expected.iter().max().cloned());
+        }
+    }
+
+    #[test]
+    fn test_update_range() {
+        let mut tree: Prices = (0..100).map(|id: u64| Price { id, cents: 100 }).collect();
+        tree.update_range(10..20, add(5));
+        tree.update_range(15.., add(-1));
+        tree.update_range(..=12, Affine { factor: 2, delta: 0 });
+        assert_eq!(tree.aggregate(0..10).total, 2000);
+        assert_eq!(tree.aggregate(10..13).total, 3 * 210);
+        assert_eq!(tree.aggregate(13..15).total, 2 * 105);
+        assert_eq!(tree.aggregate(15..20).total, 5 * 104);
+        assert_eq!(tree.aggregate(20..).total, 80 * 99);
+        assert_eq!(tree.summary().max, Some(210));
+        let expected: Vec<i64> = (0..100).map(|id: i64| match id {
+            0..=9 => 200,
+            10..=12 => 210,
+            13..=14 => 105,
+            15..=19 => 104,
+            _ => 99,
+        }).collect();
+        assert!(tree.view().iter().map(|price: &Price| price.cents).eq(expected));
+        assert!(tree.is_correct());
+    }
+
+    #[test]
+    fn test_logarithmic() {
+        let mut tree: Prices = (0..100_000).map(|id: u64| Price { id, cents: 0 }).collect();
+        APPLIED.with(|applied: &Cell<usize>| applied.set(0));
+        tree.update_range(12_345..87_654, add(1));
+        tree.update_range(..50_000, add(1));
+        // only the values of the nodes on the two edges of the range are changed right away
+        assert!(APPLIED.with(Cell::get) <= 4 * tree.height());
+        assert_eq!(tree.summary().total, 75_309 + 50_000);
+    }
+
+    #[test]
+    fn test_changes_after_update() {
+        let mut tree: Prices = (0..50).map(|id: u64| Price { id, cents: 10 }).collect();
+        tree.update_range(20..40, add(3));
+        // the stored value is compared as it is once updated
+        tree.remove(&Price { id: 25, cents: 13 }).expect("Failed remove");
+        assert!(tree.remove(&Price { id: 26, cents: 10 }).is_err());
+        assert_eq!(tree.take(&30), Some(Price { id: 30, cents: 13 }));
+        if let Entry::Occupied(entry) = tree.entry(Price { id: 35, cents: 0 }) {
+            assert_eq!(entry.get().cents, 13);
+        }
+        tree.entry(Price { id: 36, cents: 0 }).and_modify(|price: &mut Price| price.cents *= 10);
+        assert_eq!(tree.aggregate(36..=36).total, 130);
+        let high: Prices = tree.split_off(&30);
+        assert_eq!(high.summary().total, 9 * 13 + 117 + 10 * 10);
+        assert_eq!(high.into_iter().map(|price: Price| price.cents).sum::<i64>(), 9 * 13 + 117 + 10 * 10);
+        assert_eq!(tree.drain().map(|price: Price| price.cents).sum::<i64>(), 20 * 10 + 9 * 13);
+    }
+
+    #[test]
+    fn test_reads_after_update() {
+        let mut tree: Prices = (0..50).map(|id: u64| Price { id, cents: 10 }).collect();
+        tree.update_range(10..30, add(5));
+        assert_eq!(tree.get_ref(&20).map(|price: &Price| price.cents), Some(15));
+        assert_eq!(tree.get_ref(&40).map(|price: &Price| price.cents), Some(10));
+        tree.update_range::<u64, _>(.., Affine { factor: 3, delta: 0 });
+        assert_eq!(tree.get_ref(&20).map(|price: &Price| price.cents), Some(45));
+        let view: &AvlTree<Price, Natural, Lazy<Stats, Affine>, LazyBoxes<Price, Stats, Affine>> = tree.view();
+        assert_eq!(view.get_ref(&5).map(|price: &Price| price.cents), Some(30));
+        assert_eq!(view.first().map(|price: &Price| price.cents), Some(30));
+        assert_eq!(view.range(28..32).map(|price: &Price| price.cents).collect::<Vec<i64>>(), vec![45, 45, 30, 30]);
+        let tree: AvlTree<Price, Natural, Lazy<Stats, Affine>> = tree.into_tree();
+        assert_eq!(tree.iter().map(|price: &Price| price.cents).sum::<i64>(), 20 * 45 + 30 * 30);
+    }
+
+    #[test]
+    fn test_against_model() {
+        let mut tree: Prices = LazyAvlTree::with_policy(DuplicatePolicy::Reject);
+        let mut model: BTreeMap<u64, i64> = BTreeMap::new();
+        let mut seed: u64 = 7;
+        let mut next = move |bound: u64| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 33) % bound
+        };
+        for step in 0..2000 {
+            let id: u64 = next(200);
+            match next(4) {
+                0 => {
+                    let cents: i64 = next(1000) as i64;
+                    if tree.insert(Price { id, cents }).is_ok() {
+                        model.insert(id, cents);
+                    }
+                }
+                1 => {
+                    assert_eq!(tree.take(&id).map(|price: Price| price.cents), model.remove(&id));
+                }
+                _ => {
+                    let end: u64 = id + next(60);
+                    let update: Affine = Affine {
+                        factor: if next(8) == 0 { 2 } else { 1 },
+                        delta: next(21) as i64 - 10,
+                    };
+                    model.range_mut(id..end).for_each(|(_, cents)| *cents = *cents * update.factor + update.delta);
+                    tree.update_range(id..end, update);
+                }
+            }
+            if step % 100 == 0 {
+                check(&tree, &model);
+                assert!(tree.is_balanced());
+            }
+        }
+        check(&tree, &model);
+        assert!(tree.view().iter().map(|price: &Price| (price.id, price.cents)).eq(model.into_iter()));
+    }
+}
@@ -1,11 +1,59 @@
+// the original code of the crate is written in this style, keep it as is
+#![allow(clippy::borrowed_box, clippy::match_ref_pats, clippy::needless_late_init, clippy::needless_return, clippy::nonminimal_bool,
+    clippy::option_map_or_none, clippy::unnecessary_unwrap, clippy::unused_unit, clippy::collapsible_else_if)]
 use core::fmt;
-use std::borrow::BorrowMut;
-use std::cmp::max;
+use std::borrow::Borrow;
+use std::cmp::{max, Ordering};
 use std::fmt::{Debug, Display};
-use std::mem::{replace, swap};
-use std::ops::Not;
+use std::mem::replace;
+use std::ops::{Bound, Not, RangeBounds};
 use std::collections::HashSet;
 use std::hash::Hash;
+use std::io;
+use std::iter::{once, FromIterator};
+use std::marker::PhantomData;
+
+pub mod arena;
+mod balance;
+mod compare;
+mod cursor;
+mod entry;
+mod error;
+pub mod interval;
+mod iter;
+mod lazy;
+pub mod map;
+mod parse;
+pub mod persistent;
+mod policy;
+mod render;
+mod set;
+mod storage;
+mod summary;
+#[cfg(feature = "serde")]
+mod serialize;
+#[cfg(test)]
+mod fixture;
+
+pub use arena::{Arena, ArenaAvlTree};
+pub use compare::{Comparator, Natural};
+pub use cursor::{Cursor, CursorMut};
+pub use entry::{Entry, OccupiedEntry, VacantEntry};
+pub use error::AvlError;
+pub use interval::IntervalTree;
+pub use iter::{Drain, IntoIter, Iter, Range};
+pub use lazy::LazyAvlTree;
+pub use map::AvlMap;
+pub use persistent::PersistentAvlTree;
+pub use policy::DuplicatePolicy;
+pub use render::Ascii;
+pub use set::{Difference, Intersection, SymmetricDifference, Union};
+pub use storage::Boxes;
+pub use summary::{Lazy, Summary, Update};
+
+use balance::Storage;
+use parse::DumpParser;
+use storage::{Backend, Node};
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 #[repr(u8)]
@@ -24,38 +72,48 @@ impl Not for Side {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
-struct Node<T: Clone + Ord + Eq + Debug + Display + Hash> {
-    children: [Tree<T>; 2],
-    value: T,
-    duplicates: HashSet<T>,
-    height: usize,
+type Tree<T, S = ()> = Option<Box<Node<T, S>>>;
+
+/// The nodes are held by the storage B, a Box per node by default (see Boxes) or the slots of an arena (see ArenaAvlTree)
+pub struct AvlTree<T, C = Natural, S = (), B: Storage = Boxes<T, S>> {
+    root: B::Link,
+    storage: B,
+    cmp: C,
+    policy: DuplicatePolicy,
+    marker: PhantomData<(T, S)>,
 }
 
-impl<T: Clone + Ord + Eq + Debug + Display + Hash> fmt::Pointer for Node<T> {
+// the nodes of a boxed tree are reached from its root alone, they are compared and printed as they are linked
+impl<T: Debug, C: Debug, S: Debug> Debug for AvlTree<T, C, S> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let ptr = self as *const Self;
-        fmt::Pointer::fmt(&ptr, f)
+        f.debug_struct("AvlTree").field("root", &self.root).field("cmp", &self.cmp).field("policy", &self.policy).finish()
     }
 }
 
-impl<T: Clone + Ord + Eq + Debug + Display + Hash> fmt::Display for Node<T> {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:?}", self)
+impl<T: PartialEq, C: PartialEq, S: PartialEq> PartialEq for AvlTree<T, C, S> {
+    fn eq(&self, other: &Self) -> bool {
+        self.root == other.root && self.cmp == other.cmp && self.policy == other.policy
     }
 }
 
-type Tree<T> = Option<Box<Node<T>>>;
-
-#[derive(Debug, PartialEq, Clone)]
-pub struct AvlTree<T: Clone + Ord + Eq + Debug + Display + Hash> {
-    root: Tree<T>,
+impl<T, C: Clone, S, B: Storage + Clone> Clone for AvlTree<T, C, S, B> where B::Link: Clone {
+    fn clone(&self) -> Self {
+        AvlTree {
+            root: self.root.clone(),
+            storage: self.storage.clone(),
+            cmp: self.cmp.clone(),
+            policy: self.policy,
+            marker: PhantomData,
+        }
+    }
 }
 
 #[cfg(test)]
+#[allow(dropping_copy_types)]
 mod test_tree {
     use super::*;
     use std::cmp::Ordering;
+    use std::ops::Bound;
 
     #[derive(Clone, Eq, PartialEq, Debug, Hash)]
     struct Position {
@@ -81,6 +139,13 @@ mod test_tree {
         }
     }
 
+    /// Position is ordered by x only so it can be looked up with x alone
+    impl Borrow<i32> for Position {
+        fn borrow(&self) -> &i32 {
+            &self.x
+        }
+    }
+
     const TEST_1: u64 = 42;
     const TEST_2: u64 = 420;
     const TEST_3: u64 = 66;
@@ -98,7 +163,7 @@ mod test_tree {
     #[test]
     fn test_insert() {
         let mut tree: AvlTree<u64> = AvlTree::new();
-        let res: Result<&mut AvlTree<u64>, &str> = tree.insert(&TEST_1);
+        let res: Result<&mut AvlTree<u64>, AvlError> = tree.insert(TEST_1);
         assert!(res.is_ok());
         assert!(!tree.is_empty());
         assert!(tree.root.is_some());
@@ -108,9 +173,9 @@ mod test_tree {
     #[test]
     fn test_insert_twice() {
         let mut tree: AvlTree<u64> = AvlTree::new();
-        let res: Result<&mut AvlTree<u64>, &str> = tree.insert(&TEST_1);
+        let res: Result<&mut AvlTree<u64>, AvlError> = tree.insert(TEST_1);
         assert!(res.is_ok());
-        let res: Result<&mut AvlTree<u64>, &str> = tree.insert(&TEST_2);
+        let res: Result<&mut AvlTree<u64>, AvlError> = tree.insert(TEST_2);
         assert!(res.is_ok());
         assert!(!tree.is_empty());
         assert!(tree.root.is_some());
@@ -123,11 +188,11 @@ mod test_tree {
     #[test]
     fn test_insert_thrice() {
         let mut tree: AvlTree<u64> = AvlTree::new();
-        let res: Result<&mut AvlTree<u64>, &str> = tree.insert(&TEST_1);
+        let res: Result<&mut AvlTree<u64>, AvlError> = tree.insert(TEST_1);
         assert!(res.is_ok());
-        let res: Result<&mut AvlTree<u64>, &str> = tree.insert(&TEST_2);
+        let res: Result<&mut AvlTree<u64>, AvlError> = tree.insert(TEST_2);
         assert!(res.is_ok());
-        let res: Result<&mut AvlTree<u64>, &str> = tree.insert(&TEST_3);
+        let res: Result<&mut AvlTree<u64>, AvlError> = tree.insert(TEST_3);
         assert!(res.is_ok());
         assert!(!tree.is_empty());
         assert!(tree.root.is_some());
@@ -141,52 +206,52 @@ mod test_tree {
     #[test]
     fn test_contains() {
         let mut tree: AvlTree<u64> = AvlTree::new();
-        tree.insert(&TEST_1).expect("Insert failed")
-            .insert(&TEST_2).expect("Insert failed")
-            .insert(&TEST_3).expect("Insert failed")
-            .insert(&TEST_4).expect("Insert failed");
+        tree.insert(TEST_1).expect("Insert failed")
+            .insert(TEST_2).expect("Insert failed")
+            .insert(TEST_3).expect("Insert failed")
+            .insert(TEST_4).expect("Insert failed");
 
         assert!(tree.contains(&TEST_1));
         assert!(tree.contains(&TEST_2));
         assert!(tree.contains(&TEST_3));
         assert!(tree.contains(&TEST_4));
         assert!(!tree.contains(&TEST_5));
-        tree.insert(&TEST_5).expect("Insert failed");
+        tree.insert(TEST_5).expect("Insert failed");
         assert!(tree.contains(&TEST_5));
     }
 
     #[test]
     fn test_insert_complex() {
         let mut tree: AvlTree<u64> = AvlTree::new();
-        let res: Result<&mut AvlTree<u64>, &str> = tree.insert(&1);
+        let res: Result<&mut AvlTree<u64>, AvlError> = tree.insert(1);
         assert!(res.is_ok());
-        let res: Result<&mut AvlTree<u64>, &str> = tree.insert(&2);
+        let res: Result<&mut AvlTree<u64>, AvlError> = tree.insert(2);
         assert!(res.is_ok());
-        let res: Result<&mut AvlTree<u64>, &str> = tree.insert(&3);
+        let res: Result<&mut AvlTree<u64>, AvlError> = tree.insert(3);
         assert!(res.is_ok());
-        let res: Result<&mut AvlTree<u64>, &str> = tree.insert(&4);
+        let res: Result<&mut AvlTree<u64>, AvlError> = tree.insert(4);
         assert!(res.is_ok());
-        let res: Result<&mut AvlTree<u64>, &str> = tree.insert(&5);
+        let res: Result<&mut AvlTree<u64>, AvlError> = tree.insert(5);
         assert!(res.is_ok());
-        let res: Result<&mut AvlTree<u64>, &str> = tree.insert(&6);
+        let res: Result<&mut AvlTree<u64>, AvlError> = tree.insert(6);
         assert!(res.is_ok());
-        let res: Result<&mut AvlTree<u64>, &str> = tree.insert(&7);
+        let res: Result<&mut AvlTree<u64>, AvlError> = tree.insert(7);
         assert!(res.is_ok());
-        let res: Result<&mut AvlTree<u64>, &str> = tree.insert(&8);
+        let res: Result<&mut AvlTree<u64>, AvlError> = tree.insert(8);
         assert!(res.is_ok());
-        let res: Result<&mut AvlTree<u64>, &str> = tree.insert(&9);
+        let res: Result<&mut AvlTree<u64>, AvlError> = tree.insert(9);
         assert!(res.is_ok());
-        let res: Result<&mut AvlTree<u64>, &str> = tree.insert(&10);
+        let res: Result<&mut AvlTree<u64>, AvlError> = tree.insert(10);
         assert!(res.is_ok());
-        let res: Result<&mut AvlTree<u64>, &str> = tree.insert(&11);
+        let res: Result<&mut AvlTree<u64>, AvlError> = tree.insert(11);
         assert!(res.is_ok());
-        let res: Result<&mut AvlTree<u64>, &str> = tree.insert(&12);
+        let res: Result<&mut AvlTree<u64>, AvlError> = tree.insert(12);
         assert!(res.is_ok());
-        let res: Result<&mut AvlTree<u64>, &str> = tree.insert(&13);
+        let res: Result<&mut AvlTree<u64>, AvlError> = tree.insert(13);
         assert!(res.is_ok());
-        let res: Result<&mut AvlTree<u64>, &str> = tree.insert(&14);
+        let res: Result<&mut AvlTree<u64>, AvlError> = tree.insert(14);
         assert!(res.is_ok());
-        let res: Result<&mut AvlTree<u64>, &str> = tree.insert(&15);
+        let res: Result<&mut AvlTree<u64>, AvlError> = tree.insert(15);
         assert!(res.is_ok());
         assert!(!tree.is_empty());
         assert_eq!(tree.root.unwrap().height, 4)
@@ -198,7 +263,7 @@ mod test_tree {
         let mut tree: AvlTree<u64> = AvlTree::new();
         {
             let value: u64 = 67;
-            let res: Result<&mut AvlTree<u64>, &str> = tree.insert(&value);
+            let res: Result<&mut AvlTree<u64>, AvlError> = tree.insert(value);
             assert!(res.is_ok());
             drop(value);
         }
@@ -209,15 +274,15 @@ mod test_tree {
     #[test]
     fn test_delete_heap() {
         let mut tree: AvlTree<u64> = AvlTree::new();
-        let res: Result<&mut AvlTree<u64>, &str> = tree.insert(&TEST_1);
+        let res: Result<&mut AvlTree<u64>, AvlError> = tree.insert(TEST_1);
         assert!(res.is_ok());
-        let res: Result<&mut AvlTree<u64>, &str> = tree.insert(&TEST_2);
+        let res: Result<&mut AvlTree<u64>, AvlError> = tree.insert(TEST_2);
         assert!(res.is_ok());
-        let res: Result<&mut AvlTree<u64>, &str> = tree.insert(&TEST_3);
+        let res: Result<&mut AvlTree<u64>, AvlError> = tree.insert(TEST_3);
         assert!(res.is_ok());
-        let res: Result<&mut AvlTree<u64>, &str> = tree.insert(&TEST_4);
+        let res: Result<&mut AvlTree<u64>, AvlError> = tree.insert(TEST_4);
         assert!(res.is_ok());
-        let res: Result<&mut AvlTree<u64>, &str> = tree.insert(&TEST_5);
+        let res: Result<&mut AvlTree<u64>, AvlError> = tree.insert(TEST_5);
         assert!(res.is_ok());
         assert!(!tree.is_empty());
         assert_eq!(tree.root.as_ref().unwrap().value, TEST_3);
@@ -231,7 +296,7 @@ mod test_tree {
         let mut tree: AvlTree<u64> = AvlTree::new();
         {
             let payload: u64 = 67;
-            let res: Result<&mut AvlTree<u64>, &str> = tree.insert(&payload);
+            let res: Result<&mut AvlTree<u64>, AvlError> = tree.insert(payload);
             assert!(res.is_ok());
             drop(payload);
         }
@@ -243,11 +308,11 @@ mod test_tree {
     #[test]
     fn test_height() {
         let mut tree: AvlTree<u64> = AvlTree::new();
-        let _res = tree.insert(&TEST_1).expect("Failed insert")
-            .insert(&TEST_2).expect("Failed insert")
-            .insert(&TEST_3).expect("Failed insert")
-            .insert(&TEST_4).expect("Failed insert")
-            .insert(&TEST_5).expect("Failed insert");
+        let _res = tree.insert(TEST_1).expect("Failed insert")
+            .insert(TEST_2).expect("Failed insert")
+            .insert(TEST_3).expect("Failed insert")
+            .insert(TEST_4).expect("Failed insert")
+            .insert(TEST_5).expect("Failed insert");
         assert_eq!(tree.height(), 3);
     }
 
@@ -257,11 +322,11 @@ mod test_tree {
         assert!(tree.is_balanced());
         tree.delete();
         let mut tree: AvlTree<u64> = AvlTree::new();
-        let _res = tree.insert(&TEST_1).expect("Failed insert")
-            .insert(&TEST_2).expect("Failed insert")
-            .insert(&TEST_3).expect("Failed insert")
-            .insert(&TEST_4).expect("Failed insert")
-            .insert(&TEST_5).expect("Failed insert");
+        let _res = tree.insert(TEST_1).expect("Failed insert")
+            .insert(TEST_2).expect("Failed insert")
+            .insert(TEST_3).expect("Failed insert")
+            .insert(TEST_4).expect("Failed insert")
+            .insert(TEST_5).expect("Failed insert");
         assert!(tree.is_correct());
         assert!(tree.is_balanced());
     }
@@ -269,23 +334,23 @@ mod test_tree {
     #[test]
     fn test_remove() {
         let mut tree: AvlTree<u64> = AvlTree::new();
-        let _res = tree.insert(&TEST_1).expect("Failed insert")
-            .insert(&TEST_2).expect("Failed insert")
-            .insert(&TEST_3).expect("Failed insert")
-            .insert(&TEST_4).expect("Failed insert")
-            .insert(&TEST_5).expect("Failed insert");
+        let _res = tree.insert(TEST_1).expect("Failed insert")
+            .insert(TEST_2).expect("Failed insert")
+            .insert(TEST_3).expect("Failed insert")
+            .insert(TEST_4).expect("Failed insert")
+            .insert(TEST_5).expect("Failed insert");
         assert!(tree.is_correct());
         assert!(tree.is_balanced());
         assert_eq!(tree.height(), 3);
-        let res: Result<&mut AvlTree<u64>, &str> = tree.remove(&TEST_1);
+        let res: Result<&mut AvlTree<u64>, AvlError> = tree.remove(&TEST_1);
         assert!(res.is_ok());
         assert_eq!(tree.height(), 3);
         assert!(tree.is_balanced());
-        let res: Result<&mut AvlTree<u64>, &str> = tree.remove(&TEST_2);
+        let res: Result<&mut AvlTree<u64>, AvlError> = tree.remove(&TEST_2);
         assert!(res.is_ok());
         assert_eq!(tree.height(), 2);
         assert!(tree.is_balanced());
-        let res: Result<&mut AvlTree<u64>, &str> = tree.remove(&TEST_2);
+        let res: Result<&mut AvlTree<u64>, AvlError> = tree.remove(&TEST_2);
         assert!(res.is_err());
         assert_eq!(tree.height(), 2);
         assert!(tree.is_balanced());
@@ -298,11 +363,11 @@ mod test_tree {
     #[test]
     fn test_min_max() {
         let mut tree: AvlTree<u64> = AvlTree::new();
-        let _res = tree.insert(&TEST_1).expect("Failed insert")
-            .insert(&TEST_2).expect("Failed insert")
-            .insert(&TEST_3).expect("Failed insert")
-            .insert(&TEST_4).expect("Failed insert")
-            .insert(&TEST_5).expect("Failed insert");
+        let _res = tree.insert(TEST_1).expect("Failed insert")
+            .insert(TEST_2).expect("Failed insert")
+            .insert(TEST_3).expect("Failed insert")
+            .insert(TEST_4).expect("Failed insert")
+            .insert(TEST_5).expect("Failed insert");
         assert!(tree.min().is_some());
         assert_eq!(tree.min().unwrap(), TEST_1);
         assert!(tree.max().is_some());
@@ -312,11 +377,11 @@ mod test_tree {
     #[test]
     fn test_width_count_depth() {
         let mut tree: AvlTree<u64> = AvlTree::new();
-        let _res = tree.insert(&TEST_1).expect("Failed insert")
-            .insert(&TEST_2).expect("Failed insert")
-            .insert(&TEST_3).expect("Failed insert")
-            .insert(&TEST_4).expect("Failed insert")
-            .insert(&TEST_5).expect("Failed insert");
+        let _res = tree.insert(TEST_1).expect("Failed insert")
+            .insert(TEST_2).expect("Failed insert")
+            .insert(TEST_3).expect("Failed insert")
+            .insert(TEST_4).expect("Failed insert")
+            .insert(TEST_5).expect("Failed insert");
         assert_eq!(tree.depth(), 3);
         assert_eq!(tree.width(), 3);
         assert_eq!(tree.count(), 5);
@@ -325,33 +390,585 @@ mod test_tree {
     #[test]
     fn test_get_set() {
         let mut tree: AvlTree<Position> = AvlTree::new();
-        let _res = tree.insert(&Position { x: 1, y: 0 }).expect("Failed insert")
-            .insert(&Position { x: 2, y: 0 }).expect("Failed insert")
-            .insert(&Position { x: 3, y: 0 }).expect("Failed insert")
-            .insert(&Position { x: 4, y: 0 }).expect("Failed insert")
-            .insert(&Position { x: 5, y: 1 }).expect("Failed insert")
-            .insert(&Position { x: 5, y: 2 }).expect("Failed insert")
-            .insert(&Position { x: 5, y: 3 }).expect("Failed insert")
-            .insert(&Position { x: 5, y: 4 }).expect("Failed insert")
-            .insert(&Position { x: 5, y: 5 }).expect("Failed insert");
+        let _res = tree.insert(Position { x: 1, y: 0 }).expect("Failed insert")
+            .insert(Position { x: 2, y: 0 }).expect("Failed insert")
+            .insert(Position { x: 3, y: 0 }).expect("Failed insert")
+            .insert(Position { x: 4, y: 0 }).expect("Failed insert")
+            .insert(Position { x: 5, y: 1 }).expect("Failed insert")
+            .insert(Position { x: 5, y: 2 }).expect("Failed insert")
+            .insert(Position { x: 5, y: 3 }).expect("Failed insert")
+            .insert(Position { x: 5, y: 4 }).expect("Failed insert")
+            .insert(Position { x: 5, y: 5 }).expect("Failed insert");
         assert_eq!(tree.get_set(&Position { x: 5, y: -1 }).len(), 5);
+        assert_eq!(tree.get_set(&5).len(), 5);
+        assert!(tree.get_set(&6).is_empty());
+    }
+
+    #[test]
+    fn test_borrow() {
+        let mut tree: AvlTree<String> = AvlTree::new();
+        for word in ["delta", "alpha", "echo", "charlie", "bravo"].iter() {
+            tree.insert(word.to_string()).expect("Failed insert");
+        }
+        assert!(tree.contains("charlie"));
+        assert!(!tree.contains("foxtrot"));
+        assert_eq!(tree.get_ref("echo").map(String::as_str), Some("echo"));
+        assert_eq!(tree.get("alpha"), Some("alpha".to_string()));
+        assert_eq!(tree.rank("c"), 2);
+        assert!(tree.find("delta").expect("Missing value").contains("delta"));
+        assert_eq!(tree.find("zulu").unwrap_err(), AvlError::NotFound);
+        let words: Vec<&String> = tree.range::<str, _>((Bound::Included("b"), Bound::Excluded("d"))).collect();
+        assert_eq!(words, vec!["bravo", "charlie"]);
+        assert_eq!(tree.take("bravo"), Some("bravo".to_string()));
+        assert_eq!(tree.take("bravo"), None);
+        assert_eq!(tree.len(), 4);
+        assert!(tree.is_correct());
+    }
+
+    #[test]
+    fn test_comparator() {
+        let mut tree = AvlTree::with_comparator(|left: &u64, right: &u64| right.cmp(left));
+        for value in 1..=20 {
+            tree.insert(value).expect("Failed insert");
+        }
+        assert!(tree.is_correct());
+        assert!(tree.is_balanced());
+        assert_eq!(tree.first(), Some(&20));
+        assert_eq!(tree.iter().take(3).cloned().collect::<Vec<u64>>(), vec![20, 19, 18]);
+        assert_eq!(tree.range((Bound::Included(15), Bound::Included(12))).cloned().collect::<Vec<u64>>(), vec![15, 14, 13, 12]);
+        assert_eq!(tree.rank(&18), 2);
+        assert!(tree.contains(&7));
+        tree.remove(&7).expect("Failed remove");
+        assert!(!tree.contains(&7));
+        assert_eq!(tree.take(&20), Some(20));
+        assert_eq!(tree.len(), 18);
+    }
+
+    #[test]
+    fn test_comparator_duplicates() {
+        let mut tree = AvlTree::with_comparator(|left: &String, right: &String| left.to_lowercase().cmp(&right.to_lowercase()));
+        for word in ["Bravo", "alpha", "ALPHA", "charlie", "Alpha"].iter() {
+            tree.insert(word.to_string()).expect("Failed insert");
+        }
+        assert!(tree.insert("ALPHA".to_string()).is_err());
+        assert_eq!(tree.len(), 5);
+        assert_eq!(tree.get_set(&"aLpHa".to_string()).len(), 3);
+        let words: Vec<&str> = tree.iter().map(String::as_str).collect();
+        assert_eq!(words, vec!["alpha", "ALPHA", "Alpha", "Bravo", "charlie"]);
+        assert!(tree.contains_exact(&"ALPHA".to_string()));
+        assert!(!tree.contains_exact(&"bravo".to_string()));
+    }
+
+    #[test]
+    fn test_comparator_views() {
+        let positions: Vec<Position> = (0..10).map(|x| Position { x, y: (x * 7) % 10 }).collect();
+        let by_x: AvlTree<Position> = positions.iter().cloned().fold(AvlTree::new(), |mut tree, position| {
+            tree.insert(position).expect("Failed insert");
+            tree
+        });
+        let mut by_y = AvlTree::with_comparator(|left: &Position, right: &Position| left.y.cmp(&right.y));
+        for position in positions {
+            by_y.insert(position).expect("Failed insert");
+        }
+        assert_eq!(by_x.first(), Some(&Position { x: 0, y: 0 }));
+        assert_eq!(by_y.last(), Some(&Position { x: 7, y: 9 }));
+        assert_eq!(by_y.iter().map(|position| position.y).collect::<Vec<i32>>(), (0..10).collect::<Vec<i32>>());
+        assert_eq!(by_y.get_ref(&Position { x: -1, y: 1 }), Some(&Position { x: 3, y: 1 }));
+    }
+
+    #[test]
+    fn test_policy_reject() {
+        let mut tree: AvlTree<Position> = AvlTree::with_policy(DuplicatePolicy::Reject);
+        assert_eq!(tree.policy(), DuplicatePolicy::Reject);
+        tree.insert(Position { x: 1, y: 0 }).expect("Failed insert")
+            .insert(Position { x: 2, y: 0 }).expect("Failed insert");
+        assert_eq!(tree.insert(Position { x: 2, y: 1 }).unwrap_err(), AvlError::DuplicateKey);
+        assert_eq!(tree.insert(Position { x: 2, y: 0 }).unwrap_err(), AvlError::DuplicateKey);
+        assert_eq!(tree.len(), 2);
+        assert_eq!(tree.get(&2), Some(Position { x: 2, y: 0 }));
+        assert_eq!(tree.occurrences(&2), 1);
+    }
+
+    #[test]
+    fn test_policy_replace() {
+        let mut tree: AvlTree<Position> = AvlTree::with_policy(DuplicatePolicy::Replace);
+        for y in 0..5 {
+            tree.insert(Position { x: 1, y }).expect("Failed insert")
+                .insert(Position { x: 2, y }).expect("Failed insert")
+                .insert(Position { x: 3, y }).expect("Failed insert");
+        }
+        assert_eq!(tree.len(), 3);
+        assert!(tree.is_correct());
+        assert_eq!(tree.get(&2), Some(Position { x: 2, y: 4 }));
+        assert_eq!(tree.occurrences(&2), 1);
+        assert_eq!(tree.dump(false).expect("Missing print"), r#"["2,4",["1,4",null,null],["3,4",null,null]]"#);
+        assert!(tree.remove(&Position { x: 2, y: 0 }).is_err());
+        tree.remove(&Position { x: 2, y: 4 }).expect("Failed remove");
+        assert!(!tree.contains(&2));
+    }
+
+    #[test]
+    fn test_policy_bucket() {
+        let build = || {
+            let mut tree: AvlTree<Position> = AvlTree::with_policy(DuplicatePolicy::Bucket);
+            for y in 0..6 {
+                for x in 0..8 {
+                    tree.insert(Position { x, y: (y * 5 + x) % 6 }).expect("Failed insert");
+                }
+            }
+            tree
+        };
+        let mut tree: AvlTree<Position> = build();
+        assert_eq!(AvlTree::<Position>::new().policy(), DuplicatePolicy::Bucket);
+        assert_eq!(tree, build());
+        assert_eq!(tree.dump(true), build().dump(true));
+        assert_eq!(tree.insert(Position { x: 3, y: 2 }).unwrap_err(), AvlError::DuplicateValue);
+        assert_eq!(tree.occurrences(&3), 6);
+        // the values sharing a key come out in insertion order
+        let ys: Vec<i32> = tree.range(3..=3).map(|position| position.y).collect();
+        assert_eq!(ys, vec![3, 2, 1, 0, 5, 4]);
+        assert_eq!(tree.get(&3), Some(Position { x: 3, y: 3 }));
+        tree.remove(&Position { x: 3, y: 3 }).expect("Failed remove");
+        assert_eq!(tree.get(&3), Some(Position { x: 3, y: 2 }));
+        tree.remove(&Position { x: 3, y: 1 }).expect("Failed remove");
+        assert_eq!(tree.take(&3), Some(Position { x: 3, y: 2 }));
+        assert_eq!(tree.get(&3), Some(Position { x: 3, y: 0 }));
+        assert_eq!(tree.occurrences(&3), 3);
+        assert!(tree.is_correct());
+    }
+
+    #[test]
+    fn test_policy_count() {
+        let mut tree: AvlTree<Position> = AvlTree::with_policy(DuplicatePolicy::Count);
+        for y in 0..4 {
+            for x in 0..10 {
+                tree.insert(Position { x, y }).expect("Failed insert");
+            }
+        }
+        assert_eq!(tree.len(), 10);
+        assert_eq!(tree.iter().count(), 10);
+        assert!(tree.is_correct());
+        assert_eq!(tree.occurrences(&4), 4);
+        assert_eq!(tree.occurrences(&10), 0);
+        assert_eq!(tree.get(&4), Some(Position { x: 4, y: 0 }));
+        assert!(tree.remove(&Position { x: 4, y: 1 }).is_err());
+        for left in (1..4).rev() {
+            tree.remove(&Position { x: 4, y: 0 }).expect("Failed remove");
+            assert_eq!(tree.occurrences(&4), left);
+            assert_eq!(tree.len(), 10);
+        }
+        tree.remove(&Position { x: 4, y: 0 }).expect("Failed remove");
+        assert!(!tree.contains(&4));
+        assert_eq!(tree.len(), 9);
+        // the counted insertions follow their value when the tree is rebalanced
+        for x in (0..10).filter(|x: &i32| *x != 4 && *x != 7) {
+            assert!(tree.take(&x).is_some());
+        }
+        assert_eq!(tree.occurrences(&7), 4);
+        assert_eq!(tree.take(&7), Some(Position { x: 7, y: 0 }));
+        assert!(tree.is_empty());
+    }
+
+    #[test]
+    fn test_from_dump() {
+        let mut tree: AvlTree<u64> = AvlTree::new();
+        for value in 1..=12 {
+            tree.insert(value * 10).expect("Failed insert");
+        }
+        for prettify in [false, true].iter() {
+            let dump: String = tree.dump(*prettify).expect("Missing print");
+            let read: AvlTree<u64> = AvlTree::from_dump(&dump, str::parse::<u64>).expect("Failed read");
+            assert_eq!(read, tree);
+            assert!(read.is_correct());
+        }
+        let empty: AvlTree<u64> = AvlTree::from_dump(" null ", str::parse::<u64>).expect("Failed read");
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn test_from_dump_values() {
+        let mut tree: AvlTree<Position> = AvlTree::new();
+        for x in 0..6 {
+            tree.insert(Position { x, y: -x }).expect("Failed insert");
+        }
+        let parse = |text: &str| -> Result<Position, ()> {
+            let mut numbers = text.trim_matches('"').split(',').map(|number: &str| number.parse::<i32>().map_err(|_| ()));
+            Ok(Position { x: numbers.next().ok_or(())??, y: numbers.next().ok_or(())?? })
+        };
+        let dump: String = tree.dump(true).expect("Missing print");
+        let read: AvlTree<Position> = AvlTree::from_dump(&dump, parse).expect("Failed read");
+        assert_eq!(read.dump(true), Ok(dump));
+        assert_eq!(read.get(&4), Some(Position { x: 4, y: -4 }));
+    }
+
+    #[test]
+    fn test_from_dump_rotation() {
+        // a fixture one insertion away from a right left rotation at the root
+        let mut tree: AvlTree<u64> = AvlTree::from_dump("[20,[10,null,null],[40,[30,null,null],[50,null,null]]]", str::parse::<u64>).expect("Failed read");
+        assert_eq!(tree.height(), 3);
+        tree.insert(35).expect("Failed insert");
+        assert_eq!(tree.dump(false), Ok("[30,[20,[10,null,null],null],[40,[35,null,null],[50,null,null]]]".to_string()));
+        assert!(tree.is_correct());
+    }
+
+    #[test]
+    fn test_from_dump_errors() {
+        let read = |dump: &str| AvlTree::<u64>::from_dump(dump, str::parse::<u64>).map(|_| ());
+        assert_eq!(read(""), Err(AvlError::InvalidDump(0)));
+        assert_eq!(read("[1,null]"), Err(AvlError::InvalidDump(7)));
+        assert_eq!(read("[1,null,null] x"), Err(AvlError::InvalidDump(14)));
+        assert_eq!(read("[x,null,null]"), Err(AvlError::InvalidDump(1)));
+        assert_eq!(read("[,null,null]"), Err(AvlError::InvalidDump(1)));
+        assert_eq!(read("[2,[3,null,null],null]"), Err(AvlError::UnorderedDump(0)));
+        assert_eq!(read("[2,[1,null,null],[2,null,null]]"), Err(AvlError::UnorderedDump(17)));
+        assert_eq!(read("[5,[2,[1,null,null],[6,null,null]],[8,null,null]]"), Err(AvlError::UnorderedDump(0)));
+        assert_eq!(read("[3,[2,[1,null,null],null],null]"), Err(AvlError::UnbalancedDump(0)));
+        assert_eq!(read("[4,[3,[2,[1,null,null],null],null],[5,null,null]]"), Err(AvlError::UnbalancedDump(3)));
+        let reversed = AvlTree::from_dump_with_comparator("[2,[3,null,null],[1,null,null]]", str::parse::<u64>, |left: &u64, right: &u64| right.cmp(left));
+        assert_eq!(reversed.expect("Failed read").first(), Some(&3));
+        let deep: String = "[1,null,".repeat(200) + "null" + &"]".repeat(200);
+        assert!(matches!(read(&deep), Err(AvlError::UnbalancedDump(_))));
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let mut tree: AvlTree<u64> = AvlTree::new();
+        assert_eq!(tree.to_dot(), "digraph AvlTree {\n    node [shape=box];\n}\n");
+        tree.insert(TEST_1).expect("Failed insert")
+            .insert(TEST_2).expect("Failed insert");
+        assert_eq!(tree.to_dot(), r#"digraph AvlTree {
+    node [shape=box];
+    n0 [label="42\nheight: 2 balance: -1 duplicates: 0"];
+    null1 [shape=point];
+    n0 -> null1;
+    n2 [label="420\nheight: 1 balance: 0 duplicates: 0"];
+    null3 [shape=point];
+    n2 -> null3;
+    null4 [shape=point];
+    n2 -> null4;
+    n0 -> n2;
+}
+"#);
+        let mut tree: AvlTree<Position> = AvlTree::new();
+        tree.insert(Position { x: 1, y: 0 }).expect("Failed insert")
+            .insert(Position { x: 1, y: 1 }).expect("Failed insert");
+        let mut dot: Vec<u8> = Vec::new();
+        tree.write_dot(&mut dot).expect("Failed write");
+        assert_eq!(String::from_utf8(dot).expect("Invalid output"), tree.to_dot());
+        assert!(tree.to_dot().contains(r#"n0 [label="\"1,0\"\nheight: 1 balance: 0 duplicates: 1"];"#));
+    }
+
+    #[test]
+    fn test_render_ascii() {
+        let mut tree: AvlTree<u64> = AvlTree::new();
+        assert_eq!(tree.render_ascii(20), "");
+        for value in 1..=6 {
+            tree.insert(value).expect("Failed insert");
+        }
+        assert_eq!(tree.render_ascii(24), concat!(
+            "            4\n",
+            "         /     \\\n",
+            "      2           5\n",
+            "    /  \\           \\\n",
+            "   1     3           6\n",
+        ));
+        assert_eq!(tree.ascii(40).annotate(true).max_depth(2).to_string(), concat!(
+            "                4 (h3 b0)\n",
+            "               /         \\\n",
+            "      2 (h2 b0)          5 (h2 b-1)\n",
+            "       /    \\                   \\\n",
+            "    ...       ...                 ...\n",
+        ));
+        // the labels are cut to the room they have
+        assert_eq!(AvlTree::with(123456).render_ascii(4), "1234\n");
+    }
+
+    #[test]
+    fn test_from_sorted_iter() {
+        for len in 0..70 {
+            let tree: AvlTree<u64> = AvlTree::from_sorted_iter(0..len).expect("Failed build");
+            assert_eq!(tree.len(), len as usize);
+            assert!(tree.is_correct());
+            assert!(tree.is_balanced());
+            assert!(tree.iter().cloned().eq(0..len));
+        }
+        let tree: AvlTree<u64> = AvlTree::from_sorted_iter(0..10_000).expect("Failed build");
+        assert_eq!(tree.height(), 14);
+        let positions = vec![Position { x: 1, y: 0 }, Position { x: 2, y: 0 }, Position { x: 2, y: 2 }, Position { x: 2, y: 1 }, Position { x: 3, y: 0 }];
+        let tree: AvlTree<Position> = AvlTree::from_sorted_iter(positions.clone()).expect("Failed build");
+        assert_eq!(tree.count(), 5);
+        assert_eq!(tree.occurrences(&2), 3);
+        assert!(tree.iter().eq(positions.iter()));
+        assert!(tree.is_correct());
+        assert_eq!(AvlTree::from_sorted_iter(vec![1, 2, 2, 4]).unwrap_err(), AvlError::DuplicateValue);
+        assert_eq!(AvlTree::from_sorted_iter(vec![1, 3, 2, 4]).unwrap_err(), AvlError::Unsorted(2));
+        let reversed = AvlTree::from_sorted_iter_with_comparator((0..10).rev(), |left: &u64, right: &u64| right.cmp(left)).expect("Failed build");
+        assert_eq!(reversed.first(), Some(&9));
+        assert!(reversed.is_balanced());
+    }
+
+    #[test]
+    fn test_from_iterator() {
+        let tree: AvlTree<u64> = vec![5, 3, 9, 1, 3, 7].into_iter().collect();
+        assert_eq!(tree.iter().cloned().collect::<Vec<u64>>(), vec![1, 3, 5, 7, 9]);
+        assert!(tree.is_correct());
+        let tree: AvlTree<Position> = (0..50).map(|y| Position { x: (y * 7) % 10, y }).collect();
+        assert_eq!(tree.len(), 50);
+        assert!(tree.is_balanced());
+        // the values sharing a key keep the order they were given in
+        let ys: Vec<i32> = tree.range(3..=3).map(|position| position.y).collect();
+        assert_eq!(ys, vec![9, 19, 29, 39, 49]);
+    }
+
+    #[test]
+    fn test_extend() {
+        let mut tree: AvlTree<Position> = (0..100).map(|x| Position { x, y: 0 }).collect();
+        // a few values are inserted one by one
+        tree.extend(vec![Position { x: 50, y: 1 }, Position { x: 50, y: 0 }, Position { x: 200, y: 0 }]);
+        assert_eq!(tree.len(), 102);
+        assert!(tree.is_correct());
+        // many values are merged, the values already in the tree come first
+        tree.extend((0..300).rev().map(|x| Position { x, y: 2 }));
+        assert_eq!(tree.len(), 402);
+        assert!(tree.is_correct());
+        assert!(tree.is_balanced());
+        let ys: Vec<i32> = tree.range(50..=50).map(|position| position.y).collect();
+        assert_eq!(ys, vec![0, 1, 2]);
+        assert!(tree.iter().zip(tree.iter().skip(1)).all(|(left, right)| left <= right));
+        let mut tree: AvlTree<Position> = AvlTree::with_policy(DuplicatePolicy::Replace);
+        tree.extend((0..10).map(|x| Position { x, y: 0 }));
+        tree.extend((0..20).map(|x| Position { x: x / 2, y: x }));
+        assert_eq!(tree.len(), 10);
+        assert_eq!(tree.get(&4), Some(Position { x: 4, y: 9 }));
+        let mut tree: AvlTree<u64> = AvlTree::with_policy(DuplicatePolicy::Count);
+        tree.extend(vec![1, 2, 3]);
+        tree.extend((0..10).map(|value| value % 4));
+        assert_eq!(tree.len(), 4);
+        assert_eq!(tree.occurrences(&1), 4);
+        assert_eq!(tree.occurrences(&0), 3);
+    }
+
+    #[test]
+    fn test_join() {
+        for (left_len, right_len) in [(0, 0), (0, 5), (5, 0), (1, 1000), (1000, 1), (300, 400), (17, 2)].iter() {
+            let left: AvlTree<u64> = AvlTree::from_sorted_iter(0..*left_len).expect("Failed build");
+            let right: AvlTree<u64> = AvlTree::from_sorted_iter(left_len + 1..left_len + 1 + right_len).expect("Failed build");
+            let tree: AvlTree<u64> = AvlTree::join(left, *left_len, right).expect("Failed join");
+            assert_eq!(tree.len() as u64, left_len + right_len + 1);
+            assert!(tree.is_correct());
+            assert!(tree.is_balanced());
+            assert!(tree.iter().cloned().eq(0..left_len + right_len + 1));
+        }
+        let left: AvlTree<u64> = AvlTree::from_sorted_iter(0..10).expect("Failed build");
+        let right: AvlTree<u64> = AvlTree::from_sorted_iter(20..30).expect("Failed build");
+        assert_eq!(AvlTree::join(left.clone(), 9, right.clone()).unwrap_err(), AvlError::Overlap);
+        assert_eq!(AvlTree::join(left.clone(), 20, right.clone()).unwrap_err(), AvlError::Overlap);
+        assert_eq!(AvlTree::join(right, 15, left).unwrap_err(), AvlError::Overlap);
+    }
+
+    #[test]
+    fn test_split_off() {
+        let tree: AvlTree<u64> = (0..200).map(|value| value * 2).collect();
+        for key in 0..402 {
+            let mut lower: AvlTree<u64> = tree.clone();
+            let greater: AvlTree<u64> = lower.split_off(&key);
+            assert!(lower.is_correct() && lower.is_balanced());
+            assert!(greater.is_correct() && greater.is_balanced());
+            assert!(lower.iter().all(|value| *value < key));
+            assert!(greater.iter().all(|value| *value >= key));
+            assert_eq!(lower.len() + greater.len(), 200);
+        }
+        let mut tree: AvlTree<Position> = (0..30).map(|y| Position { x: y % 6, y }).collect();
+        let greater: AvlTree<Position> = tree.split_off(&3);
+        assert_eq!(tree.len(), 15);
+        // the values sharing the key go with it
+        assert_eq!(greater.occurrences(&3), 5);
+        assert_eq!(greater.first(), Some(&Position { x: 3, y: 3 }));
+        assert!(greater.is_correct());
+    }
+
+    #[test]
+    fn test_append() {
+        let mut tree: AvlTree<u64> = AvlTree::from_sorted_iter(0..1000).expect("Failed build");
+        let mut other: AvlTree<u64> = AvlTree::from_sorted_iter(1000..1010).expect("Failed build");
+        tree.append(&mut other);
+        assert!(other.is_empty());
+        assert_eq!(tree.len(), 1010);
+        assert!(tree.is_correct() && tree.is_balanced());
+        let mut other: AvlTree<u64> = AvlTree::from_sorted_iter(2000..4000).expect("Failed build");
+        other.append(&mut tree);
+        assert!(tree.is_empty());
+        assert!(other.iter().cloned().eq((0..1010).chain(2000..4000)));
+        assert!(other.is_correct() && other.is_balanced());
+        tree.append(&mut other);
+        assert_eq!(tree.len(), 3010);
+        // the ranges overlap so the trees are merged
+        let mut tree: AvlTree<Position> = (0..10).map(|x| Position { x, y: 0 }).collect();
+        let mut other: AvlTree<Position> = (5..15).map(|x| Position { x, y: 1 }).chain(Some(Position { x: 5, y: 0 })).collect();
+        tree.append(&mut other);
+        assert_eq!(tree.len(), 20);
+        assert!(tree.is_correct() && tree.is_balanced());
+        let ys: Vec<i32> = tree.range(5..=5).map(|position| position.y).collect();
+        assert_eq!(ys, vec![0, 1]);
+        let mut tree: AvlTree<u64> = AvlTree::with_policy(DuplicatePolicy::Count);
+        let mut other: AvlTree<u64> = AvlTree::with_policy(DuplicatePolicy::Count);
+        tree.extend(vec![1, 2, 2]);
+        other.extend(vec![2, 2, 2, 3]);
+        tree.append(&mut other);
+        assert_eq!(tree.occurrences(&2), 5);
+    }
+
+    #[test]
+    fn test_floor_ceiling() {
+        let tree: AvlTree<u64> = (1..=50).map(|value| value * 10).collect();
+        assert_eq!(tree.floor(&255), Some(&250));
+        assert_eq!(tree.floor(&250), Some(&250));
+        assert_eq!(tree.floor(&5), None);
+        assert_eq!(tree.ceiling(&255), Some(&260));
+        assert_eq!(tree.ceiling(&260), Some(&260));
+        assert_eq!(tree.ceiling(&501), None);
+        assert_eq!(tree.predecessor(&250), Some(&240));
+        assert_eq!(tree.predecessor(&10), None);
+        assert_eq!(tree.predecessor(&1000), Some(&500));
+        assert_eq!(tree.successor(&250), Some(&260));
+        assert_eq!(tree.successor(&500), None);
+        assert_eq!(tree.successor(&0), Some(&10));
+        for key in 0..520 {
+            assert_eq!(tree.floor(&key), tree.iter().rev().find(|value| **value <= key));
+            assert_eq!(tree.successor(&key), tree.iter().find(|value| **value > key));
+        }
+        let empty: AvlTree<u64> = AvlTree::new();
+        assert_eq!(empty.floor(&1), None);
+        assert_eq!(empty.successor(&1), None);
+    }
+
+    #[test]
+    fn test_floor_ceiling_duplicates() {
+        let mut tree: AvlTree<Position> = AvlTree::new();
+        for (x, y) in [(1, 0), (3, 0), (3, 1), (5, 0)].iter() {
+            tree.insert(Position { x: *x, y: *y }).expect("Failed insert");
+        }
+        assert_eq!(tree.floor(&4), Some(&Position { x: 3, y: 0 }));
+        assert_eq!(tree.ceiling(&2), Some(&Position { x: 3, y: 0 }));
+        assert_eq!(tree.predecessor(&3), Some(&Position { x: 1, y: 0 }));
+        assert_eq!(tree.successor(&3), Some(&Position { x: 5, y: 0 }));
+    }
+
+    #[test]
+    fn test_take() {
+        let mut tree: AvlTree<Position> = AvlTree::new();
+        for x in 0..20 {
+            tree.insert(Position { x, y: 0 }).expect("Failed insert");
+        }
+        tree.insert(Position { x: 7, y: 1 }).expect("Failed insert")
+            .insert(Position { x: 7, y: 2 }).expect("Failed insert");
+        assert_eq!(tree.get_ref(&7), Some(&Position { x: 7, y: 0 }));
+        assert_eq!(tree.rank(&7), 7);
+        // the duplicates are taken in insertion order
+        assert_eq!(tree.take(&7), Some(Position { x: 7, y: 0 }));
+        assert_eq!(tree.take(&7), Some(Position { x: 7, y: 1 }));
+        assert_eq!(tree.take(&7), Some(Position { x: 7, y: 2 }));
+        assert_eq!(tree.take(&7), None);
+        for x in (0..20).filter(|x: &i32| *x != 7) {
+            assert_eq!(tree.take(&x).map(|position: Position| position.x), Some(x));
+            assert!(tree.is_correct());
+            assert!(tree.is_balanced());
+        }
+        assert!(tree.is_empty());
+    }
+
+    #[test]
+    fn test_len() {
+        let mut tree: AvlTree<Position> = AvlTree::new();
+        assert_eq!(tree.len(), 0);
+        for x in 0..40 {
+            tree.insert(Position { x: (x * 7) % 40, y: 0 }).expect("Failed insert");
+            tree.insert(Position { x: (x * 7) % 40, y: 1 }).expect("Failed insert");
+            assert_eq!(tree.len(), tree.count());
+            assert!(tree.is_correct());
+        }
+        assert_eq!(tree.len(), 80);
+        assert!(tree.insert(Position { x: 3, y: 1 }).is_err());
+        assert_eq!(tree.len(), 80);
+        for x in 0..40 {
+            tree.remove(&Position { x: (x * 11) % 40, y: x % 2 }).expect("Failed remove");
+            assert_eq!(tree.len(), tree.count());
+            assert!(tree.is_correct());
+        }
+        assert_eq!(tree.len(), 40);
+        for x in 0..40 {
+            tree.remove(&Position { x, y: 1 - x % 2 }).expect("Failed remove");
+            assert_eq!(tree.len(), tree.count());
+            assert!(tree.is_correct());
+        }
+        assert!(tree.is_empty());
+    }
+
+    #[test]
+    fn test_rank_select() {
+        let mut tree: AvlTree<u64> = AvlTree::new();
+        for value in (1..=50).rev() {
+            tree.insert(value * 10).expect("Failed insert");
+        }
+        for (index, value) in tree.iter().enumerate() {
+            assert_eq!(tree.rank(value), index);
+            assert_eq!(tree.select(index), Some(value));
+        }
+        assert_eq!(tree.rank(&0), 0);
+        assert_eq!(tree.rank(&15), 1);
+        assert_eq!(tree.rank(&1000), 50);
+        assert_eq!(tree.select(50), None);
+    }
+
+    #[test]
+    fn test_rank_select_duplicates() {
+        let mut tree: AvlTree<Position> = AvlTree::new();
+        let _res = tree.insert(Position { x: 1, y: 0 }).expect("Failed insert")
+            .insert(Position { x: 2, y: 0 }).expect("Failed insert")
+            .insert(Position { x: 5, y: 1 }).expect("Failed insert")
+            .insert(Position { x: 5, y: 2 }).expect("Failed insert")
+            .insert(Position { x: 5, y: 3 }).expect("Failed insert")
+            .insert(Position { x: 8, y: 0 }).expect("Failed insert");
+        assert_eq!(tree.rank(&Position { x: 5, y: -1 }), 2);
+        assert_eq!(tree.rank(&Position { x: 6, y: -1 }), 5);
+        assert_eq!(tree.rank(&Position { x: 8, y: 0 }), 5);
+        let selected: Vec<&Position> = (0..tree.len()).map(|index| tree.select(index).unwrap()).collect();
+        let walked: Vec<&Position> = tree.iter().collect();
+        assert_eq!(selected, walked);
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut tree: AvlTree<Position> = AvlTree::new();
+        assert_eq!(tree.remove(&Position { x: 1, y: 0 }).unwrap_err(), AvlError::Empty);
+        assert_eq!(tree.dump(false).unwrap_err(), AvlError::Empty);
+        assert_eq!(tree.find(&Position { x: 1, y: 0 }).unwrap_err(), AvlError::NotFound);
+        let _res = tree.insert(Position { x: 1, y: 0 }).expect("Failed insert")
+            .insert(Position { x: 2, y: 0 }).expect("Failed insert")
+            .insert(Position { x: 2, y: 1 }).expect("Failed insert")
+            .insert(Position { x: 3, y: 0 }).expect("Failed insert");
+        assert_eq!(tree.insert(Position { x: 2, y: 1 }).unwrap_err(), AvlError::DuplicateValue);
+        let before: AvlTree<Position> = tree.clone();
+        assert_eq!(tree.remove(&Position { x: 2, y: 2 }).unwrap_err(), AvlError::NotFound);
+        assert_eq!(tree.remove(&Position { x: 4, y: 0 }).unwrap_err(), AvlError::NotFound);
+        assert_eq!(tree, before);
     }
 
     #[test]
     fn test_remove_duplicates() {
         let mut tree: AvlTree<Position> = AvlTree::new();
-        let _res = tree.insert(&Position { x: 1, y: 0 }).expect("Failed insert")
-            .insert(&Position { x: 2, y: 0 }).expect("Failed insert")
-            .insert(&Position { x: 3, y: 0 }).expect("Failed insert")
-            .insert(&Position { x: 5, y: 1 }).expect("Failed insert")
-            .insert(&Position { x: 5, y: 2 }).expect("Failed insert")
-            .insert(&Position { x: 5, y: 3 }).expect("Failed insert")
-            .insert(&Position { x: 5, y: 4 }).expect("Failed insert")
-            .insert(&Position { x: 5, y: 5 }).expect("Failed insert");
+        let _res = tree.insert(Position { x: 1, y: 0 }).expect("Failed insert")
+            .insert(Position { x: 2, y: 0 }).expect("Failed insert")
+            .insert(Position { x: 3, y: 0 }).expect("Failed insert")
+            .insert(Position { x: 5, y: 1 }).expect("Failed insert")
+            .insert(Position { x: 5, y: 2 }).expect("Failed insert")
+            .insert(Position { x: 5, y: 3 }).expect("Failed insert")
+            .insert(Position { x: 5, y: 4 }).expect("Failed insert")
+            .insert(Position { x: 5, y: 5 }).expect("Failed insert");
         assert_eq!(tree.depth(), 3);
         let res=tree.remove(&Position { x: 5, y: -1 });
         assert!(res.is_err());
-        assert_eq!(res.unwrap_err(), "The value was not found");
+        assert_eq!(res.unwrap_err(), AvlError::NotFound);
         assert_eq!(tree.depth(), 3);
         let res=tree.remove(&Position { x: 5, y: 1 });
         assert!(res.is_ok());
@@ -373,49 +990,130 @@ mod test_tree {
     }
 }
 
+impl<T, C: Default, S: Summary<T>, B: Backend<T, S>> Default for AvlTree<T, C, S, B> {
+    fn default() -> Self {
+        Self::with_storage(B::default(), C::default(), DuplicatePolicy::default())
+    }
+}
+
+/// The values are sorted then the tree is built in linear time, a value given twice (by Eq) is only kept once
+impl<T: Eq, C: Comparator<T> + Default, S: Summary<T>, B: Backend<T, S>> FromIterator<T> for AvlTree<T, C, S, B> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut tree: Self = Self::default();
+        tree.extend(iter);
+        tree
+    }
+}
+
+/// The values refused by the policy of the tree are dropped, like with insert
+impl<T: Eq, C: Comparator<T>, S: Summary<T>, B: Backend<T, S>> Extend<T> for AvlTree<T, C, S, B> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let mut values: Vec<T> = iter.into_iter().collect();
+        let len: usize = self.len();
+        // each insert costs about the height of the tree, rebuilding costs the whole tree plus sorting the new values
+        let height: usize = (usize::BITS - (len + values.len()).leading_zeros()) as usize;
+        if values.len() * height <= len {
+            for value in values {
+                let _ = self.insert(value);
+            }
+            return;
+        }
+        let cmp: &C = &self.cmp;
+        values.sort_by(|left: &T, right: &T| cmp.compare(left, right));
+        self.merge_sorted(values.into_iter().map(Node::create_node).collect());
+    }
+}
+
 /// This is a balanced tree implementation (also called as AVL tree)
 /// We allow you to define a custom type T to pass a the tree payload
-/// This payload should have the clone, ord, eq, debug and display trait derived
 /// The Ord trait is used to insert the values and to get it (this allow to match only partial payload)
-/// The Eq trait is used to remove a node
-/// The Clone trait is used to copy the value inside the tree
-/// The Display trait is used to print/dump the tree
-impl<'a, T: 'a + Clone + Ord + Eq + Debug + Display + Hash> AvlTree<T> {
+/// The Eq trait (that comes with Ord) is used to remove a node
+/// Everything that only walks or measures the tree does not need any trait
+impl<T> AvlTree<T> {
     /// Create a new tree (a tree is defined as an optional heap pointer to a Node of type T)
     /// By default we made a wrapper around the internal implementation with a root node
+    /// The values are ordered by their Ord trait, see with_comparator for any other order
     pub fn new() -> Self {
-        AvlTree {
-            root: None
-        }
+        Self::with_comparator(Natural)
+    }
+
+    /// Create a new tree ordered by Ord which handles the values sharing a key as the policy says
+    pub fn with_policy(policy: DuplicatePolicy) -> Self {
+        Self::with_comparator_and_policy(Natural, policy)
     }
 
     /// Create a new tree (a tree is defined as an optional heap pointer to a Node of type T)
-    /// with a default value, all value passed are cloned and should implement Clone, Ord, Eq and Debug
+    /// with a default value
     /// There is an example of such Payload creation in integration_avl
-    pub fn with(value: &T) -> Self {
+    pub fn with(value: T) -> Self {
+        let mut tree: Self = Self::new();
+        tree.root = Node::create_tree(&mut tree.storage, value);
+        tree
+    }
+}
+
+impl<T, C> AvlTree<T, C> {
+    /// Create a new tree ordered by the comparator instead of Ord, it can be any closure comparing two values
+    /// The comparator is used for every insertion, lookup and removal, Eq is still used to tell apart the duplicates
+    pub fn with_comparator(cmp: C) -> Self {
+        Self::with_comparator_and_policy(cmp, DuplicatePolicy::default())
+    }
+
+    /// Create a new tree ordered by the comparator which handles the values sharing a key as the policy says
+    pub fn with_comparator_and_policy(cmp: C, policy: DuplicatePolicy) -> Self {
+        Self::with_comparator_policy_and_summary(cmp, policy)
+    }
+}
+
+/// A tree keeping the summary S of every subtree to aggregate ranges of values (see Summary)
+impl<T, S: Summary<T>> AvlTree<T, Natural, S> {
+    /// Create a new tree ordered by Ord keeping the summary S of every subtree
+    pub fn with_summary() -> Self {
+        Self::with_comparator_policy_and_summary(Natural, DuplicatePolicy::default())
+    }
+}
+
+impl<T, C, S: Summary<T>> AvlTree<T, C, S> {
+    /// Create a new tree ordered by the comparator, handling the duplicates as the policy says and keeping the summary S of every subtree
+    pub fn with_comparator_policy_and_summary(cmp: C, policy: DuplicatePolicy) -> Self {
+        Self::with_storage(Boxes::default(), cmp, policy)
+    }
+}
+
+/// The constructors above keep the nodes in Boxes, with_storage takes any other storage (see Arena)
+impl<T, C, S: Summary<T>, B: Backend<T, S>> AvlTree<T, C, S, B> {
+    /// Create a new tree keeping its nodes in the given empty storage, ordered by the comparator and handling the duplicates as the policy says
+    pub fn with_storage(storage: B, cmp: C, policy: DuplicatePolicy) -> Self {
         AvlTree {
-            root: Node::create_tree(value)
+            root: B::empty(),
+            storage,
+            cmp,
+            policy,
+            marker: PhantomData,
         }
     }
 
-    /// Insert a value in the tree and return itself if no errors (by default we allow duplicate key value (using Eq trait, not Ord)
-    /// You can chain multiple insert
-    pub fn insert(&mut self, value: &T) -> Result<&mut Self, &str> {
-        if self.root.is_none() {
-            self.root = Node::create_tree(value)
-        } else {
-            if !self.root.as_mut().unwrap().insert(value.clone()) {
-                return Err("Can not insert same value twice");
-            }
-        }
-        Ok(&mut *self)
+    /// Get the comparator ordering the tree
+    pub fn comparator(&self) -> &C {
+        &self.cmp
+    }
+
+    /// Get the policy used for the values sharing a key
+    pub fn policy(&self) -> DuplicatePolicy {
+        self.policy
+    }
+
+    /// Draw the tree top-down on the given number of columns, print the result to see it (the values need Display)
+    /// The drawing can show the heights and balance factors and stop at a given depth, see Ascii
+    pub fn ascii(&self, width: usize) -> Ascii<'_, T, C, S, B> {
+        Ascii::new(self, width)
     }
 
     /// Delete all the values in the tree, the structure holding the tree should theoretically not be reused
     /// This is effectively the same as clear()
     pub fn delete(mut self) -> () {
-        if !self.root.is_none() {
-            Node::delete(self.root.borrow_mut())
+        if !self.is_empty() {
+            self.storage.clear(&mut self.root)
         }
         drop(self);
     }
@@ -423,106 +1121,56 @@ impl<'a, T: 'a + Clone + Ord + Eq + Debug + Display + Hash> AvlTree<T> {
     /// Delete all the values in the tree, the structure holding the tree can be reused afterwards
     /// This is effectively the same as delete()
     pub fn clear(&mut self) -> () {
-        if !self.root.is_none() {
-            Node::delete(self.root.borrow_mut())
-        }
-    }
-    /// Remove a value from the tree and return itself if was successful else return an error
-    /// Warning we use Eq to remove the correct value, if you don't know all the fields, use the get which use Ord only
-    /// See integration for an example.
-    pub fn remove(&mut self, value: &T) -> Result<&mut Self, &str> {
-        if self.root.is_none() {
-            Err("You don't have any node in the tree")
-        } else {
-            let res: Option<T> = Node::remove(&mut self.root, value);
-            if res.is_none() {
-                Err("The value was not found")
-            } else if &res.unwrap() != value {
-                Err("ERROR ! The value removed was not the correct one.")
-            } else {
-                Ok(&mut *self)
-            }
+        if !self.is_empty() {
+            self.storage.clear(&mut self.root)
         }
     }
 
-    /// Print the tree as a JSON formatted string,
-    /// It's possible to prettify it with the boolean
-    pub fn print(&self, prettify: bool) -> () {
-        if self.root.is_none() {
-            println!("You don't have any node in the tree");
-        } else {
-            println!("{}", self.root.as_ref().unwrap().dump(prettify));
-        }
+    /// Check if the tree is empty or not
+    pub fn is_empty(&self) -> bool {
+        self.storage.root(&self.root).is_none()
     }
 
-    /// Dump the tree as a JSON formatted string,
-    /// It's possible to prettify it with the boolean
-    pub fn dump(&self, prettify: bool) -> Result<String, &str> {
-        if self.root.is_none() {
-            Err("You don't have any node in the tree")
-        } else {
-            Ok(self.root.as_ref().unwrap().dump(prettify))
-        }
+    /// Iterate over the values of the tree in ascending order (by Ord), this can be walked from both ends
+    /// Values sharing the same key (the duplicates) are yielded next to each other
+    pub fn iter(&self) -> Iter<'_, T, S, B> {
+        Iter::new(self)
     }
 
-    /// Get a value based only on Ord (not Eq), this allow loosy check in case of complex payload
-    /// This return only the value or none, for a subtree see find
-    /// If duplicate keys this will return only the tree ordered first one
-    pub fn get(&self, value: &T) -> Option<T> {
-        let tree: &Tree<T> = Node::get(&self.root, value);
-        return tree.as_ref().map_or(None, |x| Some(x.value.clone()));
+    /// Take all the values out of the tree in ascending order (by Ord) without cloning them
+    /// The tree is left empty even if the iterator is not consumed entirely
+    pub fn drain(&mut self) -> Drain<'_, T, S, B> {
+        Drain::new(self)
     }
 
-    /// Get a value based only on Ord (not Eq), this allow loosy check in case of complex payload
-    /// This return only the value or none, for a subtree see find
-    /// If duplicate keys this will return the exact match if any else None
-    pub fn get_exact(&self, value: &T) -> Option<T> {
-        let set: HashSet<T> = self.get_set(value);
-        let value: Option<&T> = set.get(value);
-        return if value.is_none() { None } else { Some(value.unwrap().clone()) };
+    /// Get a cursor standing on the first value, to step through the tree in both directions
+    pub fn cursor_front(&self) -> Cursor<'_, T, S, B> {
+        Cursor::front(&self.storage, &self.root)
     }
 
-    /// Get the set of value based only on Ord (not Eq), this allow loosy check in case of complex payload
-    /// This return only the value or none, for a subtree see find
-    pub fn get_set(&self, value: &T) -> HashSet<T> {
-        let tree: &Tree<T> = Node::get(&self.root, value);
-        return if tree.is_none() {
-            HashSet::new()
-        } else {
-            let mut set: HashSet<T> = tree.as_ref().unwrap().duplicates.clone();
-            set.insert(tree.as_ref().unwrap().value.clone());
-            set
-        };
+    /// Get a cursor standing on the last value
+    pub fn cursor_back(&self) -> Cursor<'_, T, S, B> {
+        Cursor::back(&self.storage, &self.root)
     }
 
-
-    /// Find a value and return it as the subtree (equivalent as get but allow to chain operation on the subtree)
-    /// Warning this is Ord based so if there is duplicate you might get the wrong value here but you can check the duplicates to find it
-    pub fn find(&self, value: &T) -> Result<Self, &str> {
-        let tree: &Tree<T> = Node::get(&self.root, value);
-        if tree.is_none() {
-            return Err("Not found");
-        }
-        return Ok(AvlTree {
-            root: tree.clone()
-        });
+    /// Get a cursor standing on the first value whose key is not lower than the key (on the ghost position if there is none)
+    pub fn cursor_at<Q: ?Sized>(&self, key: &Q) -> Cursor<'_, T, S, B> where T: Borrow<Q>, C: Comparator<Q> {
+        Cursor::at(&self.storage, &self.root, key, &self.cmp)
     }
 
-
-    /// Check if a value is contained in the tree with Ord trait only
-    pub fn contains(&self, value: &T) -> bool {
-        Node::get(&self.root, value).is_some()
+    /// Same as cursor_front but the cursor can also insert and remove values
+    pub fn cursor_front_mut(&mut self) -> CursorMut<'_, T, C, S, B> {
+        CursorMut::front(self)
     }
 
-    /// Check if a value is contained in the tree with Eq trait
-    pub fn contains_exact(&self, value: &T) -> bool {
-        let res: HashSet<T> = self.get_set(value);
-        return res.contains(value);
+    /// Same as cursor_back but the cursor can also insert and remove values
+    pub fn cursor_back_mut(&mut self) -> CursorMut<'_, T, C, S, B> {
+        CursorMut::back(self)
     }
 
-    /// Check if the tree is empty or not
-    pub fn is_empty(&self) -> bool {
-        self.root.is_none()
+    /// Same as cursor_at but the cursor can also insert and remove values
+    pub fn cursor_at_mut<Q: ?Sized>(&mut self, key: &Q) -> CursorMut<'_, T, C, S, B> where T: Borrow<Q>, C: Comparator<Q> {
+        CursorMut::at(self, key)
     }
 
     /// Failing case for test
@@ -537,52 +1185,524 @@ impl<'a, T: 'a + Clone + Ord + Eq + Debug + Display + Hash> AvlTree<T> {
 
     /// Return the height of the tree as a fast heuristic (this could be wrong if you tampered the tree)
     pub fn height(&self) -> usize {
-        self.root.as_ref().map_or(0, |node: &Box<Node<T>>| node.height())
+        self.storage.height(&self.root)
     }
 
     /// Return the true depth of the tree by going through all the nodes
     pub fn depth(&self) -> usize {
-        self.root.as_ref().map_or(0, |node: &Box<Node<T>>| node.depth())
+        self.storage.root(&self.root).map_or(0, |node: &Node<T, S, B>| node.depth(&self.storage))
     }
+
     /// Get the number of leaves, a leave is a node which has one or more children missing (so a node with only one child is a leave also)
     ///     1          1
     ///      \        / \
     ///       2      2   3
     /// Those have a width of 2
     pub fn width(&self) -> usize {
-        self.root.as_ref().map_or(0, |node: &Box<Node<T>>| node.width())
+        self.storage.root(&self.root).map_or(0, |node: &Node<T, S, B>| node.width(&self.storage))
+    }
+
+    /// Get the number of nodes in the tree by going through all the nodes
+    pub fn count(&self) -> usize {
+        self.storage.root(&self.root).map_or(0, |node: &Node<T, S, B>| node.count(&self.storage))
+    }
+
+    /// Get the number of values in the tree (duplicates included) as stored in the root, this is a fast version of count
+    pub fn len(&self) -> usize {
+        self.storage.root(&self.root).map_or(0, |node: &Node<T, S, B>| node.size)
+    }
+
+    /// Get the value at the given position in the in order walk (starting at 0), duplicates are counted
+    pub fn select(&self, index: usize) -> Option<&T> {
+        Node::select(&self.storage, &self.root, index)
+    }
+
+    /// Get a reference to the minimum of the tree (or the left most), this is min without the copy
+    pub fn first(&self) -> Option<&T> {
+        self.storage.root(&self.root).map(|node: &Node<T, S, B>| node.min(&self.storage))
+    }
+
+    /// Get a reference to the maximum of the tree (or the right most), this is max without the copy
+    pub fn last(&self) -> Option<&T> {
+        self.storage.root(&self.root).map(|node: &Node<T, S, B>| node.max(&self.storage))
+    }
+
+    /// Check if the tree is balanced (this is quite intensive but gave correct result)
+    pub fn is_balanced(&self) -> bool {
+        self.storage.root(&self.root).map_or(true, |node: &Node<T, S, B>| node.is_balanced(&self.storage))
+    }
+
+    /// Check if the heights and sizes are correct (might have not be registered correctly, this is a soft check)
+    pub fn is_correct(&self) -> bool {
+        self.storage.root(&self.root).map_or(true, |node: &Node<T, S, B>| node.sanity_check(&self.storage))
+    }
+}
+
+/// The lookups, insertions and removals only need the comparator (Ord by default) and Eq
+impl<T: Eq, C: Comparator<T>, S: Summary<T>, B: Backend<T, S>> AvlTree<T, C, S, B> {
+    /// Insert a value in the tree and return itself if no errors (by default we allow duplicate key value (using Eq trait, not Ord)
+    /// What happens to a value whose key is already in the tree depends on the DuplicatePolicy of the tree
+    /// You can chain multiple insert
+    pub fn insert(&mut self, value: T) -> Result<&mut Self, AvlError> {
+        if self.is_empty() {
+            self.root = Node::create_tree(&mut self.storage, value)
+        } else {
+            if !Node::insert(&mut self.storage, B::node(&mut self.root), value, &self.cmp, self.policy) {
+                return Err(self.policy.refusal());
+            }
+        }
+        Ok(&mut *self)
+    }
+
+    /// Merge sorted nodes (without children) in the tree by rebuilding it in linear time, the values refused by the policy are dropped
+    /// The values already in the tree come before the new ones sharing their key
+    fn merge_sorted(&mut self, incoming: Vec<Node<T, S, B>>) {
+        let mut existing: Vec<Node<T, S, B>> = Vec::with_capacity(self.len());
+        let root: B::Link = replace(&mut self.root, B::empty());
+        Node::into_sorted_nodes(&mut self.storage, root, &mut existing);
+        let mut nodes: Vec<Node<T, S, B>> = Vec::with_capacity(existing.len() + incoming.len());
+        let mut existing = existing.into_iter().peekable();
+        for node in incoming {
+            while let Some(lower) = existing.next_if(|lower: &Node<T, S, B>| self.cmp.compare(&lower.value, &node.value) != Ordering::Greater) {
+                nodes.push(lower);
+            }
+            let copies: usize = node.copies;
+            for value in once(node.value).chain(node.duplicates) {
+                Node::push_sorted(&mut nodes, value, &self.cmp, self.policy);
+            }
+            if self.policy == DuplicatePolicy::Count {
+                nodes.last_mut().unwrap().copies += copies;
+            }
+        }
+        nodes.extend(existing);
+        let count: usize = nodes.len();
+        self.root = Node::from_sorted_nodes(&mut self.storage, &mut nodes.into_iter(), count);
+    }
+
+    /// Move all the values of the other tree in this one, the other tree is left empty
+    /// When all the keys of one tree are lower than the keys of the other this is a join in O(log n),
+    /// else the values are merged in O(n) with the values of this tree first for a shared key (as the policy of this tree says)
+    /// The nodes of an arena tree are moved to the arena of this tree first, in O(m) for the m values of the other tree
+    pub fn append(&mut self, other: &mut Self) {
+        let other_root: B::Link = replace(&mut other.root, B::empty());
+        let other_root: B::Link = self.storage.adopt(&mut other.storage, other_root);
+        let (ours, theirs): (&Node<T, S, B>, &Node<T, S, B>) = match (self.storage.root(&self.root), self.storage.root(&other_root)) {
+            (_, None) => return,
+            (None, Some(_)) => {
+                self.root = other_root;
+                return;
+            }
+            (Some(ours), Some(theirs)) => (ours, theirs),
+        };
+        if self.cmp.compare(ours.max(&self.storage), theirs.min(&self.storage)) == Ordering::Less {
+            let root: B::Link = replace(&mut self.root, B::empty());
+            self.root = Node::join_trees(&mut self.storage, root, other_root);
+        } else if self.cmp.compare(theirs.max(&self.storage), ours.min(&self.storage)) == Ordering::Less {
+            let root: B::Link = replace(&mut self.root, B::empty());
+            self.root = Node::join_trees(&mut self.storage, other_root, root);
+        } else {
+            let mut nodes: Vec<Node<T, S, B>> = Vec::with_capacity(theirs.size);
+            Node::into_sorted_nodes(&mut self.storage, other_root, &mut nodes);
+            self.merge_sorted(nodes);
+        }
+    }
+
+    /// Move the values with a key greater or equal to the given one in a new tree, the lower ones stay in this tree
+    /// This is O(log n), the new tree has the same comparator and policy
+    /// The nodes of an arena tree are then moved to the arena of the new tree, in O(m) for the m values moved
+    pub fn split_off<Q: ?Sized>(&mut self, key: &Q) -> Self where T: Borrow<Q>, C: Comparator<Q> + Clone {
+        let root: B::Link = replace(&mut self.root, B::empty());
+        let (lower, equal, greater) = Node::split(&mut self.storage, root, key, &self.cmp);
+        self.root = lower;
+        let greater: B::Link = match equal {
+            None => greater,
+            Some(equal) => Node::join(&mut self.storage, B::empty(), equal, greater),
+        };
+        let mut tree: Self = Self::with_storage(B::default(), self.cmp.clone(), self.policy);
+        tree.root = tree.storage.adopt(&mut self.storage, greater);
+        tree
+    }
+
+    /// Join two trees and a pivot value into one, all the keys of the left tree must be lower than the key of the pivot
+    /// and all the keys of the right tree greater, else AvlError::Overlap is returned
+    /// This is O(1 + difference of height of the trees), the comparator and policy of the left tree are kept
+    /// The nodes of the right tree of an arena are moved to the arena of the left one first, in O(m) for its m values
+    pub fn join(left: Self, pivot: T, right: Self) -> Result<Self, AvlError> {
+        let lower: bool = left.last().map_or(true, |last: &T| left.cmp.compare(last, &pivot) == Ordering::Less);
+        let greater: bool = right.first().map_or(true, |first: &T| left.cmp.compare(&pivot, first) == Ordering::Less);
+        if !lower || !greater {
+            return Err(AvlError::Overlap);
+        }
+        let AvlTree { root, mut storage, cmp, policy, .. } = left;
+        let AvlTree { root: right_root, storage: mut right_storage, .. } = right;
+        let right_root: B::Link = storage.adopt(&mut right_storage, right_root);
+        let mut tree: Self = Self::with_storage(storage, cmp, policy);
+        tree.root = Node::join(&mut tree.storage, root, Node::create_node(pivot), right_root);
+        Ok(tree)
+    }
+
+    /// Get the entry of the key of the value, to read, update or remove what the tree holds for that key or to insert the value
+    /// The key is compared on a single descent whose nodes the entry holds until it is dropped or consumed, see Entry
+    pub fn entry(&mut self, value: T) -> Entry<'_, T, C, S, B> {
+        Entry::new(self, value)
+    }
+
+    /// Iterate over the values of this tree and of the other one in ascending order, a value found (by Eq) in both is yielded once
+    /// For a key found in both trees the values of this tree come first, no DuplicatePolicy is applied (see into_union)
+    /// Both trees are walked with the comparator of this tree
+    pub fn union<'a>(&'a self, other: &'a Self) -> Union<'a, T, C, S, B> {
+        Union::new(self, other)
+    }
+
+    /// Iterate over the values of this tree also found (by Eq) in the other one in ascending order
+    pub fn intersection<'a>(&'a self, other: &'a Self) -> Intersection<'a, T, C, S, B> {
+        Intersection::new(self, other)
+    }
+
+    /// Iterate over the values of this tree not found (by Eq) in the other one in ascending order
+    pub fn difference<'a>(&'a self, other: &'a Self) -> Difference<'a, T, C, S, B> {
+        Difference::new(self, other)
+    }
+
+    /// Iterate over the values found (by Eq) in only one of the trees in ascending order
+    /// For a key found in both trees the values of this tree come first
+    pub fn symmetric_difference<'a>(&'a self, other: &'a Self) -> SymmetricDifference<'a, T, C, S, B> {
+        SymmetricDifference::new(self, other)
+    }
+
+    /// Build the union of the trees by splitting and joining their nodes, this is O(m log(n / m + 1)) with m the size of the smaller tree
+    /// For a key found in both trees the values of the other tree missing (by Eq) from this tree are added as the policy of this tree says
+    /// The result keeps the comparator and the policy of this tree, a key keeps its counted insertions from this tree
+    pub fn into_union(self, other: Self) -> Self {
+        let policy: DuplicatePolicy = self.policy;
+        self.combine(other, [true, true], |mut ours: Node<T, S, B>, theirs: Node<T, S, B>| {
+            for value in theirs.into_values() {
+                if !ours.holds(&value) {
+                    ours.insert_duplicate(value, policy);
+                }
+            }
+            Some(ours)
+        })
+    }
+
+    /// Build the tree of the values of this tree also found (by Eq) in the other one, in O(m log(n / m + 1)) as into_union
+    pub fn into_intersection(self, other: Self) -> Self {
+        let policy: DuplicatePolicy = self.policy;
+        self.combine(other, [false, false], |ours: Node<T, S, B>, theirs: Node<T, S, B>| {
+            let copies: usize = ours.copies;
+            let values: Vec<T> = ours.into_values().into_iter().filter(|value: &T| theirs.holds(value)).collect();
+            Node::from_values(values, copies, policy)
+        })
+    }
+
+    /// Build the tree of the values of this tree not found (by Eq) in the other one, in O(m log(n / m + 1)) as into_union
+    pub fn into_difference(self, other: Self) -> Self {
+        let policy: DuplicatePolicy = self.policy;
+        self.combine(other, [true, false], |ours: Node<T, S, B>, theirs: Node<T, S, B>| {
+            let copies: usize = ours.copies;
+            let values: Vec<T> = ours.into_values().into_iter().filter(|value: &T| !theirs.holds(value)).collect();
+            Node::from_values(values, copies, policy)
+        })
+    }
+
+    /// Build the tree of the values found (by Eq) in only one of the trees, in O(m log(n / m + 1)) as into_union
+    /// For a key found in both trees the values of this tree come first and are stored as the policy of this tree says
+    pub fn into_symmetric_difference(self, other: Self) -> Self {
+        let policy: DuplicatePolicy = self.policy;
+        self.combine(other, [true, true], |ours: Node<T, S, B>, theirs: Node<T, S, B>| {
+            let (ours_copies, theirs_copies): (usize, usize) = (ours.copies, theirs.copies);
+            let mut values: Vec<T> = ours.into_values();
+            let mut others: Vec<T> = theirs.into_values();
+            let mut shared = values.iter().map(|value: &T| others.contains(value)).collect::<Vec<bool>>().into_iter();
+            others.retain(|value: &T| !values.contains(value));
+            values.retain(|_| !shared.next().unwrap());
+            let copies: usize = if values.is_empty() { theirs_copies } else { ours_copies };
+            values.append(&mut others);
+            Node::from_values(values, copies, policy)
+        })
+    }
+
+    /// Merge the other tree in this one in O(m log(n / m + 1)) as into_union, the resolver settles each key found in both trees
+    /// It gets the values of this tree and of the other one sharing the key (each in their order) and returns the values to keep,
+    /// they must all share that key, an empty vector drops the key and the first value is stored before its duplicates (as the policy says)
+    /// The keys are not settled in order, with DuplicatePolicy::Count a kept key adds up the counted insertions of both trees
+    pub fn merge_with<F: FnMut(Vec<T>, Vec<T>) -> Vec<T>>(self, other: Self, mut resolver: F) -> Self {
+        let policy: DuplicatePolicy = self.policy;
+        self.combine(other, [true, true], |ours: Node<T, S, B>, theirs: Node<T, S, B>| {
+            let copies: usize = ours.copies + theirs.copies;
+            Node::from_values(resolver(ours.into_values(), theirs.into_values()), copies, policy)
+        })
+    }
+
+    /// Split this tree around the root of the other one, recurse on both sides and join back, see Node::merge
+    /// The nodes of the other tree of an arena are moved to the arena of this one first
+    fn combine<F: FnMut(Node<T, S, B>, Node<T, S, B>) -> Option<Node<T, S, B>>>(self, other: Self, keep: [bool; 2], mut resolve: F) -> Self {
+        let AvlTree { root, mut storage, cmp, policy, .. } = self;
+        let AvlTree { root: other_root, storage: mut other_storage, .. } = other;
+        let other_root: B::Link = storage.adopt(&mut other_storage, other_root);
+        let root: B::Link = Node::merge(&mut storage, root, other_root, &cmp, keep, &mut resolve);
+        let mut tree: Self = Self::with_storage(storage, cmp, policy);
+        tree.root = root;
+        tree
+    }
+
+    /// Remove a value from the tree and return itself if was successful else return an error
+    /// Warning we use Eq to remove the correct value, if you don't know all the fields, use the get which use Ord only
+    /// See integration for an example.
+    /// The tree is never modified when an error is returned
+    pub fn remove(&mut self, value: &T) -> Result<&mut Self, AvlError> {
+        if self.is_empty() {
+            return Err(AvlError::Empty);
+        }
+        // get_mut hands the pending updates of the path down (see Lazy) so the value is compared to the stored one as it is
+        match Node::get_mut(&mut self.storage, &mut self.root, value, &self.cmp).filter(|node: &&mut Node<T, S, B>| node.holds(value)) {
+            None => return Err(AvlError::NotFound),
+            // a counted insertion goes away before the value itself
+            Some(node) if node.copies > 0 => {
+                node.copies -= 1;
+                return Ok(&mut *self);
+            }
+            Some(_) => (),
+        }
+        // Node::remove only takes out a value equal (by Eq) to the one asked for and leaves the tree as is otherwise
+        match Node::remove(&mut self.storage, &mut self.root, value, &self.cmp) {
+            None => Err(AvlError::InconsistentRemoval),
+            Some(_) => Ok(&mut *self),
+        }
+    }
+
+    /// Remove the value matching the key (by Ord only) and return it, see remove to match with Eq
+    /// If duplicate keys this will take the tree ordered first one (the oldest one)
+    /// With DuplicatePolicy::Count the value is taken out with all its counted insertions
+    pub fn take<Q: ?Sized>(&mut self, key: &Q) -> Option<T> where T: Borrow<Q>, C: Comparator<Q> {
+        Node::take(&mut self.storage, &mut self.root, key, &self.cmp)
+    }
+
+    /// Get a reference to a value based only on Ord (not Eq), this is get without the copy
+    /// The key can be anything the values can be borrowed as (like a &str for a String)
+    /// If duplicate keys this will return only the tree ordered first one
+    pub fn get_ref<Q: ?Sized>(&self, key: &Q) -> Option<&T> where T: Borrow<Q>, C: Comparator<Q> {
+        Node::get(&self.storage, &self.root, key, &self.cmp).map(|node: &Node<T, S, B>| &node.value)
+    }
+
+    /// Get how many times the key is in the tree, this is the number of values sharing the key
+    /// or the number of insertions of the key with DuplicatePolicy::Count
+    pub fn occurrences<Q: ?Sized>(&self, key: &Q) -> usize where T: Borrow<Q>, C: Comparator<Q> {
+        Node::get(&self.storage, &self.root, key, &self.cmp).map_or(0, |node: &Node<T, S, B>| 1 + node.duplicates.len() + node.copies)
+    }
+
+    /// Check if a value is contained in the tree with Ord trait only
+    pub fn contains<Q: ?Sized>(&self, key: &Q) -> bool where T: Borrow<Q>, C: Comparator<Q> {
+        Node::get(&self.storage, &self.root, key, &self.cmp).is_some()
+    }
+
+    /// Check if a value is contained in the tree with Eq trait
+    pub fn contains_exact(&self, value: &T) -> bool {
+        Node::contains_exact(&self.storage, &self.root, value, &self.cmp)
+    }
+
+    /// Iterate over the values of the tree inside the range in ascending order (by Ord), this can be walked from both ends
+    /// The bounds are matched with Ord only so all the duplicates of a key in range are yielded
+    pub fn range<Q: ?Sized, R: RangeBounds<Q>>(&self, range: R) -> Range<'_, T, S, B> where T: Borrow<Q>, C: Comparator<Q> {
+        Range::new(self, range)
+    }
+
+    /// Get the position the value has (or would have) in the in order walk, this is the number of values strictly lower (by Ord)
+    /// If duplicate keys this is the position of the first one
+    pub fn rank<Q: ?Sized>(&self, key: &Q) -> usize where T: Borrow<Q>, C: Comparator<Q> {
+        Node::rank(&self.storage, &self.root, key, &self.cmp)
+    }
+
+    /// Get the value with the greatest key lower or equal to the key, in O(log n)
+    /// As get_ref this is the oldest of the values sharing that key
+    pub fn floor<Q: ?Sized>(&self, key: &Q) -> Option<&T> where T: Borrow<Q>, C: Comparator<Q> {
+        Node::nearest(&self.storage, &self.root, |value: &T| self.cmp.compare(key, value.borrow()), Side::Left, true).map(|node: &Node<T, S, B>| &node.value)
+    }
+
+    /// Get the value with the lowest key greater or equal to the key, in O(log n)
+    pub fn ceiling<Q: ?Sized>(&self, key: &Q) -> Option<&T> where T: Borrow<Q>, C: Comparator<Q> {
+        Node::nearest(&self.storage, &self.root, |value: &T| self.cmp.compare(key, value.borrow()), Side::Right, true).map(|node: &Node<T, S, B>| &node.value)
+    }
+
+    /// Get the value with the greatest key strictly lower than the key, in O(log n)
+    pub fn predecessor<Q: ?Sized>(&self, key: &Q) -> Option<&T> where T: Borrow<Q>, C: Comparator<Q> {
+        Node::nearest(&self.storage, &self.root, |value: &T| self.cmp.compare(key, value.borrow()), Side::Left, false).map(|node: &Node<T, S, B>| &node.value)
+    }
+
+    /// Get the value with the lowest key strictly greater than the key, in O(log n)
+    pub fn successor<Q: ?Sized>(&self, key: &Q) -> Option<&T> where T: Borrow<Q>, C: Comparator<Q> {
+        Node::nearest(&self.storage, &self.root, |value: &T| self.cmp.compare(key, value.borrow()), Side::Right, false).map(|node: &Node<T, S, B>| &node.value)
+    }
+
+    /// Get the summary of all the values of the tree (the identity when empty), in O(1)
+    pub fn summary(&self) -> S {
+        self.storage.root(&self.root).map_or_else(S::identity, |node: &Node<T, S, B>| node.summary.clone())
+    }
+
+    /// Get the summary of the values inside the range in ascending order, in O(log n) as the subtrees fully inside are not walked
+    /// The bounds are matched as range does so all the duplicates of a key in range are summarized
+    pub fn aggregate<Q: ?Sized, R: RangeBounds<Q>>(&self, range: R) -> S where T: Borrow<Q>, C: Comparator<Q> {
+        Node::aggregate(&self.storage, &self.root, range.start_bound(), range.end_bound(), &self.cmp)
+    }
+}
+
+/// The Clone trait is used to return copies of the values (or of a subtree)
+impl<T: Eq + Clone, C: Comparator<T>, S: Summary<T>, B: Backend<T, S>> AvlTree<T, C, S, B> {
+    /// Get a value based only on Ord (not Eq), this allow loosy check in case of complex payload
+    /// This return only the value or none, for a subtree see find
+    /// If duplicate keys this will return only the tree ordered first one
+    pub fn get<Q: ?Sized>(&self, key: &Q) -> Option<T> where T: Borrow<Q>, C: Comparator<Q> {
+        let node: Option<&Node<T, S, B>> = Node::get(&self.storage, &self.root, key, &self.cmp);
+        return node.map_or(None, |x| Some(x.value.clone()));
+    }
+
+    /// Get a value based only on Ord (not Eq), this allow loosy check in case of complex payload
+    /// This return only the value or none, for a subtree see find
+    /// If duplicate keys this will return the exact match if any else None
+    pub fn get_exact(&self, value: &T) -> Option<T> {
+        Node::get_exact(&self.storage, &self.root, value, &self.cmp).cloned()
+    }
+
+    /// Find a value and return it as the subtree (equivalent as get but allow to chain operation on the subtree)
+    /// Warning this is Ord based so if there is duplicate you might get the wrong value here but you can check the duplicates to find it
+    pub fn find<Q: ?Sized>(&self, key: &Q) -> Result<Self, AvlError> where T: Borrow<Q>, C: Comparator<Q> + Clone {
+        let node: Option<&Node<T, S, B>> = Node::get(&self.storage, &self.root, key, &self.cmp);
+        if node.is_none() {
+            return Err(AvlError::NotFound);
+        }
+        let mut tree: Self = Self::with_storage(B::default(), self.cmp.clone(), self.policy);
+        tree.root = node.unwrap().copy(&self.storage, &mut tree.storage);
+        return Ok(tree);
+    }
+
+    /// Get the minimum of the tree (or the left most)
+    pub fn min(&self) -> Option<T> {
+        self.storage.root(&self.root).map_or(None, |node: &Node<T, S, B>| Some(node.min(&self.storage).clone()))
+    }
+
+    /// Get the maximum of the tree (or the right most)
+    pub fn max(&self) -> Option<T> {
+        self.storage.root(&self.root).map_or(None, |node: &Node<T, S, B>| Some(node.max(&self.storage).clone()))
+    }
+}
+
+/// The Hash trait is only used to hand over the duplicates as a set
+impl<T: Eq + Clone + Hash, C: Comparator<T>, S: Summary<T>, B: Backend<T, S>> AvlTree<T, C, S, B> {
+    /// Get the set of value based only on Ord (not Eq), this allow loosy check in case of complex payload
+    /// This return only the value or none, for a subtree see find
+    pub fn get_set<Q: ?Sized>(&self, key: &Q) -> HashSet<T> where T: Borrow<Q>, C: Comparator<Q> {
+        let node: Option<&Node<T, S, B>> = Node::get(&self.storage, &self.root, key, &self.cmp);
+        return if node.is_none() {
+            HashSet::new()
+        } else {
+            let mut set: HashSet<T> = node.unwrap().duplicates.iter().cloned().collect();
+            set.insert(node.unwrap().value.clone());
+            set
+        };
+    }
+}
+
+/// Reading a dump or a sorted sequence back only needs the order to check it
+impl<T: Ord> AvlTree<T> {
+    /// Rebuild the exact tree written by dump (pretty or not), the heights and sizes are computed again
+    /// parse_value gets the text of each value as written by Display (like str::parse for numbers)
+    /// The dump is refused if a value breaks the order or a node breaks the balance, the error holds the byte position
+    pub fn from_dump<E, F: FnMut(&str) -> Result<T, E>>(dump: &str, parse_value: F) -> Result<Self, AvlError> {
+        Self::from_dump_with_comparator(dump, parse_value, Natural)
+    }
+
+    /// Build a perfectly balanced tree out of values sorted in ascending order in linear time (no rotation is needed)
+    /// The values sharing a key become the duplicates of the first one, the same value (by Eq) can not be given twice
+    /// The tree is not built if a value is lower than the one before it, the error holds its index
+    pub fn from_sorted_iter<I: IntoIterator<Item = T>>(iter: I) -> Result<Self, AvlError> {
+        Self::from_sorted_iter_with_comparator(iter, Natural)
+    }
+}
+
+impl<T, C: Comparator<T>> AvlTree<T, C> {
+    /// Same as from_dump for a tree ordered by the comparator
+    pub fn from_dump_with_comparator<E, F: FnMut(&str) -> Result<T, E>>(dump: &str, parse_value: F, cmp: C) -> Result<Self, AvlError> {
+        let mut tree: Self = Self::with_comparator(cmp);
+        tree.root = DumpParser::new(dump, parse_value).parse(&mut tree.storage, &tree.cmp)?;
+        Ok(tree)
+    }
+
+    /// Same as from_sorted_iter for values sorted by the comparator
+    pub fn from_sorted_iter_with_comparator<I: IntoIterator<Item = T>>(iter: I, cmp: C) -> Result<Self, AvlError> where T: Eq {
+        let mut tree: Self = Self::with_comparator(cmp);
+        let mut nodes: Vec<Node<T>> = Vec::new();
+        for (index, value) in iter.into_iter().enumerate() {
+            if let Some(last) = nodes.last() {
+                if tree.cmp.compare(&last.value, &value) == Ordering::Greater {
+                    return Err(AvlError::Unsorted(index));
+                }
+            }
+            if !Node::push_sorted(&mut nodes, value, &tree.cmp, tree.policy) {
+                return Err(tree.policy.refusal());
+            }
+        }
+        let count: usize = nodes.len();
+        tree.root = Node::from_sorted_nodes(&mut tree.storage, &mut nodes.into_iter(), count);
+        Ok(tree)
     }
+}
 
-    /// Get the number of nodes in the tree
-    pub fn count(&self) -> usize {
-        self.root.as_ref().map_or(0, |node: &Box<Node<T>>| node.count())
+/// The Display trait is used to print/dump the tree
+impl<T: Display, C, S: Summary<T>, B: Backend<T, S>> AvlTree<T, C, S, B> {
+    /// Print the tree as a JSON formatted string,
+    /// It's possible to prettify it with the boolean
+    pub fn print(&self, prettify: bool) -> () {
+        if self.is_empty() {
+            println!("You don't have any node in the tree");
+        } else {
+            println!("{}", self.storage.root(&self.root).unwrap().dump(&self.storage, prettify));
+        }
     }
 
-    /// Get the minimum of the tree (or the left most)
-    pub fn min(&self) -> Option<T> {
-        self.root.as_ref().map_or(None, |node: &Box<Node<T>>| Some(node.min().clone()))
+    /// Dump the tree as a JSON formatted string,
+    /// It's possible to prettify it with the boolean
+    pub fn dump(&self, prettify: bool) -> Result<String, AvlError> {
+        if self.is_empty() {
+            Err(AvlError::Empty)
+        } else {
+            Ok(self.storage.root(&self.root).unwrap().dump(&self.storage, prettify))
+        }
     }
 
-    /// Get the maximum of the tree (or the right most)
-    pub fn max(&self) -> Option<T> {
-        self.root.as_ref().map_or(None, |node: &Box<Node<T>>| Some(node.max().clone()))
+    /// Write the tree as a Graphviz digraph (render it with dot -Tsvg)
+    /// Each node shows its value, height, balance factor and number of duplicates, missing children are drawn as points
+    pub fn write_dot<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "digraph AvlTree {{")?;
+        writeln!(writer, "    node [shape=box];")?;
+        if let Some(root) = self.storage.root(&self.root) {
+            root.write_dot(&self.storage, writer, &mut 0)?;
+        }
+        writeln!(writer, "}}")
     }
 
-    /// Check if the tree is balanced (this is quite intensive but gave correct result)
-    pub fn is_balanced(&self) -> bool {
-        self.root.as_ref().map_or(true, |node: &Box<Node<T>>| node.is_balanced())
+    /// Draw the tree top-down on the given number of columns, see ascii for the options
+    pub fn render_ascii(&self, width: usize) -> String {
+        self.ascii(width).to_string()
     }
 
-    /// Check if the heights are correct (might have not be registered correctly, this is a soft check)
-    pub fn is_correct(&self) -> bool {
-        self.root.as_ref().map_or(true, |node: &Box<Node<T>>| node.sanity_check())
+    /// Get the tree as a Graphviz digraph, see write_dot
+    pub fn to_dot(&self) -> String {
+        let mut dot: Vec<u8> = Vec::new();
+        self.write_dot(&mut dot).expect("Writing in memory can not fail");
+        String::from_utf8(dot).expect("The dot output is built from strings")
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison, clippy::partialeq_to_none, clippy::clone_on_copy)]
 mod test_node {
     use super::*;
 
+    /// The boxed nodes hold their children themselves, any Boxes can be given to the calls
+    fn boxes<T, S>() -> Boxes<T, S> {
+        Boxes(PhantomData)
+    }
+
     const TEST_1: u64 = 42;
     const TEST_2: u64 = 420;
     const TEST_3: u64 = 66;
@@ -605,14 +1725,20 @@ mod test_node {
                 Some(Box::new(Node {
                     children: [None, None],
                     value: TEST_2,
-                    duplicates: HashSet::with_capacity(0),
+                    duplicates: Vec::new(),
+                    copies: 0,
+                    summary: (),
                     height: 1,
+                    size: 1,
                 })),
                 None
             ],
             value: TEST_1,
-            duplicates: HashSet::with_capacity(0),
+            duplicates: Vec::new(),
+            copies: 0,
+            summary: (),
             height: 2,
+            size: 2,
         }));
         let ref_tree: Option<&Box<Node<u64>>> = tree.as_ref();
         assert!(ref_tree.is_some());
@@ -632,71 +1758,98 @@ mod test_node {
                 Some(Box::new(Node {
                     children: [None, None],
                     value: TEST_2,
-                    duplicates: HashSet::with_capacity(0),
+                    duplicates: Vec::new(),
+                    copies: 0,
+                    summary: (),
                     height: 1,
+                    size: 1,
                 })),
                 Some(Box::new(Node {
                     children: [None, None],
                     value: TEST_3,
-                    duplicates: HashSet::with_capacity(0),
+                    duplicates: Vec::new(),
+                    copies: 0,
+                    summary: (),
                     height: 1,
+                    size: 1,
                 })),
             ],
             value: TEST_1,
-            duplicates: HashSet::with_capacity(0),
+            duplicates: Vec::new(),
+            copies: 0,
+            summary: (),
             height: 2,
+            size: 3,
         }));
         let ref_tree: Option<&Box<Node<u64>>> = tree.as_ref();
         assert!(ref_tree.is_some());
-        assert_eq!(ref_tree.unwrap().sanity_check(), true);
-        assert_eq!(ref_tree.unwrap().is_balanced(), true);
+        assert_eq!(ref_tree.unwrap().sanity_check(&boxes()), true);
+        assert_eq!(ref_tree.unwrap().is_balanced(&boxes()), true);
         let tree: Option<Box<Node<u64>>> = Some(Box::new(Node {
             children: [
                 Some(Box::new(Node {
                     children: [None, None],
                     value: TEST_2,
-                    duplicates: HashSet::with_capacity(0),
+                    duplicates: Vec::new(),
+                    copies: 0,
+                    summary: (),
                     height: 1,
+                    size: 1,
                 })),
                 Some(Box::new(Node {
                     children: [None, None],
                     value: TEST_3,
-                    duplicates: HashSet::with_capacity(0),
+                    duplicates: Vec::new(),
+                    copies: 0,
+                    summary: (),
                     height: 2,// incorrect height here (will fail sanity but not balanced)
+                    size: 1,
                 })),
             ],
             value: TEST_1,
-            duplicates: HashSet::with_capacity(0),
+            duplicates: Vec::new(),
+            copies: 0,
+            summary: (),
             height: 2,
+            size: 3,
         }));
         let ref_tree: Option<&Box<Node<u64>>> = tree.as_ref();
         assert!(ref_tree.is_some());
-        assert_eq!(ref_tree.unwrap().sanity_check(), false);
-        assert_eq!(ref_tree.unwrap().is_balanced(), true);
+        assert_eq!(ref_tree.unwrap().sanity_check(&boxes()), false);
+        assert_eq!(ref_tree.unwrap().is_balanced(&boxes()), true);
         let tree: Option<Box<Node<u64>>> = Some(Box::new(Node {
             children: [
                 Some(Box::new(Node {
                     children: [Some(Box::new(Node {
                         children: [None, None],
                         value: TEST_3,
-                        duplicates: HashSet::with_capacity(0),
+                        duplicates: Vec::new(),
+                        copies: 0,
+                        summary: (),
                         height: 1,
+                        size: 1,
                     })), None],
                     value: TEST_2,
-                    duplicates: HashSet::with_capacity(0),
+                    duplicates: Vec::new(),
+                    copies: 0,
+                    summary: (),
                     height: 2,
+                    size: 2,
                 })),
                 None,
             ],
             value: TEST_1,
-            duplicates: HashSet::with_capacity(0),
+            duplicates: Vec::new(),
+            copies: 0,
+            summary: (),
             height: 3,
+            size: 3,
         }));
         let ref_tree: Option<&Box<Node<u64>>> = tree.as_ref();
         assert!(ref_tree.is_some());
-        assert_eq!(ref_tree.unwrap().sanity_check(), true);
-        assert_eq!(ref_tree.unwrap().is_balanced(), false);
-        assert_eq!(ref_tree.unwrap().depth(), ref_tree.unwrap().height);
+        assert_eq!(ref_tree.unwrap().sanity_check(&boxes()), true);
+        assert_eq!(ref_tree.unwrap().is_balanced(&boxes()), false);
+        assert_eq!(ref_tree.unwrap().depth(&boxes()), ref_tree.unwrap().height);
 
         let tree: Option<Box<Node<u64>>> = Some(Box::new(Node {
             children: [
@@ -704,24 +1857,33 @@ mod test_node {
                     children: [Some(Box::new(Node {
                         children: [None, None],
                         value: TEST_3,
-                        duplicates: HashSet::with_capacity(0),
+                        duplicates: Vec::new(),
+                        copies: 0,
+                        summary: (),
                         height: 1,
+                        size: 1,
                     })), None],
                     value: TEST_2,
-                    duplicates: HashSet::with_capacity(0),
+                    duplicates: Vec::new(),
+                    copies: 0,
+                    summary: (),
                     height: 22121,
+                    size: 2,
                 })),
                 None,
             ],
             value: TEST_1,
-            duplicates: HashSet::with_capacity(0),
+            duplicates: Vec::new(),
+            copies: 0,
+            summary: (),
             height: 3,
+            size: 3,
         }));
         let ref_tree: Option<&Box<Node<u64>>> = tree.as_ref();
         assert!(ref_tree.is_some());
-        assert_eq!(ref_tree.unwrap().sanity_check(), false);
-        assert_eq!(ref_tree.unwrap().is_balanced(), false);
-        assert_eq!(ref_tree.unwrap().depth(), ref_tree.unwrap().height)
+        assert_eq!(ref_tree.unwrap().sanity_check(&boxes()), false);
+        assert_eq!(ref_tree.unwrap().is_balanced(&boxes()), false);
+        assert_eq!(ref_tree.unwrap().depth(&boxes()), ref_tree.unwrap().height)
     }
 
     #[test]
@@ -739,28 +1901,43 @@ mod test_node {
                     children: [Some(Box::new(Node {
                         children: [None, None],
                         value: TEST_4,
-                        duplicates: HashSet::with_capacity(0),
+                        duplicates: Vec::new(),
+                        copies: 0,
+                        summary: (),
                         height: 1,
+                        size: 1,
                     })), Some(Box::new(Node {
                         children: [None, None],
                         value: TEST_5,
-                        duplicates: HashSet::with_capacity(0),
+                        duplicates: Vec::new(),
+                        copies: 0,
+                        summary: (),
                         height: 1,
+                        size: 1,
                     }))],
                     value: TEST_2,
-                    duplicates: HashSet::with_capacity(0),
+                    duplicates: Vec::new(),
+                    copies: 0,
+                    summary: (),
                     height: 2,
+                    size: 3,
                 })),
                 Some(Box::new(Node {
                     children: [None, None],
                     value: TEST_3,
-                    duplicates: HashSet::with_capacity(0),
+                    duplicates: Vec::new(),
+                    copies: 0,
+                    summary: (),
                     height: 1,
+                    size: 1,
                 })),
             ],
             value: TEST_1,
-            duplicates: HashSet::with_capacity(0),
+            duplicates: Vec::new(),
+            copies: 0,
+            summary: (),
             height: 3,
+            size: 5,
         }));
         let ref_tree: &Box<Node<u64>> = tree.as_ref().unwrap();
         assert_eq!(ref_tree.value, TEST_1);
@@ -768,7 +1945,7 @@ mod test_node {
         assert_eq!(ref_tree.children[Side::Left as usize].as_ref().unwrap().children[Side::Left as usize].as_ref().unwrap().value, TEST_4);
         assert_eq!(ref_tree.children[Side::Left as usize].as_ref().unwrap().children[Side::Right as usize].as_ref().unwrap().value, TEST_5);
         assert_eq!(ref_tree.children[Side::Right as usize].as_ref().unwrap().value, TEST_3);
-        let res: bool = tree.as_mut().unwrap().rotate(Side::Right);
+        let res: bool = balance::rotate(&mut boxes(), tree.as_mut().unwrap(), Side::Right);
         assert!(res);
         let ref_tree: &Box<Node<u64>> = tree.as_ref().unwrap();
         assert_eq!(ref_tree.value, TEST_2);
@@ -792,29 +1969,44 @@ mod test_node {
                 Some(Box::new(Node {
                     children: [None, None],
                     value: TEST_2,
-                    duplicates: HashSet::with_capacity(0),
+                    duplicates: Vec::new(),
+                    copies: 0,
+                    summary: (),
                     height: 1,
+                    size: 1,
                 })),
                 Some(Box::new(Node {
                     children: [Some(Box::new(Node {
                         children: [None, None],
                         value: TEST_4,
-                        duplicates: HashSet::with_capacity(0),
+                        duplicates: Vec::new(),
+                        copies: 0,
+                        summary: (),
                         height: 1,
+                        size: 1,
                     })), Some(Box::new(Node {
                         children: [None, None],
                         value: TEST_5,
-                        duplicates: HashSet::with_capacity(0),
+                        duplicates: Vec::new(),
+                        copies: 0,
+                        summary: (),
                         height: 1,
+                        size: 1,
                     }))],
                     value: TEST_3,
-                    duplicates: HashSet::with_capacity(0),
+                    duplicates: Vec::new(),
+                    copies: 0,
+                    summary: (),
                     height: 2,
+                    size: 3,
                 })),
             ],
             value: TEST_1,
-            duplicates: HashSet::with_capacity(0),
+            duplicates: Vec::new(),
+            copies: 0,
+            summary: (),
             height: 3,
+            size: 5,
         }));
         let ref_tree: &Box<Node<u64>> = tree.as_ref().unwrap();
         assert_eq!(ref_tree.value, TEST_1);
@@ -822,7 +2014,7 @@ mod test_node {
         assert_eq!(ref_tree.children[Side::Right as usize].as_ref().unwrap().value, TEST_3);
         assert_eq!(ref_tree.children[Side::Right as usize].as_ref().unwrap().children[Side::Left as usize].as_ref().unwrap().value, TEST_4);
         assert_eq!(ref_tree.children[Side::Right as usize].as_ref().unwrap().children[Side::Right as usize].as_ref().unwrap().value, TEST_5);
-        let res: bool = tree.as_mut().unwrap().rotate(Side::Left);
+        let res: bool = balance::rotate(&mut boxes(), tree.as_mut().unwrap(), Side::Left);
         assert!(res);
         let ref_tree: &Box<Node<u64>> = tree.as_ref().unwrap();
         assert_eq!(ref_tree.value, TEST_3);
@@ -839,34 +2031,49 @@ mod test_node {
                 Some(Box::new(Node {
                     children: [None, None],
                     value: TEST_2,
-                    duplicates: HashSet::with_capacity(0),
+                    duplicates: Vec::new(),
+                    copies: 0,
+                    summary: (),
                     height: 1,
+                    size: 1,
                 })),
                 Some(Box::new(Node {
                     children: [Some(Box::new(Node {
                         children: [None, None],
                         value: TEST_4,
-                        duplicates: HashSet::with_capacity(0),
+                        duplicates: Vec::new(),
+                        copies: 0,
+                        summary: (),
                         height: 1,
+                        size: 1,
                     })), Some(Box::new(Node {
                         children: [None, None],
                         value: TEST_5,
-                        duplicates: HashSet::with_capacity(0),
+                        duplicates: Vec::new(),
+                        copies: 0,
+                        summary: (),
                         height: 1,
+                        size: 1,
                     }))],
                     value: TEST_3,
-                    duplicates: HashSet::with_capacity(0),
+                    duplicates: Vec::new(),
+                    copies: 0,
+                    summary: (),
                     height: 2,
+                    size: 3,
                 })),
             ],
             value: TEST_1,
-            duplicates: HashSet::with_capacity(0),
+            duplicates: Vec::new(),
+            copies: 0,
+            summary: (),
             height: 3,
+            size: 5,
         }));
 
-        let res: bool = tree.as_mut().unwrap().rotate(Side::Left);
+        let res: bool = balance::rotate(&mut boxes(), tree.as_mut().unwrap(), Side::Left);
         assert!(res);
-        let res: bool = tree.as_mut().unwrap().rotate(Side::Right);
+        let res: bool = balance::rotate(&mut boxes(), tree.as_mut().unwrap(), Side::Right);
         assert!(res);
         let ref_tree: &Box<Node<u64>> = tree.as_ref().unwrap();
         assert_eq!(ref_tree.value, TEST_1);
@@ -884,32 +2091,47 @@ mod test_node {
                     children: [Some(Box::new(Node {
                         children: [None, None],
                         value: TEST_4,
-                        duplicates: HashSet::with_capacity(0),
+                        duplicates: Vec::new(),
+                        copies: 0,
+                        summary: (),
                         height: 1,
+                        size: 1,
                     })), Some(Box::new(Node {
                         children: [None, None],
                         value: TEST_5,
-                        duplicates: HashSet::with_capacity(0),
+                        duplicates: Vec::new(),
+                        copies: 0,
+                        summary: (),
                         height: 1,
+                        size: 1,
                     }))],
                     value: TEST_2,
-                    duplicates: HashSet::with_capacity(0),
+                    duplicates: Vec::new(),
+                    copies: 0,
+                    summary: (),
                     height: 2,
+                    size: 3,
                 })),
                 Some(Box::new(Node {
                     children: [None, None],
                     value: TEST_3,
-                    duplicates: HashSet::with_capacity(0),
+                    duplicates: Vec::new(),
+                    copies: 0,
+                    summary: (),
                     height: 1,
+                    size: 1,
                 })),
             ],
             value: TEST_1,
-            duplicates: HashSet::with_capacity(0),
+            duplicates: Vec::new(),
+            copies: 0,
+            summary: (),
             height: 3,
+            size: 5,
         }));
-        let res: bool = tree.as_mut().unwrap().rotate(Side::Right);
+        let res: bool = balance::rotate(&mut boxes(), tree.as_mut().unwrap(), Side::Right);
         assert!(res);
-        let res: bool = tree.as_mut().unwrap().rotate(Side::Left);
+        let res: bool = balance::rotate(&mut boxes(), tree.as_mut().unwrap(), Side::Left);
         assert!(res);
         let ref_tree: &Box<Node<u64>> = tree.as_ref().unwrap();
         assert_eq!(ref_tree.value, TEST_1);
@@ -927,30 +2149,39 @@ mod test_node {
                     children: [Some(Box::new(Node {
                         children: [None, None],
                         value: TEST_3,
-                        duplicates: HashSet::with_capacity(0),
+                        duplicates: Vec::new(),
+                        copies: 0,
+                        summary: (),
                         height: 1,
+                        size: 1,
                     })), None],
                     value: TEST_2,
-                    duplicates: HashSet::with_capacity(0),
+                    duplicates: Vec::new(),
+                    copies: 0,
+                    summary: (),
                     height: 2,
+                    size: 2,
                 })),
                 None,
             ],
             value: TEST_1,
-            duplicates: HashSet::with_capacity(0),
+            duplicates: Vec::new(),
+            copies: 0,
+            summary: (),
             height: 3,
+            size: 3,
         }));
         let ref_tree: Option<&Box<Node<u64>>> = tree.as_ref();
         assert!(ref_tree.is_some());
-        assert_eq!(ref_tree.unwrap().sanity_check(), true);
-        assert_eq!(ref_tree.unwrap().is_balanced(), false);
-        assert_eq!(ref_tree.unwrap().depth(), ref_tree.unwrap().height);
-        let res: bool = tree.as_mut().unwrap().rebalance();
+        assert_eq!(ref_tree.unwrap().sanity_check(&boxes()), true);
+        assert_eq!(ref_tree.unwrap().is_balanced(&boxes()), false);
+        assert_eq!(ref_tree.unwrap().depth(&boxes()), ref_tree.unwrap().height);
+        let res: bool = balance::rebalance(&mut boxes(), tree.as_mut().unwrap());
         assert!(res);
         let ref_tree: &Box<Node<u64>> = tree.as_ref().unwrap();
-        assert_eq!(ref_tree.sanity_check(), true);
-        assert_eq!(ref_tree.is_balanced(), true);
-        assert_eq!(ref_tree.depth(), ref_tree.height);
+        assert_eq!(ref_tree.sanity_check(&boxes()), true);
+        assert_eq!(ref_tree.is_balanced(&boxes()), true);
+        assert_eq!(ref_tree.depth(&boxes()), ref_tree.height);
     }
 
     #[test]
@@ -961,28 +2192,37 @@ mod test_node {
                     children: [Some(Box::new(Node {
                         children: [None, None],
                         value: TEST_3,
-                        duplicates: HashSet::with_capacity(0),
+                        duplicates: Vec::new(),
+                        copies: 0,
+                        summary: (),
                         height: 1,
+                        size: 1,
                     })), None],
                     value: TEST_2,
-                    duplicates: HashSet::with_capacity(0),
+                    duplicates: Vec::new(),
+                    copies: 0,
+                    summary: (),
                     height: 2,
+                    size: 2,
                 })),
                 None,
             ],
             value: TEST_1,
-            duplicates: HashSet::with_capacity(0),
+            duplicates: Vec::new(),
+            copies: 0,
+            summary: (),
             height: 3,
+            size: 3,
         }));
-        let res: bool = tree.as_mut().unwrap().rebalance();
+        let res: bool = balance::rebalance(&mut boxes(), tree.as_mut().unwrap());
         assert!(res);
-        let res: bool = tree.as_mut().unwrap().rebalance();
+        let res: bool = balance::rebalance(&mut boxes(), tree.as_mut().unwrap());
         assert!(!res);
     }
 
     #[test]
     fn test_create_tree() {
-        let tree: Tree<u64> = Node::create_tree(&TEST_1);
+        let tree: Tree<u64> = Node::create_tree(&mut boxes(), TEST_1);
         let ref_tree: Option<&Box<Node<u64>>> = tree.as_ref();
         assert!(ref_tree.is_some());
         assert_eq!(ref_tree.unwrap().value, TEST_1);
@@ -993,7 +2233,7 @@ mod test_node {
 
     #[test]
     fn test_create_node() {
-        let node: Node<u64> = Node::create_node(&TEST_1);
+        let node: Node<u64> = Node::create_node(TEST_1);
         assert_eq!(node.value, TEST_1);
         assert_eq!(node.height, 1);
         assert!(node.children[Side::Left as usize].is_none());
@@ -1002,143 +2242,144 @@ mod test_node {
 
     #[test]
     fn test_insert() {
-        let mut node: Node<u64> = Node::create_node(&1);
-        let res: bool = node.insert(2);
+        let mut node: Node<u64> = Node::create_node(1);
+        let res: bool = Node::insert(&mut boxes(), &mut node, 2, &Natural, DuplicatePolicy::Bucket);
         assert!(res);
-        let res: bool = node.insert(3);
+        let res: bool = Node::insert(&mut boxes(), &mut node, 3, &Natural, DuplicatePolicy::Bucket);
         assert!(res);
-        let res: bool = node.insert(4);
+        let res: bool = Node::insert(&mut boxes(), &mut node, 4, &Natural, DuplicatePolicy::Bucket);
         assert!(res);
-        let res: bool = node.insert(5);
+        let res: bool = Node::insert(&mut boxes(), &mut node, 5, &Natural, DuplicatePolicy::Bucket);
         assert!(res);
-        let res: bool = node.insert(6);
+        let res: bool = Node::insert(&mut boxes(), &mut node, 6, &Natural, DuplicatePolicy::Bucket);
         assert!(res);
-        let res: bool = node.insert(7);
+        let res: bool = Node::insert(&mut boxes(), &mut node, 7, &Natural, DuplicatePolicy::Bucket);
         assert!(res);
-        let res: bool = node.insert(8);
+        let res: bool = Node::insert(&mut boxes(), &mut node, 8, &Natural, DuplicatePolicy::Bucket);
         assert!(res);
         assert_eq!(node.height, 4)
     }
 
     #[test]
     fn test_width_depth_count() {
-        let mut node: Node<u64> = Node::create_node(&1);
-        assert_eq!(node.count(), 1);
-        assert_eq!(node.depth(), 1);
-        assert_eq!(node.width(), 1);
-        let res: bool = node.insert(2);
-        assert!(res);
-        assert_eq!(node.count(), 2);
-        assert_eq!(node.depth(), 2);
-        assert_eq!(node.width(), 2);
-        let res: bool = node.insert(3);
-        assert!(res);
-        assert_eq!(node.count(), 3);
-        assert_eq!(node.depth(), 2);
-        assert_eq!(node.width(), 2);
-        let res: bool = node.insert(4);
-        assert!(res);
-        assert_eq!(node.count(), 4);
-        assert_eq!(node.depth(), 3);
-        assert_eq!(node.width(), 3);
-        let res: bool = node.insert(5);
-        assert!(res);
-        assert_eq!(node.count(), 5);
-        assert_eq!(node.depth(), 3);
-        assert_eq!(node.width(), 3);
-        let res: bool = node.insert(6);
-        assert!(res);
-        assert_eq!(node.count(), 6);
-        assert_eq!(node.depth(), 3);
-        assert_eq!(node.width(), 4);
-        let res: bool = node.insert(7);
-        assert!(res);
-        assert_eq!(node.count(), 7);
-        assert_eq!(node.depth(), 3);
-        assert_eq!(node.width(), 4);
-        let res: bool = node.insert(8);
-        assert!(res);
-        assert_eq!(node.count(), 8);
-        assert_eq!(node.depth(), 4);
-        assert_eq!(node.width(), 5);
-        let res: bool = node.insert(9);
-        assert!(res);
-        assert_eq!(node.count(), 9);
-        assert_eq!(node.depth(), 4);
-        assert_eq!(node.width(), 5);
-        let res: bool = node.insert(10);
-        assert!(res);
-        assert_eq!(node.count(), 10);
-        assert_eq!(node.depth(), 4);
-        assert_eq!(node.width(), 6);
-        let res: bool = node.insert(11);
-        assert!(res);
-        assert_eq!(node.count(), 11);
-        assert_eq!(node.depth(), 4);
-        assert_eq!(node.width(), 6);
-        let res: bool = node.insert(12);
-        assert!(res);
-        assert_eq!(node.count(), 12);
-        assert_eq!(node.depth(), 4);
-        assert_eq!(node.width(), 7);
-        let res: bool = node.insert(13);
-        assert!(res);
-        assert_eq!(node.count(), 13);
-        assert_eq!(node.depth(), 4);
-        assert_eq!(node.width(), 7);
-        let res: bool = node.insert(14);
-        assert!(res);
-        assert_eq!(node.count(), 14);
-        assert_eq!(node.depth(), 4);
-        assert_eq!(node.width(), 8);
-        let res: bool = node.insert(15);
-        assert!(res);
-        assert_eq!(node.count(), 15);
-        assert_eq!(node.depth(), 4);
-        assert_eq!(node.width(), 8);
-        let res: bool = node.insert(16);
-        assert!(res);
-        assert_eq!(node.count(), 16);
-        assert_eq!(node.depth(), 5);
-        assert_eq!(node.width(), 9);
+        let mut node: Node<u64> = Node::create_node(1);
+        assert_eq!(node.count(&boxes()), 1);
+        assert_eq!(node.depth(&boxes()), 1);
+        assert_eq!(node.width(&boxes()), 1);
+        let res: bool = Node::insert(&mut boxes(), &mut node, 2, &Natural, DuplicatePolicy::Bucket);
+        assert!(res);
+        assert_eq!(node.count(&boxes()), 2);
+        assert_eq!(node.depth(&boxes()), 2);
+        assert_eq!(node.width(&boxes()), 2);
+        let res: bool = Node::insert(&mut boxes(), &mut node, 3, &Natural, DuplicatePolicy::Bucket);
+        assert!(res);
+        assert_eq!(node.count(&boxes()), 3);
+        assert_eq!(node.depth(&boxes()), 2);
+        assert_eq!(node.width(&boxes()), 2);
+        let res: bool = Node::insert(&mut boxes(), &mut node, 4, &Natural, DuplicatePolicy::Bucket);
+        assert!(res);
+        assert_eq!(node.count(&boxes()), 4);
+        assert_eq!(node.depth(&boxes()), 3);
+        assert_eq!(node.width(&boxes()), 3);
+        let res: bool = Node::insert(&mut boxes(), &mut node, 5, &Natural, DuplicatePolicy::Bucket);
+        assert!(res);
+        assert_eq!(node.count(&boxes()), 5);
+        assert_eq!(node.depth(&boxes()), 3);
+        assert_eq!(node.width(&boxes()), 3);
+        let res: bool = Node::insert(&mut boxes(), &mut node, 6, &Natural, DuplicatePolicy::Bucket);
+        assert!(res);
+        assert_eq!(node.count(&boxes()), 6);
+        assert_eq!(node.depth(&boxes()), 3);
+        assert_eq!(node.width(&boxes()), 4);
+        let res: bool = Node::insert(&mut boxes(), &mut node, 7, &Natural, DuplicatePolicy::Bucket);
+        assert!(res);
+        assert_eq!(node.count(&boxes()), 7);
+        assert_eq!(node.depth(&boxes()), 3);
+        assert_eq!(node.width(&boxes()), 4);
+        let res: bool = Node::insert(&mut boxes(), &mut node, 8, &Natural, DuplicatePolicy::Bucket);
+        assert!(res);
+        assert_eq!(node.count(&boxes()), 8);
+        assert_eq!(node.depth(&boxes()), 4);
+        assert_eq!(node.width(&boxes()), 5);
+        let res: bool = Node::insert(&mut boxes(), &mut node, 9, &Natural, DuplicatePolicy::Bucket);
+        assert!(res);
+        assert_eq!(node.count(&boxes()), 9);
+        assert_eq!(node.depth(&boxes()), 4);
+        assert_eq!(node.width(&boxes()), 5);
+        let res: bool = Node::insert(&mut boxes(), &mut node, 10, &Natural, DuplicatePolicy::Bucket);
+        assert!(res);
+        assert_eq!(node.count(&boxes()), 10);
+        assert_eq!(node.depth(&boxes()), 4);
+        assert_eq!(node.width(&boxes()), 6);
+        let res: bool = Node::insert(&mut boxes(), &mut node, 11, &Natural, DuplicatePolicy::Bucket);
+        assert!(res);
+        assert_eq!(node.count(&boxes()), 11);
+        assert_eq!(node.depth(&boxes()), 4);
+        assert_eq!(node.width(&boxes()), 6);
+        let res: bool = Node::insert(&mut boxes(), &mut node, 12, &Natural, DuplicatePolicy::Bucket);
+        assert!(res);
+        assert_eq!(node.count(&boxes()), 12);
+        assert_eq!(node.depth(&boxes()), 4);
+        assert_eq!(node.width(&boxes()), 7);
+        let res: bool = Node::insert(&mut boxes(), &mut node, 13, &Natural, DuplicatePolicy::Bucket);
+        assert!(res);
+        assert_eq!(node.count(&boxes()), 13);
+        assert_eq!(node.depth(&boxes()), 4);
+        assert_eq!(node.width(&boxes()), 7);
+        let res: bool = Node::insert(&mut boxes(), &mut node, 14, &Natural, DuplicatePolicy::Bucket);
+        assert!(res);
+        assert_eq!(node.count(&boxes()), 14);
+        assert_eq!(node.depth(&boxes()), 4);
+        assert_eq!(node.width(&boxes()), 8);
+        let res: bool = Node::insert(&mut boxes(), &mut node, 15, &Natural, DuplicatePolicy::Bucket);
+        assert!(res);
+        assert_eq!(node.count(&boxes()), 15);
+        assert_eq!(node.depth(&boxes()), 4);
+        assert_eq!(node.width(&boxes()), 8);
+        let res: bool = Node::insert(&mut boxes(), &mut node, 16, &Natural, DuplicatePolicy::Bucket);
+        assert!(res);
+        assert_eq!(node.count(&boxes()), 16);
+        assert_eq!(node.depth(&boxes()), 5);
+        assert_eq!(node.width(&boxes()), 9);
     }
 
     #[test]
     fn test_delete() {
-        let mut tree: Tree<u64> = Node::create_tree(&1);
-        let node: &mut Box<Node<u64>> = tree.as_mut().unwrap();
-        let res: bool = node.insert(2);
+        let mut tree: Tree<u64> = Node::create_tree(&mut boxes(), 1);
+        let node: &mut Node<u64> = tree.as_deref_mut().unwrap();
+        let res: bool = Node::insert(&mut boxes(), node, 2, &Natural, DuplicatePolicy::Bucket);
         assert!(res);
-        let res: bool = node.insert(3);
+        let res: bool = Node::insert(&mut boxes(), node, 3, &Natural, DuplicatePolicy::Bucket);
         assert!(res);
-        let res: bool = node.insert(4);
+        let res: bool = Node::insert(&mut boxes(), node, 4, &Natural, DuplicatePolicy::Bucket);
         assert!(res);
-        let res: bool = node.insert(5);
+        let res: bool = Node::insert(&mut boxes(), node, 5, &Natural, DuplicatePolicy::Bucket);
         assert!(res);
-        let res: bool = node.insert(6);
+        let res: bool = Node::insert(&mut boxes(), node, 6, &Natural, DuplicatePolicy::Bucket);
         assert!(res);
-        let res: bool = node.insert(7);
+        let res: bool = Node::insert(&mut boxes(), node, 7, &Natural, DuplicatePolicy::Bucket);
         assert!(res);
-        let res: bool = node.insert(8);
+        let res: bool = Node::insert(&mut boxes(), node, 8, &Natural, DuplicatePolicy::Bucket);
         assert!(res);
         assert_eq!(node.height, 4);
-        Node::delete(&mut tree);
+        Node::delete(&mut boxes(), &mut tree);
     }
 
     #[test]
     fn test_min_max() {
-        let mut tree: Tree<u64> = Node::create_tree(&TEST_1);
-        let node: &mut Box<Node<u64>> = tree.as_mut().unwrap();
-        let res: bool = node.insert(TEST_2);
-        assert!(res);
-        let res: bool = node.insert(TEST_3);
+        let mut tree: Tree<u64> = Node::create_tree(&mut boxes(), TEST_1);
+        let node: &mut Node<u64> = tree.as_deref_mut().unwrap();
+        let res: bool = Node::insert(&mut boxes(), node, TEST_2, &Natural, DuplicatePolicy::Bucket);
         assert!(res);
-        let res: bool = node.insert(TEST_4);
+        let res: bool = Node::insert(&mut boxes(), node, TEST_3, &Natural, DuplicatePolicy::Bucket);
         assert!(res);
-        let res: bool = node.insert(TEST_4);
+        let res: bool = Node::insert(&mut boxes(), node, TEST_4, &Natural, DuplicatePolicy::Bucket);
         assert!(res);
-        assert_eq!(node.max(), &TEST_2);
-        assert_eq!(node.min(), &TEST_1);
+        // the same value can not be stored twice, even as the duplicate of itself
+        let res: bool = Node::insert(&mut boxes(), node, TEST_4, &Natural, DuplicatePolicy::Bucket);
+        assert!(!res);
+        assert_eq!(node.max(&boxes()), &TEST_2);
+        assert_eq!(node.min(&boxes()), &TEST_1);
     }
 
     #[test]
@@ -1152,31 +2393,31 @@ mod test_node {
                 write!(f, "\"{}\"", self.x)
             }
         }
-        let mut tree: Tree<Position> = Node::create_tree(&Position {
+        let mut tree: Tree<Position> = Node::create_tree(&mut boxes(), Position {
             x: 10
         });
-        let node: &mut Box<Node<Position>> = tree.as_mut().unwrap();
+        let node: &mut Node<Position> = tree.as_deref_mut().unwrap();
 
-        assert_eq!(node.dump(false), "[\"10\",null,null]");
-        let res: bool = node.insert(Position {
+        assert_eq!(node.dump(&boxes(), false), "[\"10\",null,null]");
+        let res: bool = Node::insert(&mut boxes(), node, Position {
             x: 20
-        });
+        }, &Natural, DuplicatePolicy::Bucket);
         assert!(res);
-        let res: bool = node.insert(Position {
+        let res: bool = Node::insert(&mut boxes(), node, Position {
             x: 30
-        });
+        }, &Natural, DuplicatePolicy::Bucket);
         assert!(res);
-        let res: bool = node.insert(Position {
+        let res: bool = Node::insert(&mut boxes(), node, Position {
             x: 50
-        });
+        }, &Natural, DuplicatePolicy::Bucket);
         assert!(res);
-        let res: bool = node.insert(Position {
+        let res: bool = Node::insert(&mut boxes(), node, Position {
             x: 40
-        });
+        }, &Natural, DuplicatePolicy::Bucket);
         assert!(res);
 
-        assert_eq!(node.dump(false), "[\"20\",[\"10\",null,null],[\"40\",[\"30\",null,null],[\"50\",null,null]]]");
-        assert_eq!(node.dump(true), r#"[
+        assert_eq!(node.dump(&boxes(), false), "[\"20\",[\"10\",null,null],[\"40\",[\"30\",null,null],[\"50\",null,null]]]");
+        assert_eq!(node.dump(&boxes(), true), r#"[
    "20",
    [
       "10",
@@ -1201,74 +2442,74 @@ mod test_node {
 
     #[test]
     fn test_remove_min() {
-        let mut tree: Tree<u64> = Node::create_tree(&TEST_1);
+        let mut tree: Tree<u64> = Node::create_tree(&mut boxes(), TEST_1);
 
-        let node: &mut Box<Node<u64>> = tree.as_mut().unwrap();
-        let res: bool = node.insert(TEST_2);
+        let node: &mut Node<u64> = tree.as_deref_mut().unwrap();
+        let res: bool = Node::insert(&mut boxes(), node, TEST_2, &Natural, DuplicatePolicy::Bucket);
         assert!(res);
-        let res: bool = node.insert(TEST_3);
+        let res: bool = Node::insert(&mut boxes(), node, TEST_3, &Natural, DuplicatePolicy::Bucket);
         assert!(res);
-        let res: bool = node.insert(TEST_4);
+        let res: bool = Node::insert(&mut boxes(), node, TEST_4, &Natural, DuplicatePolicy::Bucket);
         assert!(res);
         assert_eq!(tree.as_ref().unwrap().height, 3);
-        assert!(tree.as_ref().unwrap().is_balanced());
-        let res: u64 = Node::remove_min(&mut tree);
+        assert!(tree.as_ref().unwrap().is_balanced(&boxes()));
+        let res: u64 = Node::remove_min(&mut boxes(), &mut tree).value;
         assert_eq!(res, TEST_1);
         assert_eq!(tree.as_ref().unwrap().height, 2);
-        assert!(tree.as_ref().unwrap().is_balanced());
+        assert!(tree.as_ref().unwrap().is_balanced(&boxes()));
     }
 
     #[test]
     fn test_remove() {
-        let mut tree: Tree<u64> = Node::create_tree(&TEST_1);
+        let mut tree: Tree<u64> = Node::create_tree(&mut boxes(), TEST_1);
 
-        let node: &mut Box<Node<u64>> = tree.as_mut().unwrap();
-        let res: bool = node.insert(TEST_2);
+        let node: &mut Node<u64> = tree.as_deref_mut().unwrap();
+        let res: bool = Node::insert(&mut boxes(), node, TEST_2, &Natural, DuplicatePolicy::Bucket);
         assert!(res);
-        let res: bool = node.insert(TEST_3);
+        let res: bool = Node::insert(&mut boxes(), node, TEST_3, &Natural, DuplicatePolicy::Bucket);
         assert!(res);
-        let res: bool = node.insert(TEST_4);
+        let res: bool = Node::insert(&mut boxes(), node, TEST_4, &Natural, DuplicatePolicy::Bucket);
         assert!(res);
         assert_eq!(tree.as_ref().unwrap().height, 3);
-        assert!(tree.as_ref().unwrap().is_balanced());
-        let res: Option<u64> = Node::remove(&mut tree, &TEST_4);
+        assert!(tree.as_ref().unwrap().is_balanced(&boxes()));
+        let res: Option<u64> = Node::remove(&mut boxes(), &mut tree, &TEST_4, &Natural);
         assert!(res.is_some());
         assert_eq!(res.unwrap(), TEST_4);
         assert_eq!(tree.as_ref().unwrap().height, 2);
-        assert!(tree.as_ref().unwrap().is_balanced());
+        assert!(tree.as_ref().unwrap().is_balanced(&boxes()));
     }
 
     #[test]
     fn test_remove_complex() {
-        let mut tree: Tree<u64> = Node::create_tree(&TEST_1);
+        let mut tree: Tree<u64> = Node::create_tree(&mut boxes(), TEST_1);
 
-        let node: &mut Box<Node<u64>> = tree.as_mut().unwrap();
-        let res: bool = node.insert(TEST_2);
+        let node: &mut Node<u64> = tree.as_deref_mut().unwrap();
+        let res: bool = Node::insert(&mut boxes(), node, TEST_2, &Natural, DuplicatePolicy::Bucket);
         assert!(res);
-        let res: bool = node.insert(TEST_3);
+        let res: bool = Node::insert(&mut boxes(), node, TEST_3, &Natural, DuplicatePolicy::Bucket);
         assert!(res);
-        let res: bool = node.insert(TEST_4);
+        let res: bool = Node::insert(&mut boxes(), node, TEST_4, &Natural, DuplicatePolicy::Bucket);
         assert!(res);
         assert_eq!(tree.as_ref().unwrap().height, 3);
-        assert!(tree.as_ref().unwrap().is_balanced());
-        let res: Option<u64> = Node::remove(&mut tree, &TEST_4);
+        assert!(tree.as_ref().unwrap().is_balanced(&boxes()));
+        let res: Option<u64> = Node::remove(&mut boxes(), &mut tree, &TEST_4, &Natural);
         assert!(res.is_some());
         assert_eq!(res.unwrap(), TEST_4);
         assert_eq!(tree.as_ref().unwrap().height, 2);
-        assert!(tree.as_ref().unwrap().is_balanced());
+        assert!(tree.as_ref().unwrap().is_balanced(&boxes()));
 
-        let res: Option<u64> = Node::remove(&mut tree, &TEST_4);
+        let res: Option<u64> = Node::remove(&mut boxes(), &mut tree, &TEST_4, &Natural);
         assert!(res.is_none());
 
-        let res: Option<u64> = Node::remove(&mut tree, &TEST_2);
+        let res: Option<u64> = Node::remove(&mut boxes(), &mut tree, &TEST_2, &Natural);
         assert!(res.is_some());
         assert_eq!(res.unwrap(), TEST_2);
 
-        let res: Option<u64> = Node::remove(&mut tree, &TEST_1);
+        let res: Option<u64> = Node::remove(&mut boxes(), &mut tree, &TEST_1, &Natural);
         assert!(res.is_some());
         assert_eq!(res.unwrap(), TEST_1);
 
-        let res: Option<u64> = Node::remove(&mut tree, &TEST_3);
+        let res: Option<u64> = Node::remove(&mut boxes(), &mut tree, &TEST_3, &Natural);
         assert!(res.is_some());
         assert_eq!(res.unwrap(), TEST_3);
 
@@ -1278,15 +2519,16 @@ mod test_node {
 
     #[test]
     fn test_get() {
-        let mut tree: Tree<u64> = Node::create_tree(&TEST_1);
-        let node: &mut Box<Node<u64>> = tree.as_mut().unwrap();
-        let res: bool = node.insert(TEST_2);
+        let storage: Boxes<u64, ()> = boxes();
+        let mut tree: Tree<u64> = Node::create_tree(&mut boxes(), TEST_1);
+        let node: &mut Node<u64> = tree.as_deref_mut().unwrap();
+        let res: bool = Node::insert(&mut boxes(), node, TEST_2, &Natural, DuplicatePolicy::Bucket);
         assert!(res);
-        let res: bool = node.insert(TEST_3);
+        let res: bool = Node::insert(&mut boxes(), node, TEST_3, &Natural, DuplicatePolicy::Bucket);
         assert!(res);
-        let res: bool = node.insert(TEST_4);
+        let res: bool = Node::insert(&mut boxes(), node, TEST_4, &Natural, DuplicatePolicy::Bucket);
         assert!(res);
-        let tree: &Tree<u64> = Node::get(&tree, &TEST_2);
+        let tree: Option<&Node<u64>> = Node::get(&storage, &tree, &TEST_2, &Natural);
         assert!(tree.is_some());
         assert_eq!(tree.as_ref().unwrap().value, TEST_2);
         assert_eq!(tree.as_ref().unwrap().height, 2);
@@ -1294,56 +2536,59 @@ mod test_node {
 
     #[test]
     fn test_get_missing() {
-        let mut tree: Tree<u64> = Node::create_tree(&TEST_1);
-        let node: &mut Box<Node<u64>> = tree.as_mut().unwrap();
-        let res: bool = node.insert(TEST_2);
+        let storage: Boxes<u64, ()> = boxes();
+        let mut tree: Tree<u64> = Node::create_tree(&mut boxes(), TEST_1);
+        let node: &mut Node<u64> = tree.as_deref_mut().unwrap();
+        let res: bool = Node::insert(&mut boxes(), node, TEST_2, &Natural, DuplicatePolicy::Bucket);
         assert!(res);
-        let res: bool = node.insert(TEST_3);
+        let res: bool = Node::insert(&mut boxes(), node, TEST_3, &Natural, DuplicatePolicy::Bucket);
         assert!(res);
-        let res: bool = node.insert(TEST_4);
+        let res: bool = Node::insert(&mut boxes(), node, TEST_4, &Natural, DuplicatePolicy::Bucket);
         assert!(res);
-        let tree: &Tree<u64> = Node::get(&tree, &TEST_5);
+        let tree: Option<&Node<u64>> = Node::get(&storage, &tree, &TEST_5, &Natural);
         assert!(tree.is_none());
     }
 
     #[test]
     fn test_get_remove() {
-        let mut tree: Tree<u64> = Node::create_tree(&TEST_1);
-        let node: &mut Box<Node<u64>> = tree.as_mut().unwrap();
-        let res: bool = node.insert(TEST_2);
+        let storage: Boxes<u64, ()> = boxes();
+        let mut tree: Tree<u64> = Node::create_tree(&mut boxes(), TEST_1);
+        let node: &mut Node<u64> = tree.as_deref_mut().unwrap();
+        let res: bool = Node::insert(&mut boxes(), node, TEST_2, &Natural, DuplicatePolicy::Bucket);
         assert!(res);
-        let res: bool = node.insert(TEST_3);
+        let res: bool = Node::insert(&mut boxes(), node, TEST_3, &Natural, DuplicatePolicy::Bucket);
         assert!(res);
-        let tree2: &Tree<u64> = Node::get(&tree, &TEST_2);
+        let tree2: Option<&Node<u64>> = Node::get(&storage, &tree, &TEST_2, &Natural);
         assert!(tree2.is_some());
         assert_eq!(tree2.as_ref().unwrap().value, TEST_2);
         let old: u64 = tree2.as_ref().unwrap().value.clone();
-        let removed: Option<u64> = Node::remove(&mut tree, &TEST_2);
+        let removed: Option<u64> = Node::remove(&mut boxes(), &mut tree, &TEST_2, &Natural);
         assert!(removed.is_some());
         assert_eq!(removed.unwrap(), TEST_2);
         assert_eq!(old, TEST_2);
-        let removed: Option<u64> = Node::remove(&mut tree, &TEST_2);
+        let removed: Option<u64> = Node::remove(&mut boxes(), &mut tree, &TEST_2, &Natural);
         assert!(removed.is_none());
-        let tree2: &Tree<u64> = Node::get(&tree, &TEST_2);
+        let tree2: Option<&Node<u64>> = Node::get(&storage, &tree, &TEST_2, &Natural);
         assert!(tree2.is_none());
     }
 
     #[test]
     fn test_get_not_modifying() {
-        let mut tree: Tree<u64> = Node::create_tree(&TEST_1);
-        let node: &mut Box<Node<u64>> = tree.as_mut().unwrap();
-        let res: bool = node.insert(TEST_2);
+        let storage: Boxes<u64, ()> = boxes();
+        let mut tree: Tree<u64> = Node::create_tree(&mut boxes(), TEST_1);
+        let node: &mut Node<u64> = tree.as_deref_mut().unwrap();
+        let res: bool = Node::insert(&mut boxes(), node, TEST_2, &Natural, DuplicatePolicy::Bucket);
         assert!(res);
-        let res: bool = node.insert(TEST_3);
+        let res: bool = Node::insert(&mut boxes(), node, TEST_3, &Natural, DuplicatePolicy::Bucket);
         assert!(res);
-        let tree_get: &Tree<u64> = Node::get(&tree, &TEST_3);
+        let tree_get: Option<&Node<u64>> = Node::get(&storage, &tree, &TEST_3, &Natural);
         assert!(tree_get.is_some());
         assert_eq!(tree_get.as_ref().unwrap().value, TEST_3);
-        let mut tree2: Tree<u64> = tree_get.clone();
-        let removed: Option<u64> = Node::remove(&mut tree, &TEST_2);
+        let mut tree2: Tree<u64> = tree_get.map(|node: &Node<u64>| Box::new(node.clone()));
+        let removed: Option<u64> = Node::remove(&mut boxes(), &mut tree, &TEST_2, &Natural);
         assert!(removed.is_some());
         assert_eq!(removed.unwrap(), TEST_2);
-        let removed2: Option<u64> = Node::remove(&mut tree2, &TEST_2);
+        let removed2: Option<u64> = Node::remove(&mut boxes(), &mut tree2, &TEST_2, &Natural);
         assert!(removed2.is_some());
         assert_eq!(removed2.unwrap(), TEST_2);
         assert_eq!(tree2, tree);
@@ -1353,193 +2598,401 @@ mod test_node {
     }
 }
 
-impl<'a, T: 'a + Clone + Ord + Eq + Debug + Display + Hash> Node<T> {
-    fn insert(&mut self, new_value: T) -> bool {
-        if new_value <= self.value && self.value <= new_value {
-            if self.duplicates.contains(&new_value) {
-                return false;
-            }
-            self.duplicates.insert(new_value);
-            return true;
+/// Dropping the nodes only needs to take them out of their storage
+impl<T, S, B: Backend<T, S>> Node<T, S, B> {
+    fn delete(storage: &mut B, tree: &mut B::Link) {
+        if let Some(mut node) = storage.free(tree) {
+            Self::delete(storage, &mut node.children[Side::Left as usize]);
+            Self::delete(storage, &mut node.children[Side::Right as usize]);
+            node.duplicates.clear();
         }
-        let mut res = true;
-        let target_node: &mut Tree<T> = if new_value <= self.value { &mut self.children[Side::Left as usize] } else { &mut self.children[Side::Right as usize] };
-        match target_node {
-            &mut Some(ref mut subnode) => {
-                res = subnode.insert(new_value);
-            }
-            &mut None => {
-                let new_node = Node { children: [None, None], value: new_value, duplicates: HashSet::with_capacity(0), height: 1 };
-                let boxed_node = Some(Box::new(new_node));
-                *target_node = boxed_node;
+    }
+}
+
+/// The structural part of the nodes (balancing, heights and sizes) does not look at the values
+/// so it is shared by every kind of payload, see AvlMap
+/// The nodes are reached through the storage B holding them, the changes go through what it gives (see Backend)
+impl<T, S: Summary<T>, B: Backend<T, S>> Node<T, S, B> {
+    fn create_tree(storage: &mut B, value: T) -> B::Link {
+        storage.alloc(Self::create_node(value))
+    }
+
+    fn create_node(value: T) -> Self {
+        let summary: S = S::from_value(&value);
+        Node {
+            children: [B::empty(), B::empty()],
+            value,
+            duplicates: Vec::new(),
+            copies: 0,
+            summary,
+            height: 1,
+            size: 1,
+        }
+    }
+
+    fn child<'a>(&'a self, storage: &'a B, side: Side) -> Option<&'a Self> {
+        storage.root(&self.children[side as usize])
+    }
+
+    /// Get the value at the given position in the in order walk (duplicates included)
+    fn select<'a>(storage: &'a B, tree: &'a B::Link, index: usize) -> Option<&'a T> where S: 'a {
+        let mut index: usize = index;
+        let mut tree: Option<&'a Self> = storage.root(tree);
+        while let Some(node) = tree {
+            let left: usize = node.left_size(storage);
+            if index < left {
+                tree = node.child(storage, Side::Left);
+            } else if index == left {
+                return Some(&node.value);
+            } else if index <= left + node.duplicates.len() {
+                return node.duplicates.get(index - left - 1);
+            } else {
+                index -= left + 1 + node.duplicates.len();
+                tree = node.child(storage, Side::Right);
             }
         }
-        self.update_height();
-        self.rebalance();
+        None
+    }
+
+    /// Same as select but give the node holding the value at this index (its value or one of its duplicates)
+    fn select_node_mut<'a>(storage: &'a mut B, tree: &'a mut B::Link, index: usize) -> Option<&'a mut Self> {
+        let mut index: usize = index;
+        storage.descend(tree, |storage: &mut B, node: &mut B::Node| {
+            storage.push_down(node);
+            let node: &Self = storage.get(node);
+            let left: usize = node.left_size(storage);
+            if index < left {
+                Some(Side::Left)
+            } else if index <= left + node.duplicates.len() {
+                None
+            } else {
+                index -= left + 1 + node.duplicates.len();
+                Some(Side::Right)
+            }
+        })
+    }
+
+    /// The node at the end of the path of sides from the root, the path must lead to a node
+    fn at_path_mut<'a>(storage: &'a mut B, tree: &'a mut B::Link, path: &[Side]) -> &'a mut Self {
+        let mut path = path.iter();
+        storage.descend(tree, |storage: &mut B, node: &mut B::Node| {
+            storage.push_down(node);
+            path.next().copied()
+        }).unwrap()
+    }
+
+    /// Follow the path of sides from the root and hand the tree found at its end to change,
+    /// then update and rebalance the nodes of the path on the way back up
+    fn edit_at<R, F: FnOnce(&mut B, &mut B::Link) -> R>(storage: &mut B, tree: &mut B::Link, path: &[Side], change: F) -> R {
+        let (side, rest): (&Side, &[Side]) = match path.split_first() {
+            None => return change(storage, tree),
+            Some(first) => first,
+        };
+        let node: &mut B::Node = B::node(tree);
+        let mut child: B::Link = storage.take_child(node, *side);
+        let res: R = Self::edit_at(storage, &mut child, rest, change);
+        storage.set_child(node, *side, child);
+        balance::rebalance(storage, node);
         res
     }
 
-    fn create_tree(value: &T) -> Tree<T> {
-        Some(Box::new(Self::create_node(value)))
+    fn count(&self, storage: &B) -> usize {
+        let left: usize;
+        if self.child(storage, Side::Left).is_some() {
+            left = self.child(storage, Side::Left).unwrap().count(storage)
+        } else {
+            left = 0;
+        }
+        let right: usize;
+        if self.child(storage, Side::Right).is_some() {
+            right = self.child(storage, Side::Right).unwrap().count(storage)
+        } else {
+            right = 0;
+        }
+        return 1 + self.duplicates.len() + left + right;
     }
 
-    fn create_node(value: &T) -> Self {
-        Node {
-            children: [None, None],
-            value: value.clone(),
-            duplicates: HashSet::with_capacity(0),
-            height: 1,
+    fn max<'a>(&'a self, storage: &'a B) -> &'a T {
+        if self.child(storage, Side::Right).is_some() {
+            self.child(storage, Side::Right).unwrap().max(storage)
+        } else {
+            return &self.value;
         }
     }
 
-    fn delete(node: &mut Tree<T>) {
-        if node.is_some() {
-            Self::delete(node.as_mut().unwrap().children[Side::Left as usize].take().borrow_mut());
-            Self::delete(node.as_mut().unwrap().children[Side::Right as usize].take().borrow_mut());
-            node.as_mut().unwrap().duplicates.clear();
-            node.take();
+    fn min<'a>(&'a self, storage: &'a B) -> &'a T {
+        if self.child(storage, Side::Left).is_some() {
+            self.child(storage, Side::Left).unwrap().min(storage)
+        } else {
+            return &self.value;
         }
     }
 
-    fn remove_min(node: &mut Tree<T>) -> T {
-        if node.is_none() {
+    fn width(&self, storage: &B) -> usize {
+        let left: usize;
+        if self.child(storage, Side::Left).is_some() {
+            left = self.child(storage, Side::Left).unwrap().width(storage)
+        } else {
+            left = 1;
+        }
+        let right: usize;
+        if self.child(storage, Side::Right).is_some() {
+            right = self.child(storage, Side::Right).unwrap().width(storage)
+        } else {
+            right = 1;
+        }
+        if self.child(storage, Side::Left).is_none() && self.child(storage, Side::Right).is_none() {
+            return 1;
+        }
+        return left + right;
+    }
+
+    /// Detach the left most node (with its duplicates) and return it without children
+    fn remove_min(storage: &mut B, tree: &mut B::Link) -> Self {
+        if storage.height(tree) == 0 {
             panic!("You should not pass a NULL in that function");
         }
-        if node.as_ref().unwrap().children[Side::Left as usize].is_none() {
-            let right = node.as_mut().unwrap().children[Side::Right as usize].take();
-            return replace(node, right).unwrap().value;
-        }
-        let value: T = Self::remove_min(&mut node.as_mut().unwrap().children[Side::Left as usize]);
-        node.as_mut().unwrap().update_height();
-        node.as_mut().unwrap().rebalance();
-        return value;
-    }
-
-    fn remove(node: &mut Tree<T>, value: &T) -> Option<T> {
-        if node.is_some() {
-            return if &node.as_ref().unwrap().value <= value && value <= &node.as_ref().unwrap().value {
-                if &node.as_ref().unwrap().value == value && node.as_ref().unwrap().duplicates.is_empty() {
-                    if node.as_ref().unwrap().children[Side::Right as usize].is_some() {
-                        let value: T = Self::remove_min(&mut node.as_mut().unwrap().children[Side::Right as usize]);
-                        let old: T = replace(&mut node.as_mut().unwrap().value, value);
-                        node.as_mut().unwrap().rebalance();
-                        Some(old)
-                    } else {
-                        let left: Tree<T> = node.as_mut().unwrap().children[Side::Left as usize].take();
-                        let tree: Tree<T> = replace(node, left);
-                        if node.is_some() {
-                            node.as_mut().unwrap().rebalance();
-                        }
-                        tree.map_or(None, |node| Some(node.value))
-                    }
-                } else {
-
-                    if &node.as_ref().unwrap().value == value {
-                        let new_value: Option<T> = node.as_ref().unwrap().duplicates.iter().next().cloned();
-                        if new_value.is_none() {
-                            return None;
-                        }
-                        let new_value: T = new_value.unwrap();
-                        node.as_mut().unwrap().duplicates.remove(&new_value);
-                        Some(replace(&mut node.as_mut().unwrap().value, new_value))
-                    } else {
-                        if node.as_mut().unwrap().duplicates.remove(&value){
-                            Some(value.clone())
-                        }else{
-                            None
-                        }
-
-                    }
-                }
-            } else {
-                let target_node: &mut Tree<T> = if value <= &node.as_mut().unwrap().value { &mut node.as_mut().unwrap().children[Side::Left as usize] } else { &mut node.as_mut().unwrap().children[Side::Right as usize] };
-                let res: Option<T> = Self::remove(target_node, value);
-                node.as_mut().unwrap().rebalance();
-                res
-            };
+        let node: &mut B::Node = B::node(tree);
+        let mut left: B::Link = storage.take_child(node, Side::Left);
+        if storage.height(&left) == 0 {
+            let right: B::Link = storage.take_child(node, Side::Right);
+            let mut min: Self = storage.free(tree).unwrap();
+            *tree = right;
+            min.update(storage);
+            return min;
+        }
+        let min: Self = Self::remove_min(storage, &mut left);
+        storage.set_child(node, Side::Left, left);
+        balance::rebalance(storage, node);
+        min
+    }
+
+    /// Build a perfectly balanced tree out of count nodes given in order, in linear time
+    fn from_sorted_nodes<I: Iterator<Item = Self>>(storage: &mut B, nodes: &mut I, count: usize) -> B::Link {
+        if count == 0 {
+            return B::empty();
+        }
+        let left: B::Link = Self::from_sorted_nodes(storage, nodes, count / 2);
+        let mut node: Self = match nodes.next() {
+            None => return left,
+            Some(node) => node,
+        };
+        let right: B::Link = Self::from_sorted_nodes(storage, nodes, count - count / 2 - 1);
+        node.children = [left, right];
+        node.update(storage);
+        storage.alloc(node)
+    }
+
+    /// Join two trees with a pivot node whose key is between the keys of the two (the standard AVL join)
+    /// The pivot goes down the spine of the higher tree until both sides have about the same height,
+    /// then the nodes on the way back up are rebalanced, this is O(1 + difference of height)
+    /// The pivot has no children and no update left to hand down
+    fn join(storage: &mut B, mut left: B::Link, mut pivot: Self, mut right: B::Link) -> B::Link {
+        let left_height: usize = storage.height(&left);
+        let right_height: usize = storage.height(&right);
+        if left_height > right_height + 1 {
+            let node: &mut B::Node = B::node(&mut left);
+            let inner: B::Link = storage.take_child(node, Side::Right);
+            let joined: B::Link = Self::join(storage, inner, pivot, right);
+            storage.set_child(node, Side::Right, joined);
+            balance::rebalance(storage, node);
+            return left;
+        }
+        if right_height > left_height + 1 {
+            let node: &mut B::Node = B::node(&mut right);
+            let inner: B::Link = storage.take_child(node, Side::Left);
+            let joined: B::Link = Self::join(storage, left, pivot, inner);
+            storage.set_child(node, Side::Left, joined);
+            balance::rebalance(storage, node);
+            return right;
         }
-        return None;
+        pivot.children = [left, right];
+        pivot.update(storage);
+        storage.alloc(pivot)
     }
 
-    fn get(tree: &'a Tree<T>, value: &T) -> &'a Tree<T> {
-        if tree.is_some() {
-            if &tree.as_ref().unwrap().value <= value && &tree.as_ref().unwrap().value >= value {
-                return tree;
+    /// Join two trees whose keys do not overlap (all the left ones are lower) with the minimum of the right one as pivot
+    fn join_trees(storage: &mut B, left: B::Link, mut right: B::Link) -> B::Link {
+        if storage.height(&right) == 0 {
+            return left;
+        }
+        let pivot: Self = Self::remove_min(storage, &mut right);
+        Self::join(storage, left, pivot, right)
+    }
+
+    /// Combine two trees ordered the same way: the left one is split around the root of the right one, both sides are combined
+    /// the same way and joined back with what resolve keeps of the root key (or without it), this is O(m log(n / m + 1))
+    /// keep says if the keys found only in the left tree (then only in the right tree) stay, resolve settles the keys found in both
+    fn merge<C: Comparator<T>, F: FnMut(Self, Self) -> Option<Self>>(storage: &mut B, mut left: B::Link, mut right: B::Link, cmp: &C, keep: [bool; 2], resolve: &mut F) -> B::Link {
+        if storage.height(&right) == 0 {
+            if !keep[Side::Left as usize] {
+                Self::delete(storage, &mut left);
             }
-            return if value > &tree.as_ref().unwrap().value {
-                Node::get(&tree.as_ref().unwrap().children[Side::Right as usize], value)
-            } else {
-                Node::get(&tree.as_ref().unwrap().children[Side::Left as usize], value)
+            return left;
+        }
+        if storage.height(&left) == 0 {
+            if !keep[Side::Right as usize] {
+                Self::delete(storage, &mut right);
+            }
+            return right;
+        }
+        let mut pivot: Self = storage.free(&mut right).unwrap();
+        let lower: B::Link = replace(&mut pivot.children[Side::Left as usize], B::empty());
+        let greater: B::Link = replace(&mut pivot.children[Side::Right as usize], B::empty());
+        let (left_lower, equal, left_greater) = Self::split(storage, left, &pivot.value, cmp);
+        let node: Option<Self> = match equal {
+            Some(equal) => resolve(equal, pivot),
+            None if keep[Side::Right as usize] => Some(pivot),
+            None => None,
+        };
+        let lower: B::Link = Self::merge(storage, left_lower, lower, cmp, keep, resolve);
+        let greater: B::Link = Self::merge(storage, left_greater, greater, cmp, keep, resolve);
+        match node {
+            Some(node) => Self::join(storage, lower, node, greater),
+            None => Self::join_trees(storage, lower, greater),
+        }
+    }
+
+    /// Take the value and the duplicates of a node apart, the value first
+    fn into_values(self) -> Vec<T> {
+        once(self.value).chain(self.duplicates).collect()
+    }
+
+    /// Take the nodes of the tree apart in order (with their duplicates), the children are left empty
+    fn into_sorted_nodes(storage: &mut B, tree: B::Link, nodes: &mut Vec<Self>) {
+        let mut stack: Vec<Self> = Vec::new();
+        let mut tree: B::Link = tree;
+        loop {
+            while let Some(mut node) = storage.free(&mut tree) {
+                tree = replace(&mut node.children[Side::Left as usize], B::empty());
+                stack.push(node);
+            }
+            let mut node: Self = match stack.pop() {
+                None => return,
+                Some(node) => node,
             };
+            tree = replace(&mut node.children[Side::Right as usize], B::empty());
+            nodes.push(node);
         }
-        &None
     }
 
-    fn left_height(&self) -> usize {
-        self.children[Side::Left as usize].as_ref().map_or(0, |left| left.height)
+    /// Take the value out of the root of the tree, its oldest duplicate takes its place if any
+    /// else the node is replaced by its successor (or by its left child if it has no right one)
+    fn take_value(storage: &mut B, tree: &mut B::Link) -> Option<T> {
+        if storage.height(tree) == 0 {
+            return None;
+        }
+        let node: &mut B::Node = B::node(tree);
+        storage.push_down(node);
+        let current: &mut Self = storage.get_mut(node);
+        if !current.duplicates.is_empty() {
+            let new_value: T = current.duplicates.remove(0);
+            let old: T = replace(&mut current.value, new_value);
+            storage.update(node);
+            return Some(old);
+        }
+        let mut right: B::Link = storage.take_child(node, Side::Right);
+        if storage.height(&right) != 0 {
+            let min: Self = Self::remove_min(storage, &mut right);
+            storage.set_child(node, Side::Right, right);
+            let current: &mut Self = storage.get_mut(node);
+            current.duplicates = min.duplicates;
+            current.copies = min.copies;
+            let old: T = replace(&mut current.value, min.value);
+            balance::rebalance(storage, node);
+            return Some(old);
+        }
+        let left: B::Link = storage.take_child(node, Side::Left);
+        let removed: Option<Self> = storage.free(tree);
+        *tree = left;
+        removed.map(|node: Self| node.value)
     }
 
-    fn right_height(&self) -> usize {
-        self.children[Side::Right as usize].as_ref().map_or(0, |right| right.height)
+    fn left_height(&self, storage: &B) -> usize {
+        storage.height(&self.children[Side::Left as usize])
     }
 
-    fn update_height(&mut self) {
-        self.height = 1 + max(self.left_height(), self.right_height());
+    fn right_height(&self, storage: &B) -> usize {
+        storage.height(&self.children[Side::Right as usize])
     }
 
-    fn height(&self) -> usize {
-        self.height
+    fn update_height(&mut self, storage: &B) {
+        self.height = 1 + max(self.left_height(storage), self.right_height(storage));
     }
 
-    fn dump(&self, prettify: bool) -> String {
-        self._dump(prettify,1)
+    fn left_size(&self, storage: &B) -> usize {
+        self.child(storage, Side::Left).map_or(0, |left: &Self| left.size)
     }
 
-    fn _dump(&self, prettify: bool, height: usize)->String {
-        let mut pad = String::new();
-        if prettify {
-            pad = "   ".repeat(height);
+    fn right_size(&self, storage: &B) -> usize {
+        self.child(storage, Side::Right).map_or(0, |right: &Self| right.size)
+    }
+
+    fn update_size(&mut self, storage: &B) {
+        self.size = 1 + self.duplicates.len() + self.left_size(storage) + self.right_size(storage);
+    }
+
+    fn child_summary(&self, storage: &B, side: Side) -> S {
+        self.child(storage, side).map_or_else(S::identity, |child: &Self| child.summary.clone())
+    }
+
+    /// The summary of the values of the node alone, in the order they are walked
+    fn values_summary(&self) -> S {
+        self.duplicates.iter().fold(S::from_value(&self.value), |summary: S, duplicate: &T| summary.combine(&S::from_value(duplicate)))
+    }
+
+    fn update_summary(&mut self, storage: &B) {
+        self.summary = B::rebuild(&self.summary, self.child_summary(storage, Side::Left).combine(&self.values_summary()).combine(&self.child_summary(storage, Side::Right)));
+    }
+
+    /// Hand every pending update down to the values of the tree
+    fn push_all(storage: &mut B, tree: &mut B::Link) {
+        if storage.height(tree) == 0 {
+            return;
         }
-        let mut string_node: String = format!("[{}{},{}", (if prettify { "\n".to_string() } else { String::new() }) + &*pad, self.value, (if prettify { "\n".to_string() } else { String::new() }) + &*pad);
-        if self.children[Side::Left as usize].is_some() {
-            string_node += &*self.children[Side::Left as usize].as_ref().unwrap()._dump(prettify, height+1);
-        } else {
-            string_node += "null";
+        let node: &mut B::Node = B::node(tree);
+        for side in [Side::Left, Side::Right].iter() {
+            let mut child: B::Link = storage.take_child(node, *side);
+            Self::push_all(storage, &mut child);
+            storage.set_child(node, *side, child);
         }
+    }
 
-        if self.children[Side::Right as usize].is_some() {
-            string_node += &*(",".to_owned()+&( if prettify { "\n".to_string() + &*pad } else { String::new() }));
-            string_node += &*self.children[Side::Right as usize].as_ref().unwrap()._dump(prettify, height+1);
-        } else {
-            string_node+=",";
-            string_node += &*((if prettify { "\n".to_string() } else { String::new() }) + &*pad + "null");
-        }
-        return string_node + &*(if prettify { "\n".to_string() + &*( "   ".repeat(max(height,1)-1)) } else { String::new() }) + "]";
+    /// Recompute everything stored about the subtree, the children must already be up to date
+    fn update(&mut self, storage: &B) {
+        self.update_height(storage);
+        self.update_size(storage);
+        self.update_summary(storage);
+    }
+
+    fn height(&self) -> usize {
+        self.height
     }
 
-    fn sanity_check(&self) -> bool {
-        let mut is_correct: bool = self.height() == (1 + max(self.left_height(), self.right_height()));
-        if self.children[Side::Left as usize].is_some() {
-            is_correct &= self.children[Side::Left as usize].as_ref().unwrap().sanity_check();
+    fn sanity_check(&self, storage: &B) -> bool {
+        let mut is_correct: bool = self.height() == (1 + max(self.left_height(storage), self.right_height(storage)));
+        is_correct &= self.size == 1 + self.duplicates.len() + self.left_size(storage) + self.right_size(storage);
+        if self.child(storage, Side::Left).is_some() {
+            is_correct &= self.child(storage, Side::Left).unwrap().sanity_check(storage);
         }
-        if self.children[Side::Right as usize].is_some() {
-            is_correct &= self.children[Side::Right as usize].as_ref().unwrap().sanity_check();
+        if self.child(storage, Side::Right).is_some() {
+            is_correct &= self.child(storage, Side::Right).unwrap().sanity_check(storage);
         }
         return is_correct;
     }
 
-    fn is_balanced(&self) -> bool {
+    fn is_balanced(&self, storage: &B) -> bool {
         let mut lh: usize = 0;
         let mut lb: bool = true;
-        if self.children[Side::Left as usize].is_some() {
-            lh = self.children[Side::Left as usize].as_ref().unwrap().depth();
-            lb = self.children[Side::Left as usize].as_ref().unwrap().is_balanced();
+        if self.child(storage, Side::Left).is_some() {
+            lh = self.child(storage, Side::Left).unwrap().depth(storage);
+            lb = self.child(storage, Side::Left).unwrap().is_balanced(storage);
         }
         let mut rh: usize = 0;
         let mut rb: bool = true;
-        if self.children[Side::Right as usize].is_some() {
-            rh = self.children[Side::Right as usize].as_ref().unwrap().depth();
-            rb = self.children[Side::Right as usize].as_ref().unwrap().is_balanced();
+        if self.child(storage, Side::Right).is_some() {
+            rh = self.child(storage, Side::Right).unwrap().depth(storage);
+            rb = self.child(storage, Side::Right).unwrap().is_balanced(storage);
         }
         let diff: usize;
         if lh < rh {
@@ -1553,21 +3006,21 @@ impl<'a, T: 'a + Clone + Ord + Eq + Debug + Display + Hash> Node<T> {
         return false;
     }
 
-    fn depth(&self) -> usize {
+    fn depth(&self, storage: &B) -> usize {
         let mut left: usize = 0;
-        if self.children[Side::Left as usize].is_some() {
-            left = self.children[Side::Left as usize].as_ref().unwrap().depth()
+        if self.child(storage, Side::Left).is_some() {
+            left = self.child(storage, Side::Left).unwrap().depth(storage)
         }
         let mut right: usize = 0;
-        if self.children[Side::Right as usize].is_some() {
-            right = self.children[Side::Right as usize].as_ref().unwrap().depth()
+        if self.child(storage, Side::Right).is_some() {
+            right = self.child(storage, Side::Right).unwrap().depth(storage)
         }
         return 1 + max(left, right);
     }
 
-    fn balance_factor(&self) -> i8 {
-        let left_height = self.left_height();
-        let right_height = self.right_height();
+    fn balance_factor(&self, storage: &B) -> i8 {
+        let left_height = self.left_height(storage);
+        let right_height = self.right_height(storage);
 
         if left_height >= right_height {
             (left_height - right_height) as i8
@@ -1576,106 +3029,370 @@ impl<'a, T: 'a + Clone + Ord + Eq + Debug + Display + Hash> Node<T> {
         }
     }
 
-    fn rotate(&mut self, side: Side) -> bool {
-        if self.children[!side as usize].is_none() {
-            return false;
+    /// Copy the subtree of the node from its storage in the other one, with the updates it still has to hand down
+    fn copy(&self, from: &B, to: &mut B) -> B::Link where T: Clone {
+        let left: B::Link = self.child(from, Side::Left).map_or_else(B::empty, |child: &Self| child.copy(from, to));
+        let right: B::Link = self.child(from, Side::Right).map_or_else(B::empty, |child: &Self| child.copy(from, to));
+        to.alloc(Node {
+            children: [left, right],
+            value: self.value.clone(),
+            duplicates: self.duplicates.clone(),
+            copies: self.copies,
+            height: self.height,
+            size: self.size,
+            summary: self.summary.clone(),
+        })
+    }
+}
+
+/// Every comparison goes through the comparator of the tree, Eq is only used to tell apart the duplicates
+impl<'a, T: 'a + PartialEq, S: 'a + Summary<T>, B: Backend<T, S>> Node<T, S, B> {
+    /// Insert the value in the subtree of the node, the nodes of the path are updated and rebalanced on the way back up
+    fn insert<C: Comparator<T>>(storage: &mut B, node: &mut B::Node, new_value: T, cmp: &C, policy: DuplicatePolicy) -> bool {
+        storage.push_down(node);
+        let current: &mut Self = storage.get_mut(node);
+        let side: Side = match cmp.compare(&new_value, &current.value) {
+            Ordering::Equal => {
+                let res: bool = current.insert_duplicate(new_value, policy);
+                storage.update(node);
+                return res;
+            }
+            Ordering::Less => Side::Left,
+            Ordering::Greater => Side::Right,
+        };
+        let mut res = true;
+        let mut target_node: B::Link = storage.take_child(node, side);
+        if storage.height(&target_node) == 0 {
+            target_node = Node::create_tree(storage, new_value);
+        } else {
+            res = Node::insert(storage, B::node(&mut target_node), new_value, cmp, policy);
+        }
+        storage.set_child(node, side, target_node);
+        balance::rebalance(storage, node);
+        res
+    }
+
+    /// Store a value with the same key as the one of the node, as the policy says
+    /// The node is not updated, the caller does it once it is linked back
+    fn insert_duplicate(&mut self, new_value: T, policy: DuplicatePolicy) -> bool {
+        match policy {
+            DuplicatePolicy::Reject => false,
+            DuplicatePolicy::Replace => {
+                self.value = new_value;
+                true
+            }
+            DuplicatePolicy::Bucket => {
+                if self.value == new_value || self.duplicates.contains(&new_value) {
+                    return false;
+                }
+                self.duplicates.push(new_value);
+                true
+            }
+            DuplicatePolicy::Count => {
+                self.copies += 1;
+                true
+            }
         }
+    }
 
-        let right_node: &mut Box<Node<T>> = self.children[!side as usize].as_mut().unwrap();
-        let right_left_tree = right_node.children[side as usize].take();
-        let right_right_tree = right_node.children[!side as usize].take();
+    /// Check if the exact value (by Eq) is the value of the node or one of its duplicates
+    fn holds(&self, value: &T) -> bool {
+        &self.value == value || self.duplicates.contains(value)
+    }
 
-        let mut new_left_tree = replace(&mut self.children[!side as usize], right_right_tree);
-        swap(&mut self.value, &mut new_left_tree.as_mut().unwrap().value);
-        let left_tree = self.children[side as usize].take();
+    /// Build a node without children out of values sharing a key, the first one is its value and the others are stored as the policy says
+    fn from_values(values: Vec<T>, copies: usize, policy: DuplicatePolicy) -> Option<Self> {
+        let mut values = values.into_iter();
+        let mut node: Self = Node::create_node(values.next()?);
+        for value in values {
+            node.insert_duplicate(value, policy);
+        }
+        node.copies += copies;
+        Some(node)
+    }
 
-        let new_left_node = new_left_tree.as_mut().unwrap();
-        new_left_node.children[!side as usize] = right_left_tree;
-        new_left_node.children[side as usize] = left_tree;
-        self.children[side as usize] = new_left_tree;
+    /// Add a value not lower than the last node to the nodes, as a duplicate if it shares its key (see insert_duplicate)
+    fn push_sorted<C: Comparator<T>>(nodes: &mut Vec<Self>, value: T, cmp: &C, policy: DuplicatePolicy) -> bool {
+        match nodes.last_mut() {
+            Some(last) if cmp.compare(&last.value, &value) == Ordering::Equal => last.insert_duplicate(value, policy),
+            _ => {
+                nodes.push(Node::create_node(value));
+                true
+            }
+        }
+    }
 
-        if let Some(node) = self.children[side as usize].as_mut() {
-            node.update_height();
+    fn remove<C: Comparator<T>>(storage: &mut B, tree: &mut B::Link, value: &T, cmp: &C) -> Option<T> {
+        if storage.height(tree) == 0 {
+            return None;
         }
+        let node: &mut B::Node = B::node(tree);
+        storage.push_down(node);
+        let current: &Self = storage.get(node);
+        let side: Side = match cmp.compare(value, &current.value) {
+            Ordering::Less => Side::Left,
+            Ordering::Greater => Side::Right,
+            Ordering::Equal => {
+                if &current.value == value {
+                    return Self::take_value(storage, tree);
+                }
+                let current: &mut Self = storage.get_mut(node);
+                let position: usize = current.duplicates.iter().position(|duplicate: &T| duplicate == value)?;
+                let old: T = current.duplicates.remove(position);
+                storage.update(node);
+                return Some(old);
+            }
+        };
+        let mut child: B::Link = storage.take_child(node, side);
+        let res: Option<T> = Self::remove(storage, &mut child, value, cmp);
+        storage.set_child(node, side, child);
+        balance::rebalance(storage, node);
+        res
+    }
 
-        self.update_height();
+    /// Find the exact value (by Eq) in the node holding its key (by the comparator), either as its value or in its duplicates
+    fn get_exact<C: Comparator<T>>(storage: &'a B, tree: &'a B::Link, value: &T, cmp: &C) -> Option<&'a T> {
+        let node: &'a Self = Node::get(storage, tree, value, cmp)?;
+        if &node.value == value {
+            return Some(&node.value);
+        }
+        node.duplicates.iter().find(|duplicate: &&T| *duplicate == value)
+    }
 
-        true
+    fn contains_exact<C: Comparator<T>>(storage: &B, tree: &B::Link, value: &T, cmp: &C) -> bool {
+        Node::get_exact(storage, tree, value, cmp).is_some()
     }
+}
 
-    fn rebalance(&mut self) -> bool {
-        match self.balance_factor() {
-            -2 => {
-                let right_node = self.children[Side::Right as usize].as_mut().unwrap();
-                if right_node.balance_factor() == 1 {
-                    right_node.rotate(Side::Right);
-                }
-                self.rotate(Side::Left);
-                true
+/// The lookups by key only need the comparator to know the key, the values can be borrowed as the key
+impl<'a, T: 'a, S: 'a + Summary<T>, B: Backend<T, S>> Node<T, S, B> {
+    /// Take out the value holding the key, its oldest duplicate takes its place if any
+    fn take<Q: ?Sized, C: Comparator<Q>>(storage: &mut B, tree: &mut B::Link, key: &Q, cmp: &C) -> Option<T> where T: Borrow<Q> {
+        if storage.height(tree) == 0 {
+            return None;
+        }
+        let node: &mut B::Node = B::node(tree);
+        storage.push_down(node);
+        let side: Side = match cmp.compare(key, storage.get(node).value.borrow()) {
+            Ordering::Less => Side::Left,
+            Ordering::Greater => Side::Right,
+            Ordering::Equal => return Self::take_value(storage, tree),
+        };
+        let mut child: B::Link = storage.take_child(node, side);
+        let res: Option<T> = Self::take(storage, &mut child, key, cmp);
+        storage.set_child(node, side, child);
+        balance::rebalance(storage, node);
+        res
+    }
+
+    /// Cut the tree in the values lower than the key, the node holding the key (if any) and the values greater than the key
+    /// Each level joins what is left of it with the part of the level below, this is O(log n)
+    fn split<Q: ?Sized, C: Comparator<Q>>(storage: &mut B, tree: B::Link, key: &Q, cmp: &C) -> (B::Link, Option<Self>, B::Link) where T: Borrow<Q> {
+        let mut tree: B::Link = tree;
+        let mut node: Self = match storage.free(&mut tree) {
+            None => return (B::empty(), None, B::empty()),
+            Some(node) => node,
+        };
+        let left: B::Link = replace(&mut node.children[Side::Left as usize], B::empty());
+        let right: B::Link = replace(&mut node.children[Side::Right as usize], B::empty());
+        match cmp.compare(key, node.value.borrow()) {
+            Ordering::Equal => (left, Some(node), right),
+            Ordering::Less => {
+                let (lower, equal, greater) = Self::split(storage, left, key, cmp);
+                let greater: B::Link = Self::join(storage, greater, node, right);
+                (lower, equal, greater)
             }
-            2 => {
-                let left_node = self.children[Side::Left as usize].as_mut().unwrap();
-                if left_node.balance_factor() == -1 {
-                    left_node.rotate(Side::Left);
-                }
-                self.rotate(Side::Right);
-                true
+            Ordering::Greater => {
+                let (lower, equal, greater) = Self::split(storage, right, key, cmp);
+                let lower: B::Link = Self::join(storage, left, node, lower);
+                (lower, equal, greater)
             }
-            _ => {
-                self.update_height();
-                false
+        }
+    }
+
+    fn get_mut<'b, Q: ?Sized, C: Comparator<Q>>(storage: &'b mut B, tree: &'b mut B::Link, key: &Q, cmp: &C) -> Option<&'b mut Self> where T: Borrow<Q> {
+        storage.descend(tree, |storage: &mut B, node: &mut B::Node| {
+            storage.push_down(node);
+            match cmp.compare(key, storage.get(node).value.borrow()) {
+                Ordering::Equal => None,
+                Ordering::Less => Some(Side::Left),
+                Ordering::Greater => Some(Side::Right),
+            }
+        })
+    }
+
+    fn get<Q: ?Sized, C: Comparator<Q>>(storage: &'a B, tree: &'a B::Link, key: &Q, cmp: &C) -> Option<&'a Self> where T: Borrow<Q> {
+        let mut tree: Option<&'a Self> = storage.root(tree);
+        while let Some(node) = tree {
+            tree = match cmp.compare(key, node.value.borrow()) {
+                Ordering::Equal => return Some(node),
+                Ordering::Greater => node.child(storage, Side::Right),
+                Ordering::Less => node.child(storage, Side::Left),
+            };
+        }
+        None
+    }
+
+    /// The node with the nearest key on the side of the key (lower keys for Left), the node of the key itself if inclusive
+    /// The key is given by its order against the values (Less when the key is before the value)
+    /// The last node passed on the way down with its key on that side is the nearest one
+    fn nearest<F: Fn(&T) -> Ordering>(storage: &'a B, tree: &'a B::Link, key: F, side: Side, inclusive: bool) -> Option<&'a Self> {
+        let mut nearest: Option<&'a Self> = None;
+        let mut tree: Option<&'a Self> = storage.root(tree);
+        while let Some(node) = tree {
+            let order: Ordering = key(&node.value);
+            if order == Ordering::Equal && inclusive {
+                return Some(node);
+            }
+            // the key of the node is on the side looked for when the key is on the other one
+            if order == Ordering::Equal || (order == Ordering::Less) == (side == Side::Left) {
+                tree = node.child(storage, side);
+            } else {
+                nearest = Some(node);
+                tree = node.child(storage, !side);
+            }
+        }
+        nearest
+    }
+
+    /// Number of values strictly lower than the key, whether the key is in the tree or not
+    fn rank<Q: ?Sized, C: Comparator<Q>>(storage: &B, tree: &B::Link, key: &Q, cmp: &C) -> usize where T: Borrow<Q> {
+        let mut rank: usize = 0;
+        let mut tree: Option<&Self> = storage.root(tree);
+        while let Some(node) = tree {
+            match cmp.compare(key, node.value.borrow()) {
+                Ordering::Less => tree = node.child(storage, Side::Left),
+                Ordering::Greater => {
+                    rank += node.left_size(storage) + 1 + node.duplicates.len();
+                    tree = node.child(storage, Side::Right);
+                }
+                Ordering::Equal => return rank + node.left_size(storage),
             }
         }
+        rank
     }
 
-    fn count(&self) -> usize {
-        let left: usize;
-        if self.children[Side::Left as usize].is_some() {
-            left = self.children[Side::Left as usize].as_ref().unwrap().count()
-        } else {
-            left = 0;
+    /// Whether the key of the node is after the start bound and before the end bound
+    fn within<Q: ?Sized, C: Comparator<Q>>(&self, start: Bound<&Q>, end: Bound<&Q>, cmp: &C) -> (bool, bool) where T: Borrow<Q> {
+        let after_start: bool = match start {
+            Bound::Included(bound) => cmp.compare(self.value.borrow(), bound) != Ordering::Less,
+            Bound::Excluded(bound) => cmp.compare(self.value.borrow(), bound) == Ordering::Greater,
+            Bound::Unbounded => true,
+        };
+        let before_end: bool = match end {
+            Bound::Included(bound) => cmp.compare(self.value.borrow(), bound) != Ordering::Greater,
+            Bound::Excluded(bound) => cmp.compare(self.value.borrow(), bound) == Ordering::Less,
+            Bound::Unbounded => true,
+        };
+        (after_start, before_end)
+    }
+
+    /// The summary of the values of the subtree between the bounds, a subtree with no bound left is summarized by its root
+    fn aggregate<Q: ?Sized, C: Comparator<Q>>(storage: &B, tree: &B::Link, start: Bound<&Q>, end: Bound<&Q>, cmp: &C) -> S where T: Borrow<Q> {
+        let node: &Self = match storage.root(tree) {
+            None => return S::identity(),
+            Some(node) => node,
+        };
+        if let (Bound::Unbounded, Bound::Unbounded) = (start, end) {
+            return node.summary.clone();
         }
-        let right: usize;
-        if self.children[Side::Right as usize].is_some() {
-            right = self.children[Side::Right as usize].as_ref().unwrap().count()
+        let (after_start, before_end): (bool, bool) = node.within(start, end, cmp);
+        let part: S = if !after_start {
+            Self::aggregate(storage, &node.children[Side::Right as usize], start, end, cmp)
+        } else if !before_end {
+            Self::aggregate(storage, &node.children[Side::Left as usize], start, end, cmp)
         } else {
-            right = 0;
-        }
-        return 1 + self.duplicates.len() + left + right;
+            // the node is inside the range, only the start bound is left on its left and the end bound on its right
+            let left: S = Self::aggregate(storage, &node.children[Side::Left as usize], start, Bound::Unbounded, cmp);
+            let right: S = Self::aggregate(storage, &node.children[Side::Right as usize], Bound::Unbounded, end, cmp);
+            left.combine(&node.values_summary()).combine(&right)
+        };
+        // what the node still has to hand down applies to the part of its subtree too
+        B::rebuild(&node.summary, part)
     }
+}
 
-    fn max(&self) -> &T {
-        if self.children[Side::Right as usize].is_some() {
-            self.children[Side::Right as usize].as_ref().unwrap().max()
+/// The range updates need a summary holding the updates still to hand down
+impl<T, S: Summary<T>, U: Update<T, S>, B: Backend<T, Lazy<S, U>>> Node<T, Lazy<S, U>, B> {
+    /// Apply the update to the values of the subtree between the bounds, a subtree with no bound left only gets it in its root
+    fn update_range<Q: ?Sized, C: Comparator<Q>>(storage: &mut B, tree: &mut B::Link, start: Bound<&Q>, end: Bound<&Q>, update: &U, cmp: &C) where T: Borrow<Q> {
+        if storage.height(tree) == 0 {
+            return;
+        }
+        let node: &mut B::Node = B::node(tree);
+        if let (Bound::Unbounded, Bound::Unbounded) = (start, end) {
+            storage.get_mut(node).summary.tag(update);
+            return;
+        }
+        storage.push_down(node);
+        let (after_start, before_end): (bool, bool) = storage.get(node).within(start, end, cmp);
+        if !after_start {
+            Self::update_child(storage, node, Side::Right, start, end, update, cmp);
+        } else if !before_end {
+            Self::update_child(storage, node, Side::Left, start, end, update, cmp);
         } else {
-            return &self.value;
+            let current: &mut Self = storage.get_mut(node);
+            update.apply(&mut current.value);
+            current.duplicates.iter_mut().for_each(|duplicate: &mut T| update.apply(duplicate));
+            Self::update_child(storage, node, Side::Left, start, Bound::Unbounded, update, cmp);
+            Self::update_child(storage, node, Side::Right, Bound::Unbounded, end, update, cmp);
         }
+        storage.update(node);
     }
 
-    fn min(&self) -> &T {
-        if self.children[Side::Left as usize].is_some() {
-            self.children[Side::Left as usize].as_ref().unwrap().min()
-        } else {
-            return &self.value;
+    fn update_child<Q: ?Sized, C: Comparator<Q>>(storage: &mut B, node: &mut B::Node, side: Side, start: Bound<&Q>, end: Bound<&Q>, update: &U, cmp: &C) where T: Borrow<Q> {
+        let mut child: B::Link = storage.take_child(node, side);
+        Self::update_range(storage, &mut child, start, end, update, cmp);
+        storage.set_child(node, side, child);
+    }
+}
+
+impl<T: Display, S: Summary<T>, B: Backend<T, S>> Node<T, S, B> {
+    fn dump(&self, storage: &B, prettify: bool) -> String {
+        self._dump(storage, prettify,1)
+    }
+
+    /// Write the node and its subtrees with ids taken from next_id (in preorder), return the id of the node
+    fn write_dot<W: io::Write>(&self, storage: &B, writer: &mut W, next_id: &mut usize) -> io::Result<usize> {
+        let id: usize = *next_id;
+        *next_id += 1;
+        let value: String = self.value.to_string().replace('\\', "\\\\").replace('"', "\\\"");
+        writeln!(writer, "    n{} [label=\"{}\\nheight: {} balance: {} duplicates: {}\"];", id, value, self.height, self.balance_factor(storage), self.duplicates.len())?;
+        for side in [Side::Left, Side::Right].iter() {
+            match self.child(storage, *side) {
+                Some(child) => {
+                    let child_id: usize = child.write_dot(storage, writer, next_id)?;
+                    writeln!(writer, "    n{} -> n{};", id, child_id)?;
+                }
+                None => {
+                    writeln!(writer, "    null{} [shape=point];", *next_id)?;
+                    writeln!(writer, "    n{} -> null{};", id, *next_id)?;
+                    *next_id += 1;
+                }
+            }
         }
+        Ok(id)
     }
 
-    fn width(&self) -> usize {
-        let left: usize;
-        if self.children[Side::Left as usize].is_some() {
-            left = self.children[Side::Left as usize].as_ref().unwrap().width()
-        } else {
-            left = 1;
+    fn _dump(&self, storage: &B, prettify: bool, height: usize)->String {
+        let mut pad = String::new();
+        if prettify {
+            pad = "   ".repeat(height);
         }
-        let right: usize;
-        if self.children[Side::Right as usize].is_some() {
-            right = self.children[Side::Right as usize].as_ref().unwrap().width()
+        let mut string_node: String = format!("[{}{},{}", (if prettify { "\n".to_string() } else { String::new() }) + &*pad, self.value, (if prettify { "\n".to_string() } else { String::new() }) + &*pad);
+        if self.child(storage, Side::Left).is_some() {
+            string_node += &*self.child(storage, Side::Left).unwrap()._dump(storage, prettify, height+1);
         } else {
-            right = 1;
+            string_node += "null";
         }
-        if self.children[Side::Left as usize].is_none() && self.children[Side::Right as usize].is_none() {
-            return 1;
+
+        if self.child(storage, Side::Right).is_some() {
+            string_node += &*(",".to_owned()+&( if prettify { "\n".to_string() + &*pad } else { String::new() }));
+            string_node += &*self.child(storage, Side::Right).unwrap()._dump(storage, prettify, height+1);
+        } else {
+            string_node+=",";
+            string_node += &*((if prettify { "\n".to_string() } else { String::new() }) + &*pad + "null");
         }
-        return left + right;
+        return string_node + &*(if prettify { "\n".to_string() + &*( "   ".repeat(max(height,1)-1)) } else { String::new() }) + "]";
     }
 }
@@ -0,0 +1,462 @@
+use core::fmt;
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::iter::{FromIterator, FusedIterator};
+use std::mem::replace;
+
+use std::marker::PhantomData;
+
+use crate::balance;
+use crate::{iter, Boxes, Node, Side, Tree};
+
+/// A map ordered by its keys, built on the same balanced nodes as AvlTree
+/// Only the keys need Ord, the values can be of any type
+/// Each key is stored once, inserting an existing key replaces its value
+pub struct AvlMap<K, V> {
+    root: Tree<(K, V)>,
+}
+
+/// Put the value under the key and return the value it replaced if any
+fn insert<K: Ord, V>(tree: &mut Tree<(K, V)>, key: K, value: V) -> Option<V> {
+    let node: &mut Box<Node<(K, V)>> = match tree {
+        None => {
+            *tree = Some(Box::new(Node::create_node((key, value))));
+            return None;
+        }
+        Some(node) => node,
+    };
+    let side: Side = match key.cmp(&node.value.0) {
+        Ordering::Equal => return Some(replace(&mut node.value.1, value)),
+        Ordering::Less => Side::Left,
+        Ordering::Greater => Side::Right,
+    };
+    let old: Option<V> = insert(&mut node.children[side as usize], key, value);
+    balance::rebalance(&mut Boxes(PhantomData), &mut **node);
+    old
+}
+
+/// Take the entry of the key out of the tree and rebalance on the way back up
+fn remove<K: Borrow<Q>, Q: Ord + ?Sized, V>(tree: &mut Tree<(K, V)>, key: &Q) -> Option<(K, V)> {
+    let node: &mut Box<Node<(K, V)>> = tree.as_mut()?;
+    let side: Side = match key.cmp(node.value.0.borrow()) {
+        Ordering::Less => Side::Left,
+        Ordering::Greater => Side::Right,
+        Ordering::Equal => return Node::take_value(&mut Boxes(PhantomData), tree),
+    };
+    let res: Option<(K, V)> = remove(&mut node.children[side as usize], key);
+    balance::rebalance(&mut Boxes(PhantomData), &mut **node);
+    res
+}
+
+fn get<'a, K: Borrow<Q>, Q: Ord + ?Sized, V>(tree: &'a Tree<(K, V)>, key: &Q) -> Option<&'a Node<(K, V)>> {
+    let mut tree: &'a Tree<(K, V)> = tree;
+    while let Some(node) = tree.as_deref() {
+        tree = match key.cmp(node.value.0.borrow()) {
+            Ordering::Equal => return Some(node),
+            Ordering::Less => &node.children[Side::Left as usize],
+            Ordering::Greater => &node.children[Side::Right as usize],
+        };
+    }
+    None
+}
+
+fn get_mut<'a, K: Borrow<Q>, Q: Ord + ?Sized, V>(tree: &'a mut Tree<(K, V)>, key: &Q) -> Option<&'a mut Node<(K, V)>> {
+    let mut tree: &'a mut Tree<(K, V)> = tree;
+    while let Some(node) = tree {
+        tree = match key.cmp(node.value.0.borrow()) {
+            Ordering::Equal => return Some(node),
+            Ordering::Less => &mut node.children[Side::Left as usize],
+            Ordering::Greater => &mut node.children[Side::Right as usize],
+        };
+    }
+    None
+}
+
+/// The entry with the nearest key on the side of the key (lower keys for Left), the entry of the key itself if inclusive
+fn nearest<'a, K: Borrow<Q>, Q: Ord + ?Sized, V>(tree: &'a Tree<(K, V)>, key: &Q, side: Side, inclusive: bool) -> Option<(&'a K, &'a V)> {
+    Node::nearest(&Boxes(PhantomData), tree, |entry: &(K, V)| key.cmp(entry.0.borrow()), side, inclusive).map(|node: &'a Node<(K, V)>| (&node.value.0, &node.value.1))
+}
+
+impl<K, V> AvlMap<K, V> {
+    /// Create a new empty map
+    pub fn new() -> Self {
+        AvlMap {
+            root: None
+        }
+    }
+
+    /// Get the number of entries in the map in constant time
+    pub fn len(&self) -> usize {
+        self.root.as_deref().map_or(0, |node: &Node<(K, V)>| node.size)
+    }
+
+    /// Check if the map is empty or not
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Remove all the entries of the map, it can be reused afterwards
+    pub fn clear(&mut self) {
+        self.root = None;
+    }
+
+    /// Return the height of the underlying tree
+    pub fn height(&self) -> usize {
+        self.root.as_deref().map_or(0, |node: &Node<(K, V)>| node.height())
+    }
+
+    /// Check if the underlying tree is balanced (this is quite intensive but gave correct result)
+    pub fn is_balanced(&self) -> bool {
+        self.root.as_deref().map_or(true, |node: &Node<(K, V)>| node.is_balanced(&Boxes(PhantomData)))
+    }
+
+    /// Check if the heights and sizes of the underlying tree are correct
+    pub fn is_correct(&self) -> bool {
+        self.root.as_deref().map_or(true, |node: &Node<(K, V)>| node.sanity_check(&Boxes(PhantomData)))
+    }
+
+    /// Iterate over the entries in ascending order of the keys, this can be walked from both ends
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            inner: iter::Iter::from_root(&Boxes(PhantomData), &self.root, self.len()),
+        }
+    }
+
+    /// Iterate over the keys in ascending order
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys {
+            inner: self.iter(),
+        }
+    }
+
+    /// Iterate over the values in ascending order of their keys
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values {
+            inner: self.iter(),
+        }
+    }
+}
+
+impl<K: Ord, V> AvlMap<K, V> {
+    /// Insert a value under the key, if the key was already there its old value is returned
+    /// The key already in the map is kept, only the value is replaced
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        insert(&mut self.root, key, value)
+    }
+
+    /// Get a reference to the value stored under the key
+    /// The key can be anything the keys can be borrowed as (like a &str for a String)
+    pub fn get<Q: Ord + ?Sized>(&self, key: &Q) -> Option<&V> where K: Borrow<Q> {
+        get(&self.root, key).map(|node: &Node<(K, V)>| &node.value.1)
+    }
+
+    /// Get the key and the value stored under the key
+    pub fn get_key_value<Q: Ord + ?Sized>(&self, key: &Q) -> Option<(&K, &V)> where K: Borrow<Q> {
+        get(&self.root, key).map(|node: &Node<(K, V)>| (&node.value.0, &node.value.1))
+    }
+
+    /// Get a mutable reference to the value stored under the key
+    pub fn get_mut<Q: Ord + ?Sized>(&mut self, key: &Q) -> Option<&mut V> where K: Borrow<Q> {
+        get_mut(&mut self.root, key).map(|node: &mut Node<(K, V)>| &mut node.value.1)
+    }
+
+    /// Check if the key is in the map
+    pub fn contains_key<Q: Ord + ?Sized>(&self, key: &Q) -> bool where K: Borrow<Q> {
+        get(&self.root, key).is_some()
+    }
+
+    /// Get the entry with the greatest key lower or equal to the key, in O(log n)
+    pub fn floor<Q: Ord + ?Sized>(&self, key: &Q) -> Option<(&K, &V)> where K: Borrow<Q> {
+        nearest(&self.root, key, Side::Left, true)
+    }
+
+    /// Get the entry with the lowest key greater or equal to the key, in O(log n)
+    pub fn ceiling<Q: Ord + ?Sized>(&self, key: &Q) -> Option<(&K, &V)> where K: Borrow<Q> {
+        nearest(&self.root, key, Side::Right, true)
+    }
+
+    /// Get the entry with the greatest key strictly lower than the key, in O(log n)
+    pub fn predecessor<Q: Ord + ?Sized>(&self, key: &Q) -> Option<(&K, &V)> where K: Borrow<Q> {
+        nearest(&self.root, key, Side::Left, false)
+    }
+
+    /// Get the entry with the lowest key strictly greater than the key, in O(log n)
+    pub fn successor<Q: Ord + ?Sized>(&self, key: &Q) -> Option<(&K, &V)> where K: Borrow<Q> {
+        nearest(&self.root, key, Side::Right, false)
+    }
+
+    /// Remove the key from the map and return its value
+    pub fn remove<Q: Ord + ?Sized>(&mut self, key: &Q) -> Option<V> where K: Borrow<Q> {
+        self.remove_entry(key).map(|(_, value)| value)
+    }
+
+    /// Remove the key from the map and return both the stored key and its value
+    pub fn remove_entry<Q: Ord + ?Sized>(&mut self, key: &Q) -> Option<(K, V)> where K: Borrow<Q> {
+        remove(&mut self.root, key)
+    }
+}
+
+impl<K, V> Default for AvlMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Clone, V: Clone> Clone for AvlMap<K, V> {
+    fn clone(&self) -> Self {
+        AvlMap {
+            root: self.root.clone(),
+        }
+    }
+}
+
+impl<K: PartialEq, V: PartialEq> PartialEq for AvlMap<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<K: Eq, V: Eq> Eq for AvlMap<K, V> {}
+
+impl<K: fmt::Debug, V: fmt::Debug> fmt::Debug for AvlMap<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K: Ord, V> FromIterator<(K, V)> for AvlMap<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map: AvlMap<K, V> = AvlMap::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K: Ord, V> Extend<(K, V)> for AvlMap<K, V> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a AvlMap<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator over the entries of an AvlMap in ascending order of the keys
+pub struct Iter<'a, K, V> {
+    inner: iter::Iter<'a, (K, V)>,
+}
+
+impl<'a, K, V> Clone for Iter<'a, K, V> {
+    fn clone(&self) -> Self {
+        Iter {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(key, value)| (key, value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, K, V> DoubleEndedIterator for Iter<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(key, value)| (key, value))
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for Iter<'a, K, V> {}
+
+impl<'a, K, V> FusedIterator for Iter<'a, K, V> {}
+
+/// An iterator over the keys of an AvlMap in ascending order
+pub struct Keys<'a, K, V> {
+    inner: Iter<'a, K, V>,
+}
+
+impl<'a, K, V> Clone for Keys<'a, K, V> {
+    fn clone(&self) -> Self {
+        Keys {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<'a, K, V> Iterator for Keys<'a, K, V> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(key, _)| key)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, K, V> DoubleEndedIterator for Keys<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(key, _)| key)
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for Keys<'a, K, V> {}
+
+impl<'a, K, V> FusedIterator for Keys<'a, K, V> {}
+
+/// An iterator over the values of an AvlMap in ascending order of their keys
+pub struct Values<'a, K, V> {
+    inner: Iter<'a, K, V>,
+}
+
+impl<'a, K, V> Clone for Values<'a, K, V> {
+    fn clone(&self) -> Self {
+        Values {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<'a, K, V> Iterator for Values<'a, K, V> {
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(_, value)| value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, K, V> DoubleEndedIterator for Values<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(_, value)| value)
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for Values<'a, K, V> {}
+
+impl<'a, K, V> FusedIterator for Values<'a, K, V> {}
+
+#[cfg(test)]
+mod test_map {
+    use super::*;
+
+    /// A value with none of the traits the tree usually asks for
+    #[derive(Debug, PartialEq)]
+    struct Samples(Vec<f64>);
+
+    #[test]
+    fn test_create() {
+        let map: AvlMap<u64, Samples> = AvlMap::new();
+        assert!(map.is_empty());
+        assert_eq!(map.len(), 0);
+        assert_eq!(map.get(&1), None);
+        assert_eq!(map.iter().next(), None);
+    }
+
+    #[test]
+    fn test_insert_get() {
+        let mut map: AvlMap<u64, Samples> = AvlMap::new();
+        for key in 0..100 {
+            assert_eq!(map.insert((key * 37) % 100, Samples(vec![key as f64])), None);
+            assert!(map.is_balanced());
+            assert!(map.is_correct());
+        }
+        assert_eq!(map.len(), 100);
+        assert!(map.height() <= 8);
+        assert_eq!(map.get(&37), Some(&Samples(vec![1.0])));
+        assert_eq!(map.insert(37, Samples(vec![4.2])), Some(Samples(vec![1.0])));
+        assert_eq!(map.get(&37), Some(&Samples(vec![4.2])));
+        assert_eq!(map.len(), 100);
+        assert!(map.contains_key(&99));
+        assert!(!map.contains_key(&100));
+        map.get_mut(&99).expect("Missing key").0.push(6.6);
+        assert_eq!(map.get(&99).expect("Missing key").0.len(), 2);
+        assert!(map.get_mut(&100).is_none());
+    }
+
+    #[test]
+    fn test_nearest() {
+        let map: AvlMap<u64, Samples> = (0..20).map(|key| (key * 100, Samples(vec![key as f64]))).collect();
+        assert_eq!(map.floor(&1250), Some((&1200, &Samples(vec![12.0]))));
+        assert_eq!(map.ceiling(&1250), Some((&1300, &Samples(vec![13.0]))));
+        assert_eq!(map.floor(&1300).map(|(key, _)| *key), Some(1300));
+        assert_eq!(map.predecessor(&1300).map(|(key, _)| *key), Some(1200));
+        assert_eq!(map.successor(&1300).map(|(key, _)| *key), Some(1400));
+        assert_eq!(map.predecessor(&0), None);
+        assert_eq!(map.ceiling(&1901), None);
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut map: AvlMap<u64, Samples> = AvlMap::new();
+        for key in 0..64 {
+            map.insert(key, Samples(vec![key as f64]));
+        }
+        for key in (0..64).filter(|key| key % 3 == 0) {
+            assert_eq!(map.remove(&key), Some(Samples(vec![key as f64])));
+            assert_eq!(map.remove(&key), None);
+            assert!(map.is_balanced());
+            assert!(map.is_correct());
+        }
+        assert_eq!(map.len(), 42);
+        assert_eq!(map.remove_entry(&1), Some((1, Samples(vec![1.0]))));
+        let keys: Vec<u64> = map.keys().cloned().collect();
+        let expected: Vec<u64> = (2..64).filter(|key| key % 3 != 0).collect();
+        assert_eq!(keys, expected);
+        for key in expected {
+            assert!(map.remove(&key).is_some());
+        }
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn test_iter() {
+        let map: AvlMap<&str, usize> = ["delta", "alpha", "echo", "charlie", "bravo"].iter().map(|name| (*name, name.len())).collect();
+        let keys: Vec<&str> = map.keys().cloned().collect();
+        assert_eq!(keys, vec!["alpha", "bravo", "charlie", "delta", "echo"]);
+        let values: Vec<usize> = map.values().rev().cloned().collect();
+        assert_eq!(values, vec![4, 5, 7, 5, 5]);
+        let mut iter = map.iter();
+        assert_eq!(iter.len(), 5);
+        assert_eq!(iter.next(), Some((&"alpha", &5)));
+        assert_eq!(iter.next_back(), Some((&"echo", &4)));
+        assert_eq!(iter.len(), 3);
+        assert_eq!(format!("{:?}", map), r#"{"alpha": 5, "bravo": 5, "charlie": 7, "delta": 5, "echo": 4}"#);
+        assert_eq!(map.clone(), map);
+    }
+
+    #[test]
+    fn test_borrow() {
+        let mut map: AvlMap<String, usize> = AvlMap::new();
+        for word in ["delta", "alpha", "echo", "charlie", "bravo"].iter() {
+            map.insert(word.to_string(), word.len());
+        }
+        assert_eq!(map.get("charlie"), Some(&7));
+        assert!(map.contains_key("echo"));
+        assert!(!map.contains_key("foxtrot"));
+        *map.get_mut("alpha").expect("Missing key") += 1;
+        assert_eq!(map.get_key_value("alpha"), Some((&"alpha".to_string(), &6)));
+        assert_eq!(map.remove("delta"), Some(5));
+        assert_eq!(map.remove_entry("echo"), Some(("echo".to_string(), 4)));
+        assert_eq!(map.len(), 3);
+        assert!(map.is_correct());
+    }
+}
@@ -0,0 +1,140 @@
+use std::cmp::{max, min, Ordering};
+
+use crate::storage::Backend;
+use crate::{AvlError, Comparator, Node, Side, Summary};
+
+/// A valid AVL tree this deep would hold more values than the memory can, deeper dumps are refused before recursing
+const MAX_DEPTH: usize = 128;
+
+/// Read back the nested [value,left,right] shape written by dump (pretty or not), null being an empty tree
+/// The text of each value (as written by Display) is handed to parse_value
+/// The positions in the errors are byte offsets in the dump
+pub(crate) struct DumpParser<'a, F> {
+    dump: &'a str,
+    position: usize,
+    parse_value: F,
+    /// Start of each node in the in order walk, to point at the value breaking the order
+    positions: Vec<usize>,
+}
+
+impl<'a, T, E, F: FnMut(&str) -> Result<T, E>> DumpParser<'a, F> {
+    pub(crate) fn new(dump: &'a str, parse_value: F) -> Self {
+        DumpParser {
+            dump,
+            position: 0,
+            parse_value,
+            positions: Vec::new(),
+        }
+    }
+
+    /// Build the tree exactly as described by the dump, then check the order of its values with the comparator
+    pub(crate) fn parse<C: Comparator<T>, S: Summary<T>, B: Backend<T, S>>(mut self, storage: &mut B, cmp: &C) -> Result<B::Link, AvlError> {
+        let root: B::Link = self.tree(storage, 0)?;
+        self.skip_whitespaces();
+        if self.position != self.dump.len() {
+            return Err(AvlError::InvalidDump(self.position));
+        }
+        let storage: &B = storage;
+        let mut stack: Vec<&Node<T, S, B>> = Vec::new();
+        let mut tree: Option<&Node<T, S, B>> = storage.root(&root);
+        let mut previous: Option<&T> = None;
+        let mut positions = self.positions.iter();
+        loop {
+            while let Some(node) = tree {
+                stack.push(node);
+                tree = node.child(storage, Side::Left);
+            }
+            let node: &Node<T, S, B> = match stack.pop() {
+                None => break,
+                Some(node) => node,
+            };
+            let position: usize = *positions.next().unwrap();
+            if let Some(previous) = previous {
+                if cmp.compare(previous, &node.value) != Ordering::Less {
+                    return Err(AvlError::UnorderedDump(position));
+                }
+            }
+            previous = Some(&node.value);
+            tree = node.child(storage, Side::Right);
+        }
+        Ok(root)
+    }
+
+    fn tree<S: Summary<T>, B: Backend<T, S>>(&mut self, storage: &mut B, depth: usize) -> Result<B::Link, AvlError> {
+        self.skip_whitespaces();
+        let start: usize = self.position;
+        if self.dump[start..].starts_with("null") {
+            self.position += 4;
+            return Ok(B::empty());
+        }
+        if depth > MAX_DEPTH {
+            return Err(AvlError::UnbalancedDump(start));
+        }
+        self.expect(b'[')?;
+        let value: T = self.value()?;
+        self.expect(b',')?;
+        let left: B::Link = self.tree(storage, depth + 1)?;
+        self.positions.push(start);
+        self.expect(b',')?;
+        let right: B::Link = self.tree(storage, depth + 1)?;
+        self.expect(b']')?;
+        let mut node: Node<T, S, B> = Node::create_node(value);
+        node.children = [left, right];
+        node.update(storage);
+        let (lh, rh): (usize, usize) = (node.left_height(storage), node.right_height(storage));
+        if max(lh, rh) - min(lh, rh) > 1 {
+            return Err(AvlError::UnbalancedDump(start));
+        }
+        Ok(storage.alloc(node))
+    }
+
+    /// The value goes up to the first comma outside of any string or bracket
+    fn value(&mut self) -> Result<T, AvlError> {
+        self.skip_whitespaces();
+        let start: usize = self.position;
+        let bytes: &[u8] = self.dump.as_bytes();
+        let mut depth: usize = 0;
+        let mut in_string: bool = false;
+        let mut escaped: bool = false;
+        while self.position < bytes.len() {
+            let byte: u8 = bytes[self.position];
+            if in_string {
+                match byte {
+                    _ if escaped => escaped = false,
+                    b'\\' => escaped = true,
+                    b'"' => in_string = false,
+                    _ => {}
+                }
+            } else {
+                match byte {
+                    b'"' => in_string = true,
+                    b'[' | b'{' => depth += 1,
+                    b']' | b'}' if depth == 0 => break,
+                    b']' | b'}' => depth -= 1,
+                    b',' if depth == 0 => break,
+                    _ => {}
+                }
+            }
+            self.position += 1;
+        }
+        let text: &str = self.dump[start..self.position].trim_end();
+        if text.is_empty() || in_string || depth != 0 {
+            return Err(AvlError::InvalidDump(start));
+        }
+        (self.parse_value)(text).map_err(|_| AvlError::InvalidDump(start))
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), AvlError> {
+        self.skip_whitespaces();
+        if self.dump.as_bytes().get(self.position) != Some(&byte) {
+            return Err(AvlError::InvalidDump(self.position));
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    fn skip_whitespaces(&mut self) {
+        let rest: &str = &self.dump[self.position..];
+        self.position += rest.len() - rest.trim_start().len();
+    }
+}
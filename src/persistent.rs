@@ -0,0 +1,594 @@
+use core::fmt;
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::iter::FusedIterator;
+use std::marker::PhantomData;
+use std::mem::swap;
+use std::sync::Arc;
+
+use crate::balance::{self, Storage, StorageMut};
+use crate::{AvlError, AvlTree, Comparator, DuplicatePolicy, Natural, Side, Tree};
+
+type Link<T> = Option<Arc<Node<T>>>;
+
+/// A node shared by all the versions of the tree holding it, it is never changed once built
+/// A change copies the nodes on the path from the root to the changed node and keeps pointing to the other ones
+#[derive(Clone)]
+struct Node<T> {
+    children: [Link<T>; 2],
+    value: T,
+    duplicates: Vec<T>,
+    /// Insertions of the key counted instead of stored (see DuplicatePolicy::Count), they are not part of the size
+    copies: usize,
+    height: usize,
+    size: usize,
+}
+
+/// An immutable AVL tree whose versions share their unchanged nodes
+/// insert and remove return a new version in O(log n) and leave this one as it was, clone is O(1)
+/// The nodes are reference counted with Arc so the versions can be handed to other threads
+pub struct PersistentAvlTree<T, C = Natural> {
+    root: Link<T>,
+    cmp: C,
+    policy: DuplicatePolicy,
+}
+
+impl<T> Node<T> {
+    fn leaf(value: T) -> Self {
+        Node {
+            children: [None, None],
+            value,
+            duplicates: Vec::new(),
+            copies: 0,
+            height: 1,
+            size: 1,
+        }
+    }
+
+    fn height(link: &Link<T>) -> usize {
+        link.as_deref().map_or(0, |node: &Node<T>| node.height)
+    }
+
+    fn size(link: &Link<T>) -> usize {
+        link.as_deref().map_or(0, |node: &Node<T>| node.size)
+    }
+
+    fn update(&mut self) {
+        self.height = balance::height(&Shared(PhantomData), self);
+        self.size = 1 + self.duplicates.len() + Self::size(&self.children[Side::Left as usize]) + Self::size(&self.children[Side::Right as usize]);
+    }
+
+    fn sanity_check(&self) -> bool {
+        let mut is_correct: bool = true;
+        for child in self.children.iter().flatten() {
+            is_correct &= child.sanity_check();
+        }
+        let expected_height: usize = balance::height(&Shared(PhantomData), self);
+        let expected_size: usize = 1 + self.duplicates.len() + Self::size(&self.children[Side::Left as usize]) + Self::size(&self.children[Side::Right as usize]);
+        is_correct && self.height == expected_height && self.size == expected_size
+    }
+
+    fn is_balanced(&self) -> bool {
+        balance::balance_factor(&Shared(PhantomData), self).abs() <= 1 && self.children.iter().flatten().all(|child: &Arc<Node<T>>| child.is_balanced())
+    }
+
+    fn get<'a, Q: ?Sized, C: Comparator<Q>>(link: &'a Link<T>, key: &Q, cmp: &C) -> Option<&'a Node<T>> where T: Borrow<Q> {
+        let mut link: &'a Link<T> = link;
+        while let Some(node) = link.as_deref() {
+            link = match cmp.compare(key, node.value.borrow()) {
+                Ordering::Equal => return Some(node),
+                Ordering::Less => &node.children[Side::Left as usize],
+                Ordering::Greater => &node.children[Side::Right as usize],
+            };
+        }
+        None
+    }
+
+    /// Turn the nodes of a mutable tree into shared ones, in linear time
+    fn from_tree(tree: Tree<T>) -> Link<T> {
+        let node: crate::Node<T> = *tree?;
+        let [left, right] = node.children;
+        Some(Arc::new(Node {
+            children: [Self::from_tree(left), Self::from_tree(right)],
+            value: node.value,
+            duplicates: node.duplicates,
+            copies: node.copies,
+            height: node.height,
+            size: node.size,
+        }))
+    }
+}
+
+/// The path copying needs Clone, a node is copied with its value and duplicates but its children are only shared
+impl<T: Clone> Node<T> {
+    /// Update the node and rotate it if its children heights differ by 2, the rotated children are copied if they are shared
+    fn rebalance(mut self) -> Arc<Node<T>> {
+        balance::rebalance(&mut Shared(PhantomData), &mut self);
+        Arc::new(self)
+    }
+
+    /// Copy the path down to the place of the value and build the new version of the subtree holding it
+    fn insert<C: Comparator<T>>(link: &Link<T>, value: T, cmp: &C, policy: DuplicatePolicy) -> Result<Arc<Node<T>>, AvlError> where T: PartialEq {
+        let node: &Arc<Node<T>> = match link {
+            None => return Ok(Arc::new(Node::leaf(value))),
+            Some(node) => node,
+        };
+        let side: Side = match cmp.compare(&value, &node.value) {
+            Ordering::Less => Side::Left,
+            Ordering::Greater => Side::Right,
+            Ordering::Equal => {
+                // a refused value is found on the shared node, nothing is copied for it
+                let refused: bool = match policy {
+                    DuplicatePolicy::Reject => true,
+                    DuplicatePolicy::Bucket => node.value == value || node.duplicates.contains(&value),
+                    DuplicatePolicy::Replace | DuplicatePolicy::Count => false,
+                };
+                if refused {
+                    return Err(policy.refusal());
+                }
+                let mut copy: Node<T> = (**node).clone();
+                match policy {
+                    DuplicatePolicy::Replace => copy.value = value,
+                    DuplicatePolicy::Count => copy.copies += 1,
+                    // Reject never gets here, it refuses every value
+                    DuplicatePolicy::Reject | DuplicatePolicy::Bucket => copy.duplicates.push(value),
+                }
+                copy.update();
+                return Ok(Arc::new(copy));
+            }
+        };
+        let child: Arc<Node<T>> = Self::insert(&node.children[side as usize], value, cmp, policy)?;
+        let mut copy: Node<T> = (**node).clone();
+        copy.children[side as usize] = Some(child);
+        Ok(copy.rebalance())
+    }
+
+    /// Build the new version of the subtree without the exact value (by Eq), None if the value is not in the subtree
+    fn remove<C: Comparator<T>>(link: &Link<T>, value: &T, cmp: &C) -> Option<Link<T>> where T: PartialEq {
+        let node: &Arc<Node<T>> = link.as_ref()?;
+        let side: Side = match cmp.compare(value, &node.value) {
+            Ordering::Less => Side::Left,
+            Ordering::Greater => Side::Right,
+            Ordering::Equal => {
+                // a value which is not there is found on the shared node, nothing is copied for it
+                let position: Option<usize> = node.duplicates.iter().position(|duplicate: &T| duplicate == value);
+                if &node.value != value && position.is_none() {
+                    return None;
+                }
+                let mut copy: Node<T> = (**node).clone();
+                if copy.copies > 0 {
+                    // a counted insertion goes away before the value itself
+                    copy.copies -= 1;
+                } else if &copy.value != value {
+                    copy.duplicates.remove(position.unwrap());
+                } else if !copy.duplicates.is_empty() {
+                    copy.value = copy.duplicates.remove(0);
+                } else {
+                    return Some(Self::remove_node(node));
+                }
+                copy.update();
+                return Some(Some(Arc::new(copy)));
+            }
+        };
+        let child: Link<T> = Self::remove(&node.children[side as usize], value, cmp)?;
+        let mut copy: Node<T> = (**node).clone();
+        copy.children[side as usize] = child;
+        Some(Some(copy.rebalance()))
+    }
+
+    /// The subtree without its root, the successor of the root takes its place when it has two children
+    fn remove_node(node: &Arc<Node<T>>) -> Link<T> {
+        match &node.children {
+            [None, right] => right.clone(),
+            [left, None] => left.clone(),
+            [left, Some(right)] => {
+                let (rest, mut min): (Link<T>, Node<T>) = Self::remove_min(right);
+                min.children = [left.clone(), rest];
+                Some(min.rebalance())
+            }
+        }
+    }
+
+    /// The subtree without its left most node, and a copy of that node
+    fn remove_min(node: &Arc<Node<T>>) -> (Link<T>, Node<T>) {
+        match &node.children[Side::Left as usize] {
+            None => (node.children[Side::Right as usize].clone(), (**node).clone()),
+            Some(left) => {
+                let (rest, min): (Link<T>, Node<T>) = Self::remove_min(left);
+                let mut copy: Node<T> = (**node).clone();
+                copy.children[Side::Left as usize] = rest;
+                (Some(copy.rebalance()), min)
+            }
+        }
+    }
+}
+
+/// The nodes of a PersistentAvlTree are shared with Arc, a rotation copies the ones still held by another version
+struct Shared<T>(PhantomData<T>);
+
+impl<T> Storage for Shared<T> {
+    type Node = Node<T>;
+    type Link = Link<T>;
+
+    fn height(&self, link: &Link<T>) -> usize {
+        Node::height(link)
+    }
+
+    fn child<'a>(&'a self, node: &'a Node<T>, side: Side) -> &'a Link<T> {
+        &node.children[side as usize]
+    }
+}
+
+impl<T: Clone> StorageMut for Shared<T> {
+    fn node(link: &mut Link<T>) -> &mut Node<T> {
+        Arc::make_mut(link.as_mut().unwrap())
+    }
+
+    fn take_child(&mut self, node: &mut Node<T>, side: Side) -> Link<T> {
+        node.children[side as usize].take()
+    }
+
+    fn set_child(&mut self, node: &mut Node<T>, side: Side, child: Link<T>) {
+        node.children[side as usize] = child;
+    }
+
+    fn swap_values(&mut self, node: &mut Node<T>, other: &mut Node<T>) {
+        swap(&mut node.value, &mut other.value);
+        swap(&mut node.duplicates, &mut other.duplicates);
+        swap(&mut node.copies, &mut other.copies);
+    }
+
+    fn update(&mut self, node: &mut Node<T>) {
+        node.update();
+    }
+}
+
+impl<T, C: Clone> Clone for PersistentAvlTree<T, C> {
+    /// Only the root is shared, this is O(1)
+    fn clone(&self) -> Self {
+        PersistentAvlTree {
+            root: self.root.clone(),
+            cmp: self.cmp.clone(),
+            policy: self.policy,
+        }
+    }
+}
+
+impl<T: fmt::Debug, C> fmt::Debug for PersistentAvlTree<T, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<T, C: Default> Default for PersistentAvlTree<T, C> {
+    fn default() -> Self {
+        Self::with_comparator(C::default())
+    }
+}
+
+impl<T> PersistentAvlTree<T> {
+    /// Create a new empty tree ordered by Ord
+    pub fn new() -> Self {
+        Self::with_comparator(Natural)
+    }
+
+    /// Create a new empty tree ordered by Ord which handles the values sharing a key as the policy says
+    pub fn with_policy(policy: DuplicatePolicy) -> Self {
+        Self::with_comparator_and_policy(Natural, policy)
+    }
+}
+
+impl<T, C> PersistentAvlTree<T, C> {
+    /// Create a new empty tree ordered by the comparator
+    pub fn with_comparator(cmp: C) -> Self {
+        Self::with_comparator_and_policy(cmp, DuplicatePolicy::default())
+    }
+
+    /// Create a new empty tree ordered by the comparator which handles the values sharing a key as the policy says
+    pub fn with_comparator_and_policy(cmp: C, policy: DuplicatePolicy) -> Self {
+        PersistentAvlTree {
+            root: None,
+            cmp,
+            policy,
+        }
+    }
+
+    /// Get the number of values in constant time, duplicates included
+    pub fn len(&self) -> usize {
+        Node::size(&self.root)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    pub fn height(&self) -> usize {
+        Node::height(&self.root)
+    }
+
+    /// Check if the two versions are the same tree (they share their root), this is O(1)
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        match (&self.root, &other.root) {
+            (None, None) => true,
+            (Some(ours), Some(theirs)) => Arc::ptr_eq(ours, theirs),
+            _ => false,
+        }
+    }
+
+    /// Iterate over the values in ascending order, values sharing the same key are yielded together
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter::new(&self.root, self.len())
+    }
+
+    pub fn first(&self) -> Option<&T> {
+        self.iter().next()
+    }
+
+    pub fn is_balanced(&self) -> bool {
+        self.root.as_deref().map_or(true, |node: &Node<T>| node.is_balanced())
+    }
+
+    /// Check if the heights and sizes of every node are correct
+    pub fn is_correct(&self) -> bool {
+        self.root.as_deref().map_or(true, |node: &Node<T>| node.sanity_check())
+    }
+}
+
+impl<T: Eq + Clone, C: Comparator<T> + Clone> PersistentAvlTree<T, C> {
+    /// Get a new version with the value inserted as the policy says, this version is left untouched
+    /// Only the O(log n) nodes of the path to the value are copied, the new version shares all the others
+    pub fn insert(&self, value: T) -> Result<Self, AvlError> {
+        Ok(PersistentAvlTree {
+            root: Some(Node::insert(&self.root, value, &self.cmp, self.policy)?),
+            cmp: self.cmp.clone(),
+            policy: self.policy,
+        })
+    }
+
+    /// Get a new version without the exact value (by Eq), this version is left untouched
+    /// With DuplicatePolicy::Count a counted insertion goes away before the value itself
+    pub fn remove(&self, value: &T) -> Result<Self, AvlError> {
+        if self.root.is_none() {
+            return Err(AvlError::Empty);
+        }
+        Ok(PersistentAvlTree {
+            root: Node::remove(&self.root, value, &self.cmp).ok_or(AvlError::NotFound)?,
+            cmp: self.cmp.clone(),
+            policy: self.policy,
+        })
+    }
+}
+
+impl<T, C> PersistentAvlTree<T, C> {
+    /// Get a reference to a value based only on the comparator (not Eq), the oldest one if duplicate keys
+    pub fn get_ref<Q: ?Sized>(&self, key: &Q) -> Option<&T> where T: Borrow<Q>, C: Comparator<Q> {
+        Node::get(&self.root, key, &self.cmp).map(|node: &Node<T>| &node.value)
+    }
+
+    /// Check if a value is contained in the tree with the comparator only
+    pub fn contains<Q: ?Sized>(&self, key: &Q) -> bool where T: Borrow<Q>, C: Comparator<Q> {
+        Node::get(&self.root, key, &self.cmp).is_some()
+    }
+
+    /// Get how many times the key is in the tree, as AvlTree::occurrences
+    pub fn occurrences<Q: ?Sized>(&self, key: &Q) -> usize where T: Borrow<Q>, C: Comparator<Q> {
+        Node::get(&self.root, key, &self.cmp).map_or(0, |node: &Node<T>| 1 + node.duplicates.len() + node.copies)
+    }
+}
+
+/// The mutable tree is turned into the first version of a persistent one in linear time, without copying the values
+impl<T, C> From<AvlTree<T, C>> for PersistentAvlTree<T, C> {
+    fn from(tree: AvlTree<T, C>) -> Self {
+        PersistentAvlTree {
+            root: Node::from_tree(tree.root),
+            cmp: tree.cmp,
+            policy: tree.policy,
+        }
+    }
+}
+
+/// An iterator over the values of a PersistentAvlTree in ascending order
+pub struct Iter<'a, T> {
+    stack: Vec<&'a Node<T>>,
+    /// Values of the current node still to yield, in reverse order
+    bucket: Vec<&'a T>,
+    remaining: usize,
+}
+
+impl<'a, T> Iter<'a, T> {
+    fn new(root: &'a Link<T>, len: usize) -> Self {
+        let mut iter = Iter {
+            stack: Vec::new(),
+            bucket: Vec::new(),
+            remaining: len,
+        };
+        iter.push_spine(root);
+        iter
+    }
+
+    fn push_spine(&mut self, mut link: &'a Link<T>) {
+        while let Some(node) = link.as_deref() {
+            self.stack.push(node);
+            link = &node.children[Side::Left as usize];
+        }
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.bucket.is_empty() {
+            let node: &'a Node<T> = self.stack.pop()?;
+            self.bucket.extend(node.duplicates.iter().rev());
+            self.bucket.push(&node.value);
+            self.push_spine(&node.children[Side::Right as usize]);
+        }
+        self.remaining -= 1;
+        self.bucket.pop()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+
+impl<'a, T> FusedIterator for Iter<'a, T> {}
+
+impl<'a, T, C> IntoIterator for &'a PersistentAvlTree<T, C> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test_persistent {
+    use super::*;
+    use crate::fixture::{shuffled, Position};
+    use std::collections::HashSet;
+    use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+    use std::thread;
+
+    /// The nodes of the tree, to see which ones two versions share
+    fn nodes<T>(link: &Link<T>, found: &mut HashSet<*const Node<T>>) {
+        if let Some(node) = link {
+            found.insert(Arc::as_ptr(node));
+            for child in node.children.iter() {
+                nodes(child, found);
+            }
+        }
+    }
+
+    fn build(values: impl Iterator<Item = u64>) -> PersistentAvlTree<u64> {
+        values.fold(PersistentAvlTree::new(), |tree: PersistentAvlTree<u64>, value: u64| tree.insert(value).expect("Failed insert"))
+    }
+
+    #[test]
+    fn test_insert() {
+        let empty: PersistentAvlTree<u64> = PersistentAvlTree::new();
+        let mut versions: Vec<PersistentAvlTree<u64>> = vec![empty];
+        for value in shuffled(100) {
+            let next: PersistentAvlTree<u64> = versions.last().unwrap().insert(value).expect("Failed insert");
+            assert!(next.is_correct());
+            assert!(next.is_balanced());
+            versions.push(next);
+        }
+        for (len, version) in versions.iter().enumerate() {
+            assert_eq!(version.len(), len);
+            assert_eq!(version.iter().count(), len);
+        }
+        assert!(versions[100].iter().cloned().eq(0..100));
+        assert!(!versions[10].contains(&((10 * 37) % 100)));
+        assert!(versions[11].contains(&((10 * 37) % 100)));
+        assert_eq!(versions[100].insert(5).unwrap_err(), AvlError::DuplicateValue);
+    }
+
+    #[test]
+    fn test_remove() {
+        let full: PersistentAvlTree<u64> = build(0..200);
+        let mut tree: PersistentAvlTree<u64> = full.clone();
+        for value in (0..200).filter(|value| value % 4 != 1) {
+            tree = tree.remove(&value).expect("Failed remove");
+            assert!(tree.is_correct());
+            assert!(tree.is_balanced());
+        }
+        assert!(tree.iter().cloned().eq((0..200).filter(|value| value % 4 == 1)));
+        assert_eq!(full.len(), 200);
+        assert!(full.iter().cloned().eq(0..200));
+        assert_eq!(tree.remove(&0).unwrap_err(), AvlError::NotFound);
+        assert_eq!(PersistentAvlTree::<u64>::new().remove(&0).unwrap_err(), AvlError::Empty);
+    }
+
+    #[test]
+    fn test_sharing() {
+        let tree: PersistentAvlTree<u64> = build(0..1000);
+        let snapshot: PersistentAvlTree<u64> = tree.clone();
+        assert!(snapshot.ptr_eq(&tree));
+        let mut before: HashSet<*const Node<u64>> = HashSet::new();
+        nodes(&tree.root, &mut before);
+        for next in [tree.insert(1000).expect("Failed insert"), tree.remove(&500).expect("Failed remove")].iter() {
+            assert!(!next.ptr_eq(&tree));
+            let mut after: HashSet<*const Node<u64>> = HashSet::new();
+            nodes(&next.root, &mut after);
+            // only the path to the change (and the rotated nodes) are new
+            assert!(after.difference(&before).count() <= tree.height() + 2);
+        }
+        assert!(tree.iter().cloned().eq(0..1000));
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let by_x = |left: &Position, right: &Position| left.x.cmp(&right.x);
+        let tree = PersistentAvlTree::with_comparator(by_x)
+            .insert(Position { x: 1, y: 0 }).expect("Failed insert")
+            .insert(Position { x: 1, y: 1 }).expect("Failed insert")
+            .insert(Position { x: 0, y: 0 }).expect("Failed insert");
+        assert_eq!(tree.len(), 3);
+        assert_eq!(tree.occurrences(&Position { x: 1, y: -1 }), 2);
+        let removed = tree.remove(&Position { x: 1, y: 0 }).expect("Failed remove");
+        assert_eq!(removed.get_ref(&Position { x: 1, y: -1 }), Some(&Position { x: 1, y: 1 }));
+        assert_eq!(tree.get_ref(&Position { x: 1, y: -1 }), Some(&Position { x: 1, y: 0 }));
+        let counted: PersistentAvlTree<u64> = PersistentAvlTree::with_policy(DuplicatePolicy::Count)
+            .insert(7).expect("Failed insert")
+            .insert(7).expect("Failed insert");
+        assert_eq!(counted.occurrences(&7), 2);
+        assert_eq!(counted.remove(&7).expect("Failed remove").occurrences(&7), 1);
+        assert_eq!(counted.len(), 1);
+    }
+
+    /// A value counting how many times it was cloned
+    #[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+    struct Counted(u64);
+
+    static CLONES: AtomicUsize = AtomicUsize::new(0);
+
+    impl Clone for Counted {
+        fn clone(&self) -> Self {
+            CLONES.fetch_add(1, AtomicOrdering::SeqCst);
+            Counted(self.0)
+        }
+    }
+
+    #[test]
+    fn test_refused_copies_nothing() {
+        let mut bucket: PersistentAvlTree<Counted> = PersistentAvlTree::new();
+        let mut reject: PersistentAvlTree<Counted> = PersistentAvlTree::with_policy(DuplicatePolicy::Reject);
+        for value in shuffled(100) {
+            bucket = bucket.insert(Counted(value)).expect("Failed insert");
+            reject = reject.insert(Counted(value)).expect("Failed insert");
+        }
+        CLONES.store(0, AtomicOrdering::SeqCst);
+        assert_eq!(bucket.insert(Counted(42)).unwrap_err(), AvlError::DuplicateValue);
+        assert_eq!(reject.insert(Counted(42)).unwrap_err(), AvlError::DuplicateKey);
+        assert_eq!(bucket.remove(&Counted(100)).unwrap_err(), AvlError::NotFound);
+        assert_eq!(CLONES.load(AtomicOrdering::SeqCst), 0);
+        assert_eq!(bucket.insert(Counted(100)).expect("Failed insert").len(), 101);
+        assert!(CLONES.load(AtomicOrdering::SeqCst) > 0);
+    }
+
+    #[test]
+    fn test_from_tree() {
+        let tree: AvlTree<u64> = (0..50).collect();
+        let persistent: PersistentAvlTree<u64> = PersistentAvlTree::from(tree);
+        assert!(persistent.is_correct());
+        assert!(persistent.iter().cloned().eq(0..50));
+        assert_eq!(persistent.first(), Some(&0));
+    }
+
+    #[test]
+    fn test_snapshot_threads() {
+        let mut tree: PersistentAvlTree<u64> = build(0..100);
+        let snapshot: PersistentAvlTree<u64> = tree.clone();
+        let reader = thread::spawn(move || snapshot.iter().sum::<u64>());
+        for value in 100..200 {
+            tree = tree.insert(value).expect("Failed insert");
+        }
+        assert_eq!(reader.join().expect("Failed reader"), (0..100).sum::<u64>());
+        assert_eq!(tree.len(), 200);
+    }
+}
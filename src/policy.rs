@@ -0,0 +1,33 @@
+use crate::AvlError;
+
+/// What a tree does with a value whose key (by its comparator) is already in the tree
+/// The policy is chosen when the tree is built and can not change afterwards
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DuplicatePolicy {
+    /// Refuse the value, insert returns AvlError::DuplicateKey
+    Reject,
+    /// The value takes the place of the one holding the key
+    Replace,
+    /// Keep every value different by Eq next to the one holding the key, in insertion order (first in, first out)
+    /// The same value (by Eq) can not be inserted twice, insert returns AvlError::DuplicateValue
+    Bucket,
+    /// Only keep the first value and count how many times its key was inserted, see AvlTree::occurrences
+    Count,
+}
+
+/// Bucket is the default, it keeps every value like the tree always did
+impl Default for DuplicatePolicy {
+    fn default() -> Self {
+        DuplicatePolicy::Bucket
+    }
+}
+
+impl DuplicatePolicy {
+    /// The error given when a value is refused under this policy
+    pub(crate) fn refusal(self) -> AvlError {
+        match self {
+            DuplicatePolicy::Reject => AvlError::DuplicateKey,
+            _ => AvlError::DuplicateValue,
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use core::fmt;
+use std::fmt::Display;
+
+use crate::balance::Storage;
+use crate::storage::Backend;
+use crate::{AvlTree, Boxes, Node, Side, Summary};
+
+/// A top-down drawing of an AvlTree on a given number of columns, to print or to paste in a bug report
+/// Each level splits the width of its parent in two and the labels are centered in their part,
+/// a label too long for its part is cut
+pub struct Ascii<'a, T, C, S = (), B: Storage = Boxes<T, S>> {
+    tree: &'a AvlTree<T, C, S, B>,
+    width: usize,
+    annotate: bool,
+    max_depth: usize,
+}
+
+impl<'a, T, C, S: Summary<T>, B: Backend<T, S>> Ascii<'a, T, C, S, B> {
+    pub(crate) fn new(tree: &'a AvlTree<T, C, S, B>, width: usize) -> Self {
+        Ascii {
+            tree,
+            width: width.max(1),
+            annotate: false,
+            max_depth: usize::MAX,
+        }
+    }
+
+    /// Show the height and the balance factor of each node after its value, like 42 (h2 b-1)
+    pub fn annotate(mut self, annotate: bool) -> Self {
+        self.annotate = annotate;
+        self
+    }
+
+    /// Only draw the first levels of the tree, the subtrees below are drawn as ...
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth.max(1);
+        self
+    }
+
+    /// Column of the center of the part of the line given to the node at this index of its level
+    fn center(&self, depth: usize, index: usize) -> usize {
+        let start: usize = (index * self.width) >> depth;
+        let end: usize = ((index + 1) * self.width) >> depth;
+        (start + end) / 2
+    }
+
+    /// Write the label centered on the column, cut to the room its node has on this level
+    fn place(&self, line: &mut [char], depth: usize, index: usize, label: &str) {
+        let room: usize = (self.width >> depth).max(1);
+        let label: Vec<char> = label.chars().take(room).collect();
+        let start: usize = self.center(depth, index).saturating_sub(label.len() / 2).min(self.width - label.len());
+        line[start..start + label.len()].copy_from_slice(&label);
+    }
+
+    fn write_line(&self, f: &mut fmt::Formatter<'_>, line: &[char]) -> fmt::Result {
+        let line: String = line.iter().collect();
+        writeln!(f, "{}", line.trim_end())
+    }
+}
+
+impl<'a, T: Display, C, S: Summary<T>, B: Backend<T, S>> Display for Ascii<'a, T, C, S, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let storage: &B = &self.tree.storage;
+        let mut level: Vec<(usize, &Node<T, S, B>)> = storage.root(&self.tree.root).map(|root: &Node<T, S, B>| (0, root)).into_iter().collect();
+        let mut depth: usize = 0;
+        while !level.is_empty() {
+            let mut line: Vec<char> = vec![' '; self.width];
+            for (index, node) in level.iter() {
+                let label: String = if self.annotate {
+                    format!("{} (h{} b{})", node.value, node.height, node.balance_factor(storage))
+                } else {
+                    node.value.to_string()
+                };
+                self.place(&mut line, depth, *index, &label);
+            }
+            self.write_line(f, &line)?;
+            let mut next: Vec<(usize, &Node<T, S, B>)> = Vec::new();
+            let mut links: Vec<char> = vec![' '; self.width];
+            for (index, node) in level.iter() {
+                let center: usize = self.center(depth, *index);
+                for (side, link) in [(Side::Left, '/'), (Side::Right, '\\')].iter() {
+                    if let Some(child) = node.child(storage, *side) {
+                        let child_index: usize = 2 * index + *side as usize;
+                        links[(center + self.center(depth + 1, child_index)) / 2] = *link;
+                        next.push((child_index, child));
+                    }
+                }
+            }
+            if next.is_empty() {
+                break;
+            }
+            self.write_line(f, &links)?;
+            depth += 1;
+            if depth == self.max_depth {
+                let mut line: Vec<char> = vec![' '; self.width];
+                for (index, _) in next.iter() {
+                    self.place(&mut line, depth, *index, "...");
+                }
+                return self.write_line(f, &line);
+            }
+            level = next;
+        }
+        Ok(())
+    }
+}
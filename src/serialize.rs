@@ -0,0 +1,142 @@
+use core::fmt;
+use std::marker::PhantomData;
+
+use serde::de::{Error, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{AvlError, AvlMap, AvlTree, Comparator};
+
+/// The tree is written as the sorted sequence of its values, the duplicates are next to the value holding their key
+/// The counted insertions of DuplicatePolicy::Count are not part of the sequence
+impl<T: Serialize, C> Serialize for AvlTree<T, C> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+/// The tree is rebuilt (and balanced) by inserting the values of the sequence in order
+/// with the default comparator and policy, any refused value makes the whole deserialization fail
+impl<'de, T: Deserialize<'de> + Eq, C: Comparator<T> + Default> Deserialize<'de> for AvlTree<T, C> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_seq(TreeVisitor {
+            marker: PhantomData,
+        })
+    }
+}
+
+struct TreeVisitor<T, C> {
+    marker: PhantomData<fn() -> AvlTree<T, C>>,
+}
+
+impl<'de, T: Deserialize<'de> + Eq, C: Comparator<T> + Default> Visitor<'de> for TreeVisitor<T, C> {
+    type Value = AvlTree<T, C>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a sequence of values")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut tree: AvlTree<T, C> = AvlTree::default();
+        while let Some(value) = seq.next_element()? {
+            tree.insert(value).map_err(|error: AvlError| A::Error::custom(error))?;
+        }
+        Ok(tree)
+    }
+}
+
+/// The map is written as a map of its entries in ascending order of the keys
+impl<K: Serialize, V: Serialize> Serialize for AvlMap<K, V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.iter())
+    }
+}
+
+/// The map is rebuilt (and balanced) by inserting the entries in order, a repeated key keeps its last value
+impl<'de, K: Deserialize<'de> + Ord, V: Deserialize<'de>> Deserialize<'de> for AvlMap<K, V> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(MapVisitor {
+            marker: PhantomData,
+        })
+    }
+}
+
+struct MapVisitor<K, V> {
+    marker: PhantomData<fn() -> AvlMap<K, V>>,
+}
+
+impl<'de, K: Deserialize<'de> + Ord, V: Deserialize<'de>> Visitor<'de> for MapVisitor<K, V> {
+    type Value = AvlMap<K, V>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<Self::Value, A::Error> {
+        let mut map: AvlMap<K, V> = AvlMap::new();
+        while let Some((key, value)) = access.next_entry()? {
+            map.insert(key, value);
+        }
+        Ok(map)
+    }
+}
+
+#[cfg(test)]
+mod test_serialize {
+    use super::*;
+    use crate::fixture::Position;
+    use crate::DuplicatePolicy;
+
+    #[test]
+    fn test_tree_round_trip() {
+        let mut tree: AvlTree<Position> = AvlTree::new();
+        for x in [3, 1, 2, 5, 4, 2, 2].iter() {
+            let y: i32 = tree.occurrences(&Position { x: *x, y: -1 }) as i32;
+            tree.insert(Position { x: *x, y }).expect("Failed insert");
+        }
+        let json: String = serde_json::to_string(&tree).expect("Failed serialize");
+        assert_eq!(json, r#"[{"x":1,"y":0},{"x":2,"y":0},{"x":2,"y":1},{"x":2,"y":2},{"x":3,"y":0},{"x":4,"y":0},{"x":5,"y":0}]"#);
+        let back: AvlTree<Position> = serde_json::from_str(&json).expect("Failed deserialize");
+        assert!(back.is_balanced());
+        assert!(back.is_correct());
+        assert_eq!(back.len(), 7);
+        assert!(back.iter().eq(tree.iter()));
+    }
+
+    #[test]
+    fn test_tree_rebalanced() {
+        let json: String = serde_json::to_string(&(0..100).collect::<Vec<u64>>()).expect("Failed serialize");
+        let tree: AvlTree<u64> = serde_json::from_str(&json).expect("Failed deserialize");
+        assert_eq!(tree.len(), 100);
+        assert!(tree.is_balanced());
+        assert!(tree.height() <= 8);
+        let tree: AvlTree<u64> = serde_json::from_str("[5, 3, 9, 1]").expect("Failed deserialize");
+        assert_eq!(tree.iter().cloned().collect::<Vec<u64>>(), vec![1, 3, 5, 9]);
+    }
+
+    #[test]
+    fn test_tree_errors() {
+        let res: Result<AvlTree<u64>, serde_json::Error> = serde_json::from_str("[1, 2, 1]");
+        assert!(res.unwrap_err().to_string().starts_with("Can not insert same value twice"));
+        let res: Result<AvlTree<u64>, serde_json::Error> = serde_json::from_str("{\"x\": 1}");
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_tree_count_policy() {
+        let mut tree: AvlTree<u64> = AvlTree::with_policy(DuplicatePolicy::Count);
+        tree.insert(1).expect("Failed insert")
+            .insert(1).expect("Failed insert")
+            .insert(2).expect("Failed insert");
+        assert_eq!(serde_json::to_string(&tree).expect("Failed serialize"), "[1,2]");
+    }
+
+    #[test]
+    fn test_map_round_trip() {
+        let map: AvlMap<String, Vec<f64>> = vec![("b".to_string(), vec![0.5]), ("a".to_string(), vec![]), ("c".to_string(), vec![1.0, 2.0])].into_iter().collect();
+        let json: String = serde_json::to_string(&map).expect("Failed serialize");
+        assert_eq!(json, r#"{"a":[],"b":[0.5],"c":[1.0,2.0]}"#);
+        let back: AvlMap<String, Vec<f64>> = serde_json::from_str(&json).expect("Failed deserialize");
+        assert_eq!(back, map);
+        assert!(back.is_balanced());
+    }
+}
@@ -0,0 +1,312 @@
+use std::cmp::Ordering;
+use std::iter::{once, FusedIterator};
+
+use crate::balance::Storage;
+use crate::storage::Backend;
+use crate::{AvlTree, Boxes, Comparator, Node, Side, Summary};
+
+/// The nodes of a tree in order, each one holding a key with all its values
+struct Nodes<'a, T, S, B: Storage> {
+    storage: &'a B,
+    stack: Vec<&'a Node<T, S, B>>,
+}
+
+impl<'a, T, S: Summary<T>, B: Backend<T, S>> Nodes<'a, T, S, B> {
+    fn new(storage: &'a B, root: &'a B::Link) -> Self {
+        let mut nodes = Nodes { storage, stack: Vec::new() };
+        nodes.push_spine(storage.root(root));
+        nodes
+    }
+
+    fn push_spine(&mut self, mut tree: Option<&'a Node<T, S, B>>) {
+        while let Some(node) = tree {
+            self.stack.push(node);
+            tree = node.child(self.storage, Side::Left);
+        }
+    }
+
+    fn peek(&self) -> Option<&'a Node<T, S, B>> {
+        self.stack.last().copied()
+    }
+
+    fn next(&mut self) -> Option<&'a Node<T, S, B>> {
+        let node: &'a Node<T, S, B> = self.stack.pop()?;
+        self.push_spine(node.child(self.storage, Side::Right));
+        Some(node)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Operation {
+    Union,
+    Intersection,
+    Difference,
+    SymmetricDifference,
+}
+
+/// Walk both trees side by side one key at a time and fill the bucket with the values the operation keeps for that key
+struct Merge<'a, T, C, S, B: Storage> {
+    left: Nodes<'a, T, S, B>,
+    right: Nodes<'a, T, S, B>,
+    cmp: &'a C,
+    operation: Operation,
+    /// Values of the current key still to yield, in reverse order
+    bucket: Vec<&'a T>,
+}
+
+impl<'a, T: PartialEq, C: Comparator<T>, S: Summary<T>, B: Backend<T, S>> Merge<'a, T, C, S, B> {
+    fn new(left: &'a AvlTree<T, C, S, B>, right: &'a AvlTree<T, C, S, B>, operation: Operation) -> Self {
+        Merge {
+            left: Nodes::new(&left.storage, &left.root),
+            right: Nodes::new(&right.storage, &right.root),
+            cmp: &left.cmp,
+            operation,
+            bucket: Vec::new(),
+        }
+    }
+
+    /// Load the values kept for the next key, return false once both trees are walked
+    fn advance(&mut self) -> bool {
+        let order: Ordering = match (self.left.peek(), self.right.peek()) {
+            (None, None) => return false,
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(left), Some(right)) => self.cmp.compare(&left.value, &right.value),
+        };
+        let (left, right) = match order {
+            Ordering::Less => (self.left.next(), None),
+            Ordering::Greater => (None, self.right.next()),
+            Ordering::Equal => (self.left.next(), self.right.next()),
+        };
+        let (keep_left, keep_right): (bool, bool) = match self.operation {
+            Operation::Union => (true, true),
+            Operation::Intersection => (false, false),
+            Operation::Difference => (true, false),
+            Operation::SymmetricDifference => (true, true),
+        };
+        match (left, right) {
+            (Some(left), None) if keep_left => self.bucket.extend(values(left)),
+            (None, Some(right)) if keep_right => self.bucket.extend(values(right)),
+            (Some(left), Some(right)) => match self.operation {
+                Operation::Union => {
+                    self.bucket.extend(values(left));
+                    self.bucket.extend(values(right).filter(|value: &&T| !left.holds(value)));
+                }
+                Operation::Intersection => self.bucket.extend(values(left).filter(|value: &&T| right.holds(value))),
+                Operation::Difference => self.bucket.extend(values(left).filter(|value: &&T| !right.holds(value))),
+                Operation::SymmetricDifference => {
+                    self.bucket.extend(values(left).filter(|value: &&T| !right.holds(value)));
+                    self.bucket.extend(values(right).filter(|value: &&T| !left.holds(value)));
+                }
+            },
+            _ => {}
+        }
+        self.bucket.reverse();
+        true
+    }
+
+    fn next(&mut self) -> Option<&'a T> {
+        loop {
+            if let Some(value) = self.bucket.pop() {
+                return Some(value);
+            }
+            if !self.advance() {
+                return None;
+            }
+        }
+    }
+}
+
+fn values<T, S, B: Storage>(node: &Node<T, S, B>) -> impl Iterator<Item = &T> {
+    once(&node.value).chain(node.duplicates.iter())
+}
+
+/// The values of two trees in ascending order without repeating the values (by Eq) found in both, see AvlTree::union
+pub struct Union<'a, T, C, S = (), B: Storage = Boxes<T, S>>(Merge<'a, T, C, S, B>);
+
+/// The values of a tree also found (by Eq) in another one in ascending order, see AvlTree::intersection
+pub struct Intersection<'a, T, C, S = (), B: Storage = Boxes<T, S>>(Merge<'a, T, C, S, B>);
+
+/// The values of a tree not found (by Eq) in another one in ascending order, see AvlTree::difference
+pub struct Difference<'a, T, C, S = (), B: Storage = Boxes<T, S>>(Merge<'a, T, C, S, B>);
+
+/// The values found (by Eq) in only one of two trees in ascending order, see AvlTree::symmetric_difference
+pub struct SymmetricDifference<'a, T, C, S = (), B: Storage = Boxes<T, S>>(Merge<'a, T, C, S, B>);
+
+impl<'a, T: PartialEq, C: Comparator<T>, S: Summary<T>, B: Backend<T, S>> Union<'a, T, C, S, B> {
+    pub(crate) fn new(left: &'a AvlTree<T, C, S, B>, right: &'a AvlTree<T, C, S, B>) -> Self {
+        Union(Merge::new(left, right, Operation::Union))
+    }
+}
+
+impl<'a, T: PartialEq, C: Comparator<T>, S: Summary<T>, B: Backend<T, S>> Intersection<'a, T, C, S, B> {
+    pub(crate) fn new(left: &'a AvlTree<T, C, S, B>, right: &'a AvlTree<T, C, S, B>) -> Self {
+        Intersection(Merge::new(left, right, Operation::Intersection))
+    }
+}
+
+impl<'a, T: PartialEq, C: Comparator<T>, S: Summary<T>, B: Backend<T, S>> Difference<'a, T, C, S, B> {
+    pub(crate) fn new(left: &'a AvlTree<T, C, S, B>, right: &'a AvlTree<T, C, S, B>) -> Self {
+        Difference(Merge::new(left, right, Operation::Difference))
+    }
+}
+
+impl<'a, T: PartialEq, C: Comparator<T>, S: Summary<T>, B: Backend<T, S>> SymmetricDifference<'a, T, C, S, B> {
+    pub(crate) fn new(left: &'a AvlTree<T, C, S, B>, right: &'a AvlTree<T, C, S, B>) -> Self {
+        SymmetricDifference(Merge::new(left, right, Operation::SymmetricDifference))
+    }
+}
+
+impl<'a, T: PartialEq, C: Comparator<T>, S: Summary<T>, B: Backend<T, S>> Iterator for Union<'a, T, C, S, B> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+}
+
+impl<'a, T: PartialEq, C: Comparator<T>, S: Summary<T>, B: Backend<T, S>> Iterator for Intersection<'a, T, C, S, B> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+}
+
+impl<'a, T: PartialEq, C: Comparator<T>, S: Summary<T>, B: Backend<T, S>> Iterator for Difference<'a, T, C, S, B> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+}
+
+impl<'a, T: PartialEq, C: Comparator<T>, S: Summary<T>, B: Backend<T, S>> Iterator for SymmetricDifference<'a, T, C, S, B> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+}
+
+impl<'a, T: PartialEq, C: Comparator<T>, S: Summary<T>, B: Backend<T, S>> FusedIterator for Union<'a, T, C, S, B> {}
+
+impl<'a, T: PartialEq, C: Comparator<T>, S: Summary<T>, B: Backend<T, S>> FusedIterator for Intersection<'a, T, C, S, B> {}
+
+impl<'a, T: PartialEq, C: Comparator<T>, S: Summary<T>, B: Backend<T, S>> FusedIterator for Difference<'a, T, C, S, B> {}
+
+impl<'a, T: PartialEq, C: Comparator<T>, S: Summary<T>, B: Backend<T, S>> FusedIterator for SymmetricDifference<'a, T, C, S, B> {}
+
+#[cfg(test)]
+mod test_set {
+    use crate::fixture::{points, positions, Position};
+    use crate::{AvlTree, DuplicatePolicy};
+
+    #[test]
+    fn test_lazy() {
+        let left: AvlTree<u64> = (0..20).filter(|value| value % 2 == 0).collect();
+        let right: AvlTree<u64> = (0..20).filter(|value| value % 3 == 0).collect();
+        assert_eq!(left.union(&right).cloned().collect::<Vec<u64>>(), vec![0, 2, 3, 4, 6, 8, 9, 10, 12, 14, 15, 16, 18]);
+        assert_eq!(left.intersection(&right).cloned().collect::<Vec<u64>>(), vec![0, 6, 12, 18]);
+        assert_eq!(left.difference(&right).cloned().collect::<Vec<u64>>(), vec![2, 4, 8, 10, 14, 16]);
+        assert_eq!(left.symmetric_difference(&right).cloned().collect::<Vec<u64>>(), vec![2, 3, 4, 8, 9, 10, 14, 15, 16]);
+        let empty: AvlTree<u64> = AvlTree::new();
+        assert!(left.union(&empty).eq(left.iter()));
+        assert!(empty.union(&left).eq(left.iter()));
+        assert_eq!(left.intersection(&empty).count(), 0);
+        assert!(left.difference(&empty).eq(left.iter()));
+        assert_eq!(empty.difference(&left).count(), 0);
+    }
+
+    #[test]
+    fn test_lazy_duplicates() {
+        let left: AvlTree<Position> = positions(&[(1, 0), (2, 0), (2, 1), (3, 0)]);
+        let right: AvlTree<Position> = positions(&[(2, 1), (2, 2), (3, 1), (4, 0)]);
+        assert_eq!(points(left.union(&right)), vec![(1, 0), (2, 0), (2, 1), (2, 2), (3, 0), (3, 1), (4, 0)]);
+        assert_eq!(points(left.intersection(&right)), vec![(2, 1)]);
+        assert_eq!(points(left.difference(&right)), vec![(1, 0), (2, 0), (3, 0)]);
+        assert_eq!(points(left.symmetric_difference(&right)), vec![(1, 0), (2, 0), (2, 2), (3, 0), (3, 1), (4, 0)]);
+    }
+
+    #[test]
+    fn test_owning() {
+        for (left_len, right_len, step) in [(0, 10, 1), (10, 0, 1), (1000, 10, 7), (10, 1000, 3), (500, 500, 2)].iter() {
+            let left: AvlTree<u64> = (0..*left_len).collect();
+            let right: AvlTree<u64> = (0..*right_len).map(|value| value * step).collect();
+            let checks: [(AvlTree<u64>, Vec<u64>); 4] = [
+                (left.clone().into_union(right.clone()), left.union(&right).cloned().collect()),
+                (left.clone().into_intersection(right.clone()), left.intersection(&right).cloned().collect()),
+                (left.clone().into_difference(right.clone()), left.difference(&right).cloned().collect()),
+                (left.clone().into_symmetric_difference(right.clone()), left.symmetric_difference(&right).cloned().collect()),
+            ];
+            for (tree, expected) in checks.iter() {
+                assert!(tree.is_correct());
+                assert!(tree.is_balanced());
+                assert_eq!(tree.len(), expected.len());
+                assert!(tree.iter().eq(expected.iter()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_owning_duplicates() {
+        let left: AvlTree<Position> = positions(&[(1, 0), (2, 0), (2, 1), (3, 0)]);
+        let right: AvlTree<Position> = positions(&[(2, 1), (2, 2), (3, 1), (4, 0)]);
+        let union: AvlTree<Position> = left.clone().into_union(right.clone());
+        assert!(union.iter().eq(left.union(&right)));
+        let intersection: AvlTree<Position> = left.clone().into_intersection(right.clone());
+        assert_eq!(points(intersection.iter()), vec![(2, 1)]);
+        assert!(intersection.is_correct());
+        let difference: AvlTree<Position> = left.clone().into_difference(right.clone());
+        assert_eq!(points(difference.iter()), vec![(1, 0), (2, 0), (3, 0)]);
+        let symmetric: AvlTree<Position> = left.into_symmetric_difference(right);
+        assert_eq!(points(symmetric.iter()), vec![(1, 0), (2, 0), (2, 2), (3, 0), (3, 1), (4, 0)]);
+        assert_eq!(symmetric.len(), 6);
+    }
+
+    #[test]
+    fn test_owning_policy() {
+        let mut left: AvlTree<Position> = AvlTree::with_policy(DuplicatePolicy::Reject);
+        left.extend(positions(&[(1, 0), (2, 0)]));
+        let right: AvlTree<Position> = positions(&[(2, 1), (3, 0)]);
+        // the union keeps the values of this tree when its policy refuses the other ones
+        let union: AvlTree<Position> = left.into_union(right);
+        assert_eq!(points(union.iter()), vec![(1, 0), (2, 0), (3, 0)]);
+        assert_eq!(union.policy(), DuplicatePolicy::Reject);
+        let mut left: AvlTree<u64> = AvlTree::with_policy(DuplicatePolicy::Count);
+        left.extend(vec![1, 1, 1, 2]);
+        let right: AvlTree<u64> = vec![1, 3].into_iter().collect();
+        let union: AvlTree<u64> = left.clone().into_union(right.clone());
+        assert_eq!(union.occurrences(&1), 3);
+        assert_eq!(union.len(), 3);
+        let intersection: AvlTree<u64> = left.into_intersection(right);
+        assert_eq!(intersection.occurrences(&1), 3);
+        assert_eq!(intersection.len(), 1);
+    }
+
+    #[test]
+    fn test_merge_with() {
+        let old: AvlTree<Position> = positions(&[(1, 0), (2, 0), (3, 0), (5, 0)]);
+        let new: AvlTree<Position> = positions(&[(2, 7), (3, 0), (4, 1), (5, 8), (5, 9)]);
+        let mut conflicts: usize = 0;
+        let merged: AvlTree<Position> = old.merge_with(new, |ours: Vec<Position>, theirs: Vec<Position>| {
+            conflicts += 1;
+            // keep the newest value unless it was deleted (y of 9)
+            match theirs.last() {
+                Some(last) if last.y == 9 => Vec::new(),
+                Some(last) => vec![last.clone()],
+                None => ours,
+            }
+        });
+        assert_eq!(conflicts, 3);
+        assert_eq!(points(merged.iter()), vec![(1, 0), (2, 7), (3, 0), (4, 1)]);
+        assert!(merged.is_correct());
+        assert!(merged.is_balanced());
+        let left: AvlTree<u64> = (0..1000).collect();
+        let right: AvlTree<u64> = (500..1500).collect();
+        let merged: AvlTree<u64> = left.merge_with(right, |ours: Vec<u64>, _| ours);
+        assert_eq!(merged.len(), 1500);
+        assert!(merged.is_correct());
+        assert!(merged.is_balanced());
+    }
+}
@@ -0,0 +1,390 @@
+use core::fmt;
+use std::fmt::Debug;
+use std::marker::PhantomData;
+use std::mem::{replace, swap};
+
+use crate::balance::{self, Storage, StorageMut};
+use crate::summary::{Eager, Propagate};
+use crate::{AvlTree, Side, Summary};
+
+/// A node of an AvlTree, its children are links of the storage B holding the nodes (see Backend)
+pub struct Node<T, S = (), B: Storage = Boxes<T, S>> {
+    pub(crate) children: [B::Link; 2],
+    pub(crate) value: T,
+    pub(crate) duplicates: Vec<T>,
+    /// Insertions of the key counted instead of stored (see DuplicatePolicy::Count), they are not part of the size
+    pub(crate) copies: usize,
+    pub(crate) height: usize,
+    pub(crate) size: usize,
+    /// The summary of all the values of the subtree (see Summary), kept up to date like the height and the size
+    pub(crate) summary: S,
+}
+
+impl<T, S, B: Storage> fmt::Pointer for Node<T, S, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ptr = self as *const Self;
+        fmt::Pointer::fmt(&ptr, f)
+    }
+}
+
+impl<T: Debug, S: Debug, P> fmt::Display for Node<T, S, Boxes<T, S, P>> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+// a boxed node holds its children, the traits can not be derived over the links of any storage
+impl<T: Debug, S: Debug, P> Debug for Node<T, S, Boxes<T, S, P>> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Node")
+            .field("children", &self.children)
+            .field("value", &self.value)
+            .field("duplicates", &self.duplicates)
+            .field("copies", &self.copies)
+            .field("height", &self.height)
+            .field("size", &self.size)
+            .field("summary", &self.summary)
+            .finish()
+    }
+}
+
+impl<T: Clone, S: Clone, P> Clone for Node<T, S, Boxes<T, S, P>> {
+    fn clone(&self) -> Self {
+        Node {
+            children: self.children.clone(),
+            value: self.value.clone(),
+            duplicates: self.duplicates.clone(),
+            copies: self.copies,
+            height: self.height,
+            size: self.size,
+            summary: self.summary.clone(),
+        }
+    }
+}
+
+impl<T: PartialEq, S: PartialEq, P> PartialEq for Node<T, S, Boxes<T, S, P>> {
+    fn eq(&self, other: &Self) -> bool {
+        self.children == other.children && self.value == other.value && self.duplicates == other.duplicates && self.copies == other.copies
+            && self.height == other.height && self.size == other.size && self.summary == other.summary
+    }
+}
+
+/// Where an AvlTree keeps its nodes: what the rotations need (see balance) and how the nodes come in and out of the storage
+/// The algorithms of the tree are written once over it, the storage only holds nodes and hands down their pending updates
+pub trait Backend<T, S>: StorageMut + Default {
+    /// The root of the subtree, None when it is empty
+    fn root<'a>(&'a self, link: &'a Self::Link) -> Option<&'a Node<T, S, Self>>;
+
+    /// The node reached by what StorageMut::node gives
+    fn get<'a>(&'a self, node: &'a Self::Node) -> &'a Node<T, S, Self>;
+
+    fn get_mut<'a>(&'a mut self, node: &'a mut Self::Node) -> &'a mut Node<T, S, Self>;
+
+    /// A link to an empty subtree
+    fn empty() -> Self::Link;
+
+    /// Keep the node in the storage (its children are already in it) and give the link to it
+    fn alloc(&mut self, node: Node<T, S, Self>) -> Self::Link;
+
+    /// Take the root of the subtree out of the storage with its pending updates handed down, the link is left empty
+    /// Its children are not freed, they are still linked to it
+    fn free(&mut self, link: &mut Self::Link) -> Option<Node<T, S, Self>>;
+
+    /// Hand the updates pending in the summary of the node down to its values and to its children (see Propagate),
+    /// this is done before the values are read or moved and before the children change
+    fn push_down(&mut self, node: &mut Self::Node);
+
+    /// The summary of a subtree out of the one of its values, keeping what the summary of its root still has to hand down
+    fn rebuild(summary: &S, part: S) -> S;
+
+    /// Walk down from the link, step gives the side to go to from each node (after handing down its pending updates if it needs to)
+    /// or None to stop there, the node stopped on is given and None if the walk leaves the tree
+    fn descend<'a, F: FnMut(&mut Self, &mut Self::Node) -> Option<Side>>(&'a mut self, link: &'a mut Self::Link, step: F) -> Option<&'a mut Node<T, S, Self>>;
+
+    /// Move the subtree held by the other storage in this one and give the link to it here
+    fn adopt(&mut self, other: &mut Self, link: Self::Link) -> Self::Link;
+
+    /// Drop every node of the tree of the root and leave it empty, the storage holds no other tree
+    fn clear(&mut self, root: &mut Self::Link);
+}
+
+/// Move the subtree of a storage in another one, a node at a time
+pub(crate) fn transfer<T, S, F: Backend<T, S>, B: Backend<T, S>>(from: &mut F, to: &mut B, mut link: F::Link) -> B::Link {
+    let node: Node<T, S, F> = match from.free(&mut link) {
+        None => return B::empty(),
+        Some(node) => node,
+    };
+    let [left, right] = node.children;
+    let children: [B::Link; 2] = [transfer(from, to, left), transfer(from, to, right)];
+    to.alloc(Node {
+        children,
+        value: node.value,
+        duplicates: node.duplicates,
+        copies: node.copies,
+        height: node.height,
+        size: node.size,
+        summary: node.summary,
+    })
+}
+
+/// The nodes from the root of a tree down to one of them (or to the empty place of a key), taken out of the tree
+/// so the last one is reached and changed in place without walking down again, see CursorMut and Entry
+/// Each node is held without the child on the side of the next one, the path puts them back on the way up
+/// (rebalancing them once something changed) and the tree is whole again when it is dropped
+pub(crate) struct Path<'a, T, C, S, B: Backend<T, S>> {
+    /// None once the whole tree is handed back (see into_tree)
+    tree: Option<&'a mut AvlTree<T, C, S, B>>,
+    links: Vec<B::Link>,
+    sides: Vec<Side>,
+    changed: bool,
+}
+
+impl<'a, T, C, S, B: Backend<T, S>> Path<'a, T, C, S, B> {
+    pub(crate) fn new(tree: &'a mut AvlTree<T, C, S, B>) -> Self {
+        Path {
+            tree: Some(tree),
+            links: Vec::new(),
+            sides: Vec::new(),
+            changed: false,
+        }
+    }
+
+    /// The tree the path was taken from, only its storage is whole while the path holds nodes
+    pub(crate) fn tree(&self) -> &AvlTree<T, C, S, B> {
+        self.tree.as_deref().unwrap()
+    }
+
+    /// The whole tree, the path must hold no node
+    pub(crate) fn tree_mut(&mut self) -> &mut AvlTree<T, C, S, B> {
+        debug_assert!(self.links.is_empty(), "The path still holds nodes of the tree");
+        self.tree.as_deref_mut().unwrap()
+    }
+
+    /// The sides followed from the root
+    pub(crate) fn sides(&self) -> &[Side] {
+        &self.sides
+    }
+
+    /// Number of links held, 0 when the tree is whole
+    pub(crate) fn depth(&self) -> usize {
+        self.links.len()
+    }
+
+    /// The node at the end of the path, None at an empty place or when the tree is whole
+    pub(crate) fn node(&self) -> Option<&Node<T, S, B>> {
+        self.tree().storage.root(self.links.last()?)
+    }
+
+    /// The node held at this depth of the path (0 for the root), without its child on the side of the path
+    pub(crate) fn node_at(&self, depth: usize) -> Option<&Node<T, S, B>> {
+        self.tree().storage.root(self.links.get(depth)?)
+    }
+
+    /// Same as node to change it, the path is rebalanced on the way back up
+    pub(crate) fn node_mut(&mut self) -> Option<&mut Node<T, S, B>> {
+        let storage: &mut B = &mut self.tree.as_deref_mut().unwrap().storage;
+        let link: &mut B::Link = self.links.last_mut()?;
+        if storage.height(link) == 0 {
+            return None;
+        }
+        self.changed = true;
+        Some(storage.get_mut(B::node(link)))
+    }
+
+    /// Hand down the pending updates of the node at the end of the path (see Propagate)
+    pub(crate) fn push_down(&mut self) {
+        let storage: &mut B = &mut self.tree.as_deref_mut().unwrap().storage;
+        if let Some(link) = self.links.last_mut() {
+            if storage.height(link) != 0 {
+                storage.push_down(B::node(link));
+            }
+        }
+    }
+
+    /// Change the subtree at the end of the path as Node::edit_at does, the path is rebalanced on the way back up
+    pub(crate) fn edit<R, F: FnOnce(&mut B, &mut B::Link) -> R>(&mut self, change: F) -> R {
+        self.changed = true;
+        change(&mut self.tree.as_deref_mut().unwrap().storage, self.links.last_mut().unwrap())
+    }
+
+    /// Take the root out of the tree, the tree must be whole
+    pub(crate) fn enter(&mut self) {
+        let root: B::Link = replace(&mut self.tree.as_deref_mut().unwrap().root, B::empty());
+        self.links.push(root);
+    }
+
+    /// Take the child of the node at the end of the path out of it (maybe an empty place)
+    pub(crate) fn down(&mut self, side: Side) {
+        let storage: &mut B = &mut self.tree.as_deref_mut().unwrap().storage;
+        let child: B::Link = storage.take_child(B::node(self.links.last_mut().unwrap()), side);
+        self.links.push(child);
+        self.sides.push(side);
+    }
+
+    /// Put the link at the end of the path back in its parent, false on the root
+    pub(crate) fn up(&mut self) -> bool {
+        let side: Side = match self.sides.pop() {
+            None => return false,
+            Some(side) => side,
+        };
+        let storage: &mut B = &mut self.tree.as_deref_mut().unwrap().storage;
+        let child: B::Link = self.links.pop().unwrap();
+        let parent: &mut B::Node = B::node(self.links.last_mut().unwrap());
+        storage.set_child(parent, side, child);
+        if self.changed {
+            balance::rebalance(storage, parent);
+        }
+        true
+    }
+
+    /// Put every node back, the tree is whole again
+    pub(crate) fn close(&mut self) {
+        if self.changed {
+            let storage: &mut B = &mut self.tree.as_deref_mut().unwrap().storage;
+            if let Some(link) = self.links.last_mut() {
+                if storage.height(link) != 0 {
+                    balance::rebalance(storage, B::node(link));
+                }
+            }
+        }
+        while self.up() {}
+        if let Some(root) = self.links.pop() {
+            self.tree.as_deref_mut().unwrap().root = root;
+        }
+        self.changed = false;
+    }
+
+    /// Put every node back and give the whole tree for as long as the path held it
+    pub(crate) fn into_tree(mut self) -> &'a mut AvlTree<T, C, S, B> {
+        self.close();
+        self.tree.take().unwrap()
+    }
+}
+
+impl<'a, T, C, S, B: Backend<T, S>> Drop for Path<'a, T, C, S, B> {
+    fn drop(&mut self) {
+        if self.tree.is_some() {
+            self.close();
+        }
+    }
+}
+
+/// The nodes of an AvlTree own their children in a Box, the pending updates are handed down as P says before a child is detached
+pub struct Boxes<T, S, P = Eager>(pub(crate) PhantomData<(T, S, P)>);
+
+impl<T, S, P> Default for Boxes<T, S, P> {
+    fn default() -> Self {
+        Boxes(PhantomData)
+    }
+}
+
+impl<T, S, P> Clone for Boxes<T, S, P> {
+    fn clone(&self) -> Self {
+        Boxes(PhantomData)
+    }
+}
+
+impl<T, S, P> Debug for Boxes<T, S, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Boxes")
+    }
+}
+
+/// A boxed subtree, it is None when empty
+type Tree<T, S, P> = Option<Box<Node<T, S, Boxes<T, S, P>>>>;
+
+impl<T, S, P> Storage for Boxes<T, S, P> {
+    type Node = Node<T, S, Self>;
+    type Link = Tree<T, S, P>;
+
+    fn height(&self, link: &Tree<T, S, P>) -> usize {
+        link.as_deref().map_or(0, |node: &Node<T, S, Self>| node.height)
+    }
+
+    fn child<'a>(&'a self, node: &'a Node<T, S, Self>, side: Side) -> &'a Tree<T, S, P> {
+        &node.children[side as usize]
+    }
+}
+
+impl<T, S: Summary<T>, P: Propagate<T, S>> StorageMut for Boxes<T, S, P> {
+    fn node(link: &mut Tree<T, S, P>) -> &mut Node<T, S, Self> {
+        link.as_deref_mut().unwrap()
+    }
+
+    fn take_child(&mut self, node: &mut Node<T, S, Self>, side: Side) -> Tree<T, S, P> {
+        self.push_down(node);
+        node.children[side as usize].take()
+    }
+
+    fn set_child(&mut self, node: &mut Node<T, S, Self>, side: Side, child: Tree<T, S, P>) {
+        node.children[side as usize] = child;
+    }
+
+    fn swap_values(&mut self, node: &mut Node<T, S, Self>, other: &mut Node<T, S, Self>) {
+        swap(&mut node.value, &mut other.value);
+        swap(&mut node.duplicates, &mut other.duplicates);
+        swap(&mut node.copies, &mut other.copies);
+    }
+
+    fn update(&mut self, node: &mut Node<T, S, Self>) {
+        node.update(self);
+    }
+}
+
+impl<T, S: Summary<T>, P: Propagate<T, S>> Backend<T, S> for Boxes<T, S, P> {
+    fn root<'a>(&'a self, link: &'a Tree<T, S, P>) -> Option<&'a Node<T, S, Self>> {
+        link.as_deref()
+    }
+
+    fn get<'a>(&'a self, node: &'a Node<T, S, Self>) -> &'a Node<T, S, Self> {
+        node
+    }
+
+    fn get_mut<'a>(&'a mut self, node: &'a mut Node<T, S, Self>) -> &'a mut Node<T, S, Self> {
+        node
+    }
+
+    fn empty() -> Tree<T, S, P> {
+        None
+    }
+
+    fn alloc(&mut self, node: Node<T, S, Self>) -> Tree<T, S, P> {
+        Some(Box::new(node))
+    }
+
+    fn free(&mut self, link: &mut Tree<T, S, P>) -> Option<Node<T, S, Self>> {
+        let mut node: Box<Node<T, S, Self>> = link.take()?;
+        self.push_down(&mut node);
+        Some(*node)
+    }
+
+    fn push_down(&mut self, node: &mut Node<T, S, Self>) {
+        let [left, right] = &mut node.children;
+        let children: [Option<&mut S>; 2] = [left.as_deref_mut().map(|node| &mut node.summary), right.as_deref_mut().map(|node| &mut node.summary)];
+        P::push_down(&mut node.summary, &mut node.value, &mut node.duplicates, children);
+    }
+
+    fn rebuild(summary: &S, part: S) -> S {
+        P::rebuild(summary, part)
+    }
+
+    fn descend<'a, F: FnMut(&mut Self, &mut Node<T, S, Self>) -> Option<Side>>(&'a mut self, link: &'a mut Tree<T, S, P>, mut step: F) -> Option<&'a mut Node<T, S, Self>> {
+        let mut link: &'a mut Tree<T, S, P> = link;
+        while let Some(node) = link.as_deref_mut() {
+            match step(self, node) {
+                None => return Some(node),
+                Some(side) => link = &mut node.children[side as usize],
+            }
+        }
+        None
+    }
+
+    /// A boxed subtree is not tied to a storage, it is moved as is
+    fn adopt(&mut self, _other: &mut Self, link: Tree<T, S, P>) -> Tree<T, S, P> {
+        link
+    }
+
+    fn clear(&mut self, root: &mut Tree<T, S, P>) {
+        Node::delete(self, root);
+    }
+}
@@ -0,0 +1,311 @@
+/// An aggregate of values kept in every node for its whole subtree, like the height and the size
+/// combine must be associative and identity neutral for it, the order of the values is kept
+/// Every value stored is summarized once, the counted insertions of DuplicatePolicy::Count are not (as len does not count them)
+pub trait Summary<T>: Clone {
+    /// The summary of no value
+    fn identity() -> Self;
+
+    /// The summary of a single value
+    fn from_value(value: &T) -> Self;
+
+    /// The summary of the values of self followed by the values of other
+    fn combine(&self, other: &Self) -> Self;
+}
+
+/// No summary, the default of the trees
+impl<T> Summary<T> for () {
+    fn identity() -> Self {}
+
+    fn from_value(_: &T) -> Self {}
+
+    fn combine(&self, _: &Self) -> Self {}
+}
+
+/// A change of every value of a range of keys at once (like adding a delta to a price), see LazyAvlTree::update_range
+/// The change of a whole subtree is stored once in its root and handed down later, so it must compose with the next one
+pub trait Update<T, S>: Clone {
+    /// Change a value, its key must stay the same
+    fn apply(&self, value: &mut T);
+
+    /// The summary of values once changed, out of their summary before the change
+    fn apply_summary(&self, summary: &S) -> S;
+
+    /// The update doing self then next
+    fn then(&self, next: &Self) -> Self;
+}
+
+/// The summary S of a subtree with the update U still to apply to the values of its root and to its children
+/// The summary is always up to date, the values get the update when they are handed it down (see LazyAvlTree)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lazy<S, U> {
+    summary: S,
+    pending: Option<U>,
+}
+
+impl<S, U> Lazy<S, U> {
+    /// Get the summary of the values, updates included
+    pub fn summary(&self) -> &S {
+        &self.summary
+    }
+
+    /// Take the summary of the values, updates included
+    pub fn into_summary(self) -> S {
+        self.summary
+    }
+}
+
+impl<S: Clone, U: Clone> Lazy<S, U> {
+    /// Apply the update to the whole subtree, the values and the children get it when it is handed down
+    pub(crate) fn tag<T>(&mut self, update: &U) where U: Update<T, S> {
+        self.summary = update.apply_summary(&self.summary);
+        self.pending = Some(match self.pending.take() {
+            Some(pending) => pending.then(update),
+            None => update.clone(),
+        });
+    }
+}
+
+impl<T, S: Summary<T>, U: Update<T, S>> Summary<T> for Lazy<S, U> {
+    fn identity() -> Self {
+        Lazy {
+            summary: S::identity(),
+            pending: None,
+        }
+    }
+
+    fn from_value(value: &T) -> Self {
+        Lazy {
+            summary: S::from_value(value),
+            pending: None,
+        }
+    }
+
+    fn combine(&self, other: &Self) -> Self {
+        Lazy {
+            summary: self.summary.combine(&other.summary),
+            pending: None,
+        }
+    }
+}
+
+/// How the storage of a tree hands down the updates pending in the summaries of its nodes, the trees pick it (see Boxes)
+/// Only Lazy has pending updates: Eager is for every other summary and Deferred for the Lazy ones of LazyAvlTree
+pub trait Propagate<T, S> {
+    /// Hand what is still to apply to the values of a node down to them and to the summaries of its children
+    fn push_down(summary: &mut S, value: &mut T, duplicates: &mut [T], children: [Option<&mut S>; 2]);
+
+    /// The summary of a subtree out of the summary of its values, with what the summary of its root still has to apply to them
+    fn rebuild(summary: &S, part: S) -> S;
+}
+
+/// The summaries have nothing pending, they are kept as computed
+pub struct Eager;
+
+/// The summaries are Lazy, their pending updates are handed down before the values are read or the children change
+pub struct Deferred;
+
+impl<T, S: Summary<T>> Propagate<T, S> for Eager {
+    fn push_down(_summary: &mut S, _value: &mut T, _duplicates: &mut [T], _children: [Option<&mut S>; 2]) {}
+
+    fn rebuild(_summary: &S, part: S) -> S {
+        part
+    }
+}
+
+impl<T, S: Summary<T>, U: Update<T, S>> Propagate<T, Lazy<S, U>> for Deferred {
+    fn push_down(summary: &mut Lazy<S, U>, value: &mut T, duplicates: &mut [T], mut children: [Option<&mut Lazy<S, U>>; 2]) {
+        let update: U = match summary.pending.take() {
+            None => return,
+            Some(update) => update,
+        };
+        update.apply(value);
+        duplicates.iter_mut().for_each(|duplicate: &mut T| update.apply(duplicate));
+        for child in children.iter_mut().flatten() {
+            child.tag(&update);
+        }
+    }
+
+    fn rebuild(summary: &Lazy<S, U>, part: Lazy<S, U>) -> Lazy<S, U> {
+        match &summary.pending {
+            None => part,
+            Some(update) => Lazy {
+                summary: update.apply_summary(&part.summary),
+                pending: summary.pending.clone(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_summary {
+    use crate::fixture::Position;
+    use crate::{AvlError, AvlTree, DuplicatePolicy, Entry, Natural, Summary};
+    use std::ops::{Bound, RangeBounds};
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    struct Sum(u64);
+
+    impl Summary<u64> for Sum {
+        fn identity() -> Self {
+            Sum(0)
+        }
+
+        fn from_value(value: &u64) -> Self {
+            Sum(*value)
+        }
+
+        fn combine(&self, other: &Self) -> Self {
+            Sum(self.0 + other.0)
+        }
+    }
+
+    /// The lowest and the greatest y with the number of positions, combine is not commutative for Ys
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    struct Ys {
+        count: usize,
+        min: Option<i32>,
+        max: Option<i32>,
+        sequence: Vec<i32>,
+    }
+
+    impl Summary<Position> for Ys {
+        fn identity() -> Self {
+            Ys {
+                count: 0,
+                min: None,
+                max: None,
+                sequence: Vec::new(),
+            }
+        }
+
+        fn from_value(value: &Position) -> Self {
+            Ys {
+                count: 1,
+                min: Some(value.y),
+                max: Some(value.y),
+                sequence: vec![value.y],
+            }
+        }
+
+        fn combine(&self, other: &Self) -> Self {
+            Ys {
+                count: self.count + other.count,
+                min: self.min.into_iter().chain(other.min).min(),
+                max: self.max.into_iter().chain(other.max).max(),
+                sequence: self.sequence.iter().chain(other.sequence.iter()).cloned().collect(),
+            }
+        }
+    }
+
+    fn check_sums<R: RangeBounds<u64> + Clone>(tree: &AvlTree<u64, Natural, Sum>, range: R) {
+        let expected: u64 = tree.range(range.clone()).sum();
+        assert_eq!(tree.aggregate(range), Sum(expected));
+    }
+
+    fn check_all_sums(tree: &AvlTree<u64, Natural, Sum>) {
+        assert_eq!(tree.summary(), Sum(tree.iter().sum()));
+        for start in (0..110).step_by(7) {
+            for end in (start..120).step_by(11) {
+                check_sums(tree, start..end);
+                check_sums(tree, start..=end);
+                check_sums(tree, (Bound::Excluded(start), Bound::Included(end)));
+            }
+            check_sums(tree, start..);
+            check_sums(tree, ..start);
+        }
+    }
+
+    #[test]
+    fn test_empty() {
+        let tree: AvlTree<u64, Natural, Sum> = AvlTree::with_summary();
+        assert_eq!(tree.summary(), Sum(0));
+        assert_eq!(tree.aggregate(..), Sum(0));
+        assert_eq!(tree.aggregate(3..7), Sum(0));
+        let tree: AvlTree<u64> = (0..10).collect();
+        assert_eq!(tree.summary(), ());
+    }
+
+    #[test]
+    fn test_insert_remove() {
+        let mut tree: AvlTree<u64, Natural, Sum> = AvlTree::with_summary();
+        for value in (0..100).map(|value: u64| (value * 37) % 101) {
+            tree.insert(value).expect("Failed insert");
+        }
+        assert!(tree.is_correct());
+        check_all_sums(&tree);
+        for value in (0..100).step_by(3) {
+            tree.remove(&value).ok();
+        }
+        assert!(tree.is_balanced());
+        check_all_sums(&tree);
+        for value in (0..50).filter(|value: &u64| value % 3 == 1) {
+            tree.take(&value);
+        }
+        check_all_sums(&tree);
+        tree.clear();
+        assert_eq!(tree.summary(), Sum(0));
+    }
+
+    #[test]
+    fn test_split_join() {
+        let mut tree: AvlTree<u64, Natural, Sum> = (0..100).collect();
+        check_all_sums(&tree);
+        let mut high: AvlTree<u64, Natural, Sum> = tree.split_off(&60);
+        assert_eq!(tree.summary(), Sum((0..60).sum()));
+        assert_eq!(high.summary(), Sum((60..100).sum()));
+        check_all_sums(&high);
+        let highest: AvlTree<u64, Natural, Sum> = high.split_off(&80);
+        assert_eq!(highest.aggregate(..90), Sum((80..90).sum()));
+        assert_eq!(AvlTree::join(high, 200, highest).err(), Some(AvlError::Overlap));
+        let mut tree: AvlTree<u64, Natural, Sum> = AvlTree::join(tree, 60, (61..100).collect()).expect("Failed join");
+        check_all_sums(&tree);
+        let mut other: AvlTree<u64, Natural, Sum> = (100..120).collect();
+        tree.append(&mut other);
+        assert_eq!(tree.summary(), Sum((0..120).sum()));
+        check_all_sums(&tree);
+        let union: AvlTree<u64, Natural, Sum> = tree.into_union((50..150).collect());
+        assert_eq!(union.summary(), Sum((0..150).sum()));
+        check_all_sums(&union);
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let mut tree: AvlTree<Position, Natural, Ys> = AvlTree::with_summary();
+        for (x, y) in [(5, 1), (3, 7), (5, 4), (8, 2), (5, 9), (1, 3), (3, 0)].iter() {
+            tree.insert(Position { x: *x, y: *y }).expect("Failed insert");
+        }
+        let all: Ys = tree.summary();
+        assert_eq!(all.count, 7);
+        assert_eq!(all.sequence, vec![3, 7, 0, 1, 4, 9, 2]);
+        let middle: Ys = tree.aggregate::<i32, _>(3..=5);
+        assert_eq!(middle.sequence, vec![7, 0, 1, 4, 9]);
+        assert_eq!((middle.min, middle.max), (Some(0), Some(9)));
+        assert_eq!(tree.aggregate::<i32, _>(4..5).count, 0);
+        assert_eq!(tree.aggregate::<i32, _>((Bound::Excluded(3), Bound::Unbounded)).sequence, vec![1, 4, 9, 2]);
+        assert_eq!(tree.take(&5), Some(Position { x: 5, y: 1 }));
+        assert_eq!(tree.aggregate::<i32, _>(5..).sequence, vec![4, 9, 2]);
+        tree.remove(&Position { x: 5, y: 9 }).expect("Failed remove");
+        assert_eq!(tree.summary().sequence, vec![3, 7, 0, 4, 2]);
+        let mut tree: AvlTree<Position, Natural, Ys> = AvlTree::with_comparator_policy_and_summary(Natural, DuplicatePolicy::Replace);
+        tree.insert(Position { x: 1, y: 1 }).expect("Failed insert");
+        tree.insert(Position { x: 2, y: 2 }).expect("Failed insert");
+        tree.insert(Position { x: 1, y: 5 }).expect("Failed insert");
+        assert_eq!(tree.summary().sequence, vec![5, 2]);
+    }
+
+    #[test]
+    fn test_entry() {
+        let mut tree: AvlTree<Position, Natural, Ys> = (0..50).map(|x: i32| Position { x, y: x }).collect();
+        for x in (0..60).step_by(5) {
+            tree.entry(Position { x, y: 100 }).and_modify(|position: &mut Position| position.y += 1000).or_insert();
+        }
+        if let Entry::Occupied(mut entry) = tree.entry(Position { x: 7, y: 0 }) {
+            entry.insert(Position { x: 7, y: 500 }).expect("Failed insert");
+        }
+        let expected: Vec<i32> = tree.iter().map(|position: &Position| position.y).collect();
+        assert_eq!(tree.summary().sequence, expected);
+        assert_eq!(tree.summary().max, Some(1045));
+        assert_eq!(tree.aggregate::<i32, _>(50..).sequence, vec![100, 100]);
+        assert_eq!(tree.aggregate::<i32, _>(6..=10).sequence, vec![6, 500, 8, 9, 1010]);
+    }
+}
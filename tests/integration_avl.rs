@@ -1,3 +1,5 @@
+// the original tests of the crate are written in this style, keep them as is
+#![allow(clippy::derived_hash_with_manual_eq, clippy::needless_borrow)]
 use std::cmp::Ordering;
 use core::fmt;
 use truetree::AvlTree;
@@ -40,12 +42,12 @@ fn test_avl() {
     let test_payload_4: Payload = Payload { age: 88, name: "4".to_string() };
     let test_payload_5: Payload = Payload { age: 99, name: "5".to_string() };
     let mut tree: AvlTree<Payload> = AvlTree::new();
-    assert!(tree.insert(&test_payload_1).is_ok());
+    assert!(tree.insert(test_payload_1.clone()).is_ok());
     assert!(tree.is_balanced());
-    tree.insert(&test_payload_2).expect("Insert failed")
-        .insert(&test_payload_3).expect("Insert failed")
-        .insert(&test_payload_4).expect("Insert failed")
-        .insert(&test_payload_5).expect("Insert failed");
+    tree.insert(test_payload_2.clone()).expect("Insert failed")
+        .insert(test_payload_3.clone()).expect("Insert failed")
+        .insert(test_payload_4.clone()).expect("Insert failed")
+        .insert(test_payload_5.clone()).expect("Insert failed");
     assert_eq!(tree.width(), 3);
     assert_eq!(tree.height(), 3);
     assert_eq!(tree.count(), 5);
@@ -105,4 +107,95 @@ fn test_avl() {
     assert!(tree.is_balanced());
     assert_eq!(tree.depth(), 0);
     tree.delete(); // you can not use the tree afterwards
-}
\ No newline at end of file
+}
+#[test]
+fn test_avl_range() {
+    let mut tree: AvlTree<Payload> = AvlTree::new();
+    for (age, name) in [(42, "1"), (420, "2"), (66, "3"), (88, "4"), (99, "5"), (66, "6")].iter() {
+        tree.insert(Payload { age: *age, name: name.to_string() }).expect("Insert failed");
+    }
+    // only the age is used to order the payloads so the bounds do not need a name
+    let from = Payload { age: 60, name: String::new() };
+    let to = Payload { age: 99, name: String::new() };
+    let names: Vec<&str> = tree.range(from.clone()..to.clone()).map(|payload| payload.name.as_str()).collect();
+    assert_eq!(names.len(), 3);
+    assert!(names.contains(&"3") && names.contains(&"6"));
+    assert_eq!(names[2], "4");
+    let ages: Vec<u32> = tree.range(from..=to).rev().map(|payload| payload.age).collect();
+    assert_eq!(ages, vec![99, 88, 66, 66]);
+}
+
+/// Only Ord (and what it requires) is implemented, no Clone, Hash, Debug or Display
+pub struct Measure {
+    id: u32,
+    samples: Vec<f64>,
+}
+
+impl PartialEq for Measure {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id && self.samples == other.samples
+    }
+}
+
+impl Eq for Measure {}
+
+impl Ord for Measure {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.id.cmp(&other.id)
+    }
+}
+
+impl PartialOrd for Measure {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[test]
+fn test_avl_ord_only() {
+    let mut tree: AvlTree<Measure> = AvlTree::new();
+    for id in [5, 3, 8, 1, 4, 7, 9].iter() {
+        tree.insert(Measure { id: *id, samples: vec![*id as f64] }).expect("Insert failed");
+    }
+    tree.insert(Measure { id: 5, samples: vec![0.5] }).expect("Insert failed");
+    assert!(tree.insert(Measure { id: 5, samples: vec![0.5] }).is_err());
+    assert_eq!(tree.len(), 8);
+    assert!(tree.is_balanced());
+    let probe = Measure { id: 5, samples: Vec::new() };
+    assert!(tree.contains(&probe));
+    assert!(!tree.contains_exact(&probe));
+    assert_eq!(tree.get_ref(&probe).expect("Missing value").samples, vec![5.0]);
+    assert_eq!(tree.rank(&probe), 3);
+    assert_eq!(tree.select(4).expect("Missing value").samples, vec![0.5]);
+    assert_eq!(tree.first().expect("No first").id, 1);
+    assert_eq!(tree.last().expect("No last").id, 9);
+    let ids: Vec<u32> = tree.iter().map(|measure| measure.id).collect();
+    assert_eq!(ids, vec![1, 3, 4, 5, 5, 7, 8, 9]);
+    assert_eq!(tree.range(&probe..).count(), 5);
+    tree.remove(&Measure { id: 5, samples: vec![5.0] }).expect("Remove failed");
+    assert_eq!(tree.get_ref(&probe).expect("Missing value").samples, vec![0.5]);
+    let samples: Vec<Vec<f64>> = tree.drain().map(|measure| measure.samples).collect();
+    assert_eq!(samples.len(), 7);
+    assert!(tree.is_empty());
+}
+
+#[test]
+fn test_avl_from_dump() {
+    // the Display of Payload is {"age":"name"}
+    let parse = |text: &str| -> Result<Payload, String> {
+        let fields: Vec<&str> = text.trim_matches(|c: char| c == '{' || c == '}').split(':').map(|field: &str| field.trim_matches('"')).collect();
+        match fields.as_slice() {
+            [age, name] => Ok(Payload { age: age.parse().map_err(|_| text.to_string())?, name: name.to_string() }),
+            _ => Err(text.to_string()),
+        }
+    };
+    let dump: &str = r#"[{"66":"3"},[{"42":"1"},null,null],[{"99":"5"},[{"88":"4"},null,null],[{"420":"2"},null,null]]]"#;
+    let mut tree: AvlTree<Payload> = AvlTree::from_dump(dump, parse).expect("Failed read");
+    assert_eq!(tree.dump(false).expect("Missing print"), dump);
+    assert_eq!(tree.len(), 5);
+    assert_eq!(tree.height(), 3);
+    tree.remove(&Payload { age: 42, name: "1".to_string() }).expect("Remove failed");
+    assert_eq!(tree.dump(false).expect("Missing print"), r#"[{"99":"5"},[{"66":"3"},null,[{"88":"4"},null,null]],[{"420":"2"},null,null]]"#);
+    let swapped: &str = r#"[{"66":"3"},[{"99":"5"},null,null],[{"42":"1"},null,null]]"#;
+    assert_eq!(AvlTree::from_dump(swapped, parse).unwrap_err(), truetree::AvlError::UnorderedDump(0));
+}
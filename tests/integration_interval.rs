@@ -0,0 +1,27 @@
+use truetree::IntervalTree;
+
+#[derive(Debug, PartialEq)]
+pub struct Region {
+    gene: String,
+}
+
+#[test]
+fn test_interval() {
+    // genomic regions are half-open, a region ending at 200 does not hold the base 200
+    let mut regions: IntervalTree<u64, Region> = IntervalTree::new();
+    for (start, end, gene) in [(100, 200, "a"), (150, 400, "b"), (200, 250, "c"), (390, 395, "d"), (1000, 1100, "e")].iter() {
+        assert!(regions.insert(*start..*end, Region { gene: gene.to_string() }).is_none());
+    }
+    assert_eq!(regions.len(), 5);
+    assert!(regions.is_balanced());
+    let genes: Vec<&str> = regions.containing(&200).map(|(_, region)| region.gene.as_str()).collect();
+    assert_eq!(genes, vec!["b", "c"]);
+    let genes: Vec<&str> = regions.overlapping(240..392).map(|(_, region)| region.gene.as_str()).collect();
+    assert_eq!(genes, vec!["b", "c", "d"]);
+    assert_eq!(regions.remove(&(150..400)).expect("Missing region").gene, "b");
+    assert!(regions.remove(&(150..400)).is_none());
+    let genes: Vec<&str> = regions.containing(&390).map(|(_, region)| region.gene.as_str()).collect();
+    assert_eq!(genes, vec!["d"]);
+    let starts: Vec<u64> = regions.iter().map(|(interval, _)| *interval.start).collect();
+    assert_eq!(starts, vec![100, 200, 390, 1000]);
+}
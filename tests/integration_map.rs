@@ -0,0 +1,29 @@
+use truetree::AvlMap;
+
+#[derive(Debug, PartialEq)]
+pub struct Payload {
+    name: String,
+    scores: Vec<f64>,
+}
+
+#[test]
+fn test_map() {
+    // the age is the key so the payload does not have to fake an Ord on one of its fields
+    let mut map: AvlMap<u32, Payload> = AvlMap::new();
+    for (age, name) in [(42, "1"), (420, "2"), (66, "3"), (88, "4"), (99, "5")].iter() {
+        assert!(map.insert(*age, Payload { name: name.to_string(), scores: Vec::new() }).is_none());
+    }
+    assert_eq!(map.len(), 5);
+    assert!(map.is_balanced());
+    assert_eq!(map.get(&420).expect("Missing key").name, "2");
+    map.get_mut(&66).expect("Missing key").scores.push(0.5);
+    assert_eq!(map.get(&66).expect("Missing key").scores, vec![0.5]);
+    let old = map.insert(66, Payload { name: "6".to_string(), scores: Vec::new() });
+    assert_eq!(old.expect("Missing old value").name, "3");
+    assert_eq!(map.remove(&42).expect("Missing key").name, "1");
+    assert!(map.remove(&42).is_none());
+    let ages: Vec<u32> = map.keys().cloned().collect();
+    assert_eq!(ages, vec![66, 88, 99, 420]);
+    let names: Vec<&str> = map.values().map(|payload| payload.name.as_str()).collect();
+    assert_eq!(names, vec!["6", "4", "5", "2"]);
+}